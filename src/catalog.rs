@@ -0,0 +1,1007 @@
+//! Resolves public identifiers, system identifiers and URIs to local
+//! resources using [OASIS XML
+//! Catalogs](https://www.oasis-open.org/committees/download.php/14809/xml-catalogs.html)
+//! version 1.1.
+//!
+//! Catalog files are read through an
+//! [`xinclude::Resolver`](../xinclude/trait.Resolver.html), usually
+//! the [`FileResolver`](../xinclude/struct.FileResolver.html).
+//! Catalogs referred to by `nextCatalog` and `delegate*` entries are
+//! loaded along with the catalog that refers to them; those that
+//! cannot be loaded are ignored, as the specification requires.
+//!
+//! ### Example
+//! ```
+//! use sxd_document::catalog::Catalog;
+//!
+//! let mut resolver = |_: &str| Ok(r#"<catalog xmlns="urn:oasis:names:tc:entity:xmlns:xml:catalog">
+//!   <rewriteSystem systemIdStartString="http://example.com/dtds/" rewritePrefix="dtds/"/>
+//! </catalog>"#.to_owned());
+//!
+//! let mut catalog = Catalog::new();
+//! catalog.load("file:///etc/xml/catalog", &mut resolver).expect("Unable to load catalog");
+//!
+//! assert_eq!(
+//!     catalog.resolve_system("http://example.com/dtds/doc.dtd"),
+//!     Some("file:///etc/xml/dtds/doc.dtd".to_owned())
+//! );
+//! ```
+
+use std::{cmp::Reverse, error, fmt, io};
+
+use crate::{
+    dom::{ChildOfElement, ChildOfRoot, Element},
+    parser,
+    str::XmlChar,
+    xinclude::Resolver,
+    QName,
+};
+
+pub const CATALOG_NS_URI: &str = "urn:oasis:names:tc:entity:xmlns:xml:catalog";
+
+const PUBLICID_URN_PREFIX: &str = "urn:publicid:";
+
+/// Whether public identifiers are used when a system identifier is
+/// also given
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Prefer {
+    Public,
+    System,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The catalog file could not be loaded
+    Resource { uri: String, error: io::Error },
+    /// The catalog file is not well-formed
+    Parse { uri: String, error: parser::Error },
+    /// The document element of the file is not a catalog
+    NotACatalog(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Error::*;
+
+        match *self {
+            Resource { ref uri, ref error } => write!(f, "Unable to load {}: {}", uri, error),
+            Parse { ref uri, ref error } => write!(f, "Unable to parse {}: {}", uri, error),
+            NotACatalog(ref uri) => write!(f, "{} is not a catalog", uri),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "Unable to load catalog"
+    }
+}
+
+/// An ordered list of catalog entry files
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    prefer: Prefer,
+    files: Vec<EntryFile>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Catalog::new()
+    }
+}
+
+impl Catalog {
+    pub fn new() -> Catalog {
+        Catalog {
+            prefer: Prefer::Public,
+            files: Vec::new(),
+        }
+    }
+
+    /// Sets the preference used by catalog files loaded afterwards
+    /// that do not specify one themselves
+    pub fn set_prefer(&mut self, prefer: Prefer) {
+        self.prefer = prefer;
+    }
+
+    /// Loads the catalog file at the URI, adding it to the end of the
+    /// list of catalog files
+    pub fn load<R>(&mut self, uri: &str, resolver: &mut R) -> Result<(), Error>
+    where
+        R: Resolver,
+    {
+        let mut loader = Loader {
+            resolver,
+            loading: Vec::new(),
+        };
+        let file = loader.load(uri, self.prefer)?;
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Finds the resource for an external identifier, such as the
+    /// one in a document type declaration
+    pub fn resolve_external_id(
+        &self,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> Option<String> {
+        let mut public_id = public_id.map(|id| match unwrap_urn(id) {
+            Some(unwrapped) => unwrapped,
+            None => normalize_public_id(id),
+        });
+
+        let mut system_id = system_id.map(normalize_uri);
+        if let Some(unwrapped) = system_id.as_ref().and_then(|id| unwrap_urn(id)) {
+            public_id = public_id.or(Some(unwrapped));
+            system_id = None;
+        }
+
+        let public_id = public_id.as_ref().map(|id| &id[..]);
+        let system_id = system_id.as_ref().map(|id| &id[..]);
+        first_match(&self.files, |f| f.lookup_external_id(public_id, system_id))
+    }
+
+    /// Finds the resource for a system identifier
+    pub fn resolve_system(&self, system_id: &str) -> Option<String> {
+        self.resolve_external_id(None, Some(system_id))
+    }
+
+    /// Finds the resource for a URI that is not an external
+    /// identifier, such as the `href` of an XInclude
+    pub fn resolve_uri(&self, uri: &str) -> Option<String> {
+        if let Some(public_id) = unwrap_urn(uri) {
+            return first_match(&self.files, |f| {
+                f.lookup_external_id(Some(&public_id), None)
+            });
+        }
+
+        let uri = normalize_uri(uri);
+        first_match(&self.files, |f| f.lookup_uri(&uri))
+    }
+}
+
+/// Loads resources through the catalog, falling back to the original
+/// URI when the catalog has no match. URIs are resolved using the
+/// `uri` entries, then as system identifiers.
+#[derive(Debug, Clone)]
+pub struct CatalogResolver<'c, R> {
+    catalog: &'c Catalog,
+    resolver: R,
+}
+
+impl<'c, R> CatalogResolver<'c, R> {
+    pub fn new(catalog: &'c Catalog, resolver: R) -> CatalogResolver<'c, R> {
+        CatalogResolver { catalog, resolver }
+    }
+}
+
+impl<'c, R> Resolver for CatalogResolver<'c, R>
+where
+    R: Resolver,
+{
+    fn load(&mut self, uri: &str) -> io::Result<String> {
+        let resolved = self
+            .catalog
+            .resolve_uri(uri)
+            .or_else(|| self.catalog.resolve_system(uri));
+
+        match resolved {
+            Some(resolved) => self.resolver.load(&resolved),
+            None => self.resolver.load(uri),
+        }
+    }
+}
+
+/// The outcome of searching one catalog entry file
+enum Lookup {
+    Found(String),
+    /// Matched a delegate entry, but none of the delegated catalogs
+    /// had a match. No other catalogs may be consulted.
+    Stop,
+    Continue,
+}
+
+/// Searches the catalog files in order, as a new catalog list
+fn first_match<'a, I, F>(files: I, mut lookup: F) -> Option<String>
+where
+    I: IntoIterator<Item = &'a EntryFile>,
+    F: FnMut(&EntryFile) -> Lookup,
+{
+    for file in files {
+        match lookup(file) {
+            Lookup::Found(uri) => return Some(uri),
+            Lookup::Stop => return None,
+            Lookup::Continue => {}
+        }
+    }
+    None
+}
+
+/// The delegated catalogs whose prefix matches, longest prefix first
+fn delegates<'a>(
+    delegates: impl Iterator<Item = (&'a str, &'a Option<EntryFile>)>,
+    id: &str,
+) -> Option<Vec<&'a EntryFile>> {
+    let mut matching: Vec<_> = delegates
+        .filter(|&(prefix, _)| id.starts_with(prefix))
+        .collect();
+
+    if matching.is_empty() {
+        return None;
+    }
+
+    matching.sort_by_key(|&(prefix, _)| Reverse(prefix.len()));
+    Some(
+        matching
+            .into_iter()
+            .filter_map(|(_, c)| c.as_ref())
+            .collect(),
+    )
+}
+
+fn longest_match<'a>(
+    candidates: impl Iterator<Item = (&'a str, &'a str)>,
+    matches: impl Fn(&str) -> bool,
+) -> Option<(&'a str, &'a str)> {
+    candidates
+        .filter(|&(key, _)| matches(key))
+        .fold(None, |longest, candidate| match longest {
+            Some((key, _)) if key.len() >= candidate.0.len() => longest,
+            _ => Some(candidate),
+        })
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Public {
+        public_id: String,
+        uri: String,
+        prefer: Prefer,
+    },
+    System {
+        system_id: String,
+        uri: String,
+    },
+    RewriteSystem {
+        prefix: String,
+        rewrite: String,
+    },
+    SystemSuffix {
+        suffix: String,
+        uri: String,
+    },
+    DelegatePublic {
+        prefix: String,
+        catalog: Option<EntryFile>,
+        prefer: Prefer,
+    },
+    DelegateSystem {
+        prefix: String,
+        catalog: Option<EntryFile>,
+    },
+    Uri {
+        name: String,
+        uri: String,
+    },
+    RewriteUri {
+        prefix: String,
+        rewrite: String,
+    },
+    UriSuffix {
+        suffix: String,
+        uri: String,
+    },
+    DelegateUri {
+        prefix: String,
+        catalog: Option<EntryFile>,
+    },
+    NextCatalog(Option<EntryFile>),
+}
+
+#[derive(Debug, Clone)]
+struct EntryFile {
+    entries: Vec<Entry>,
+}
+
+impl EntryFile {
+    fn next_catalogs(&self) -> impl Iterator<Item = &EntryFile> {
+        self.entries.iter().filter_map(|e| match *e {
+            Entry::NextCatalog(Some(ref c)) => Some(c),
+            _ => None,
+        })
+    }
+
+    fn lookup_external_id(&self, public_id: Option<&str>, system_id: Option<&str>) -> Lookup {
+        if let Some(system_id) = system_id {
+            match self.lookup_system_here(system_id) {
+                Lookup::Continue => {}
+                other => return other,
+            }
+        }
+
+        if let Some(public_id) = public_id {
+            let usable = |prefer: Prefer| prefer == Prefer::Public || system_id.is_none();
+
+            let mut public = self.entries.iter().filter_map(|e| match *e {
+                Entry::Public {
+                    public_id: ref id,
+                    ref uri,
+                    prefer,
+                } if id == public_id && usable(prefer) => Some(uri),
+                _ => None,
+            });
+            if let Some(uri) = public.next() {
+                return Lookup::Found(uri.clone());
+            }
+
+            let delegated = self.entries.iter().filter_map(|e| match *e {
+                Entry::DelegatePublic {
+                    ref prefix,
+                    ref catalog,
+                    prefer,
+                } if usable(prefer) => Some((&prefix[..], catalog)),
+                _ => None,
+            });
+            if let Some(catalogs) = delegates(delegated, public_id) {
+                let found = first_match(catalogs, |c| c.lookup_external_id(Some(public_id), None));
+                return found.map_or(Lookup::Stop, Lookup::Found);
+            }
+        }
+
+        for next in self.next_catalogs() {
+            match next.lookup_external_id(public_id, system_id) {
+                Lookup::Continue => {}
+                other => return other,
+            }
+        }
+
+        Lookup::Continue
+    }
+
+    /// Searches the system entries of this file, without following
+    /// `nextCatalog` entries
+    fn lookup_system_here(&self, system_id: &str) -> Lookup {
+        let mut system = self.entries.iter().filter_map(|e| match *e {
+            Entry::System {
+                system_id: ref id,
+                ref uri,
+            } if id == system_id => Some(uri),
+            _ => None,
+        });
+        if let Some(uri) = system.next() {
+            return Lookup::Found(uri.clone());
+        }
+
+        let rewrites = self.entries.iter().filter_map(|e| match *e {
+            Entry::RewriteSystem {
+                ref prefix,
+                ref rewrite,
+            } => Some((&prefix[..], &rewrite[..])),
+            _ => None,
+        });
+        if let Some((prefix, rewrite)) = longest_match(rewrites, |p| system_id.starts_with(p)) {
+            return Lookup::Found(format!("{}{}", rewrite, &system_id[prefix.len()..]));
+        }
+
+        let suffixes = self.entries.iter().filter_map(|e| match *e {
+            Entry::SystemSuffix {
+                ref suffix,
+                ref uri,
+            } => Some((&suffix[..], &uri[..])),
+            _ => None,
+        });
+        if let Some((_, uri)) = longest_match(suffixes, |s| system_id.ends_with(s)) {
+            return Lookup::Found(uri.to_owned());
+        }
+
+        let delegated = self.entries.iter().filter_map(|e| match *e {
+            Entry::DelegateSystem {
+                ref prefix,
+                ref catalog,
+            } => Some((&prefix[..], catalog)),
+            _ => None,
+        });
+        if let Some(catalogs) = delegates(delegated, system_id) {
+            let found = first_match(catalogs, |c| c.lookup_external_id(None, Some(system_id)));
+            return found.map_or(Lookup::Stop, Lookup::Found);
+        }
+
+        Lookup::Continue
+    }
+
+    fn lookup_uri(&self, uri: &str) -> Lookup {
+        let mut exact = self.entries.iter().filter_map(|e| match *e {
+            Entry::Uri {
+                ref name,
+                uri: ref target,
+            } if name == uri => Some(target),
+            _ => None,
+        });
+        if let Some(target) = exact.next() {
+            return Lookup::Found(target.clone());
+        }
+
+        let rewrites = self.entries.iter().filter_map(|e| match *e {
+            Entry::RewriteUri {
+                ref prefix,
+                ref rewrite,
+            } => Some((&prefix[..], &rewrite[..])),
+            _ => None,
+        });
+        if let Some((prefix, rewrite)) = longest_match(rewrites, |p| uri.starts_with(p)) {
+            return Lookup::Found(format!("{}{}", rewrite, &uri[prefix.len()..]));
+        }
+
+        let suffixes = self.entries.iter().filter_map(|e| match *e {
+            Entry::UriSuffix {
+                ref suffix,
+                uri: ref target,
+            } => Some((&suffix[..], &target[..])),
+            _ => None,
+        });
+        if let Some((_, target)) = longest_match(suffixes, |s| uri.ends_with(s)) {
+            return Lookup::Found(target.to_owned());
+        }
+
+        let delegated = self.entries.iter().filter_map(|e| match *e {
+            Entry::DelegateUri {
+                ref prefix,
+                ref catalog,
+            } => Some((&prefix[..], catalog)),
+            _ => None,
+        });
+        if let Some(catalogs) = delegates(delegated, uri) {
+            let found = first_match(catalogs, |c| c.lookup_uri(uri));
+            return found.map_or(Lookup::Stop, Lookup::Found);
+        }
+
+        for next in self.next_catalogs() {
+            match next.lookup_uri(uri) {
+                Lookup::Continue => {}
+                other => return other,
+            }
+        }
+
+        Lookup::Continue
+    }
+}
+
+struct Loader<'r, R> {
+    resolver: &'r mut R,
+    // The catalogs currently being loaded, to avoid loops
+    loading: Vec<String>,
+}
+
+impl<'r, R> Loader<'r, R>
+where
+    R: Resolver,
+{
+    fn load(&mut self, uri: &str, prefer: Prefer) -> Result<EntryFile, Error> {
+        let text = self.resolver.load(uri).map_err(|error| Error::Resource {
+            uri: uri.into(),
+            error,
+        })?;
+        let package = parser::parse(&text).map_err(|error| Error::Parse {
+            uri: uri.into(),
+            error,
+        })?;
+        let doc = package.as_document();
+        doc.set_document_uri(Some(uri));
+
+        let catalog = doc
+            .root()
+            .children()
+            .into_iter()
+            .filter_map(ChildOfRoot::element)
+            .find(|&e| e.name() == catalog_name("catalog"))
+            .ok_or_else(|| Error::NotACatalog(uri.into()))?;
+
+        self.loading.push(uri.into());
+        let mut entries = Vec::new();
+        self.read_entries(catalog, prefer, &mut entries);
+        self.loading.pop();
+
+        Ok(EntryFile { entries })
+    }
+
+    /// Loads a catalog referred to by another one, ignoring any
+    /// failures
+    fn load_referenced(&mut self, uri: &str, prefer: Prefer) -> Option<EntryFile> {
+        if self.loading.iter().any(|u| u == uri) {
+            return None;
+        }
+        self.load(uri, prefer).ok()
+    }
+
+    /// Reads the entries of a `catalog` or `group` element. Entries
+    /// that are missing a required attribute are ignored.
+    fn read_entries(&mut self, parent: Element<'_>, prefer: Prefer, entries: &mut Vec<Entry>) {
+        let prefer = match parent.attribute_value("prefer") {
+            Some("public") => Prefer::Public,
+            Some("system") => Prefer::System,
+            _ => prefer,
+        };
+
+        for element in parent
+            .children()
+            .into_iter()
+            .filter_map(ChildOfElement::element)
+        {
+            let name = element.name();
+            if name.namespace_uri() != Some(CATALOG_NS_URI) {
+                continue;
+            }
+
+            let attribute = |name| element.attribute_value(name);
+            let uri = |name| attribute(name).map(|v| element.resolve_uri(v));
+
+            let entry = match name.local_part() {
+                "group" => {
+                    self.read_entries(element, prefer, entries);
+                    None
+                }
+                "public" => attribute("publicId").and_then(|id| {
+                    uri("uri").map(|uri| Entry::Public {
+                        public_id: normalize_public_id(id),
+                        uri,
+                        prefer,
+                    })
+                }),
+                "system" => attribute("systemId").and_then(|id| {
+                    uri("uri").map(|uri| Entry::System {
+                        system_id: normalize_uri(id),
+                        uri,
+                    })
+                }),
+                "rewriteSystem" => attribute("systemIdStartString").and_then(|prefix| {
+                    uri("rewritePrefix").map(|rewrite| Entry::RewriteSystem {
+                        prefix: normalize_uri(prefix),
+                        rewrite,
+                    })
+                }),
+                "systemSuffix" => attribute("systemIdSuffix").and_then(|suffix| {
+                    uri("uri").map(|uri| Entry::SystemSuffix {
+                        suffix: normalize_uri(suffix),
+                        uri,
+                    })
+                }),
+                "uri" => attribute("name").and_then(|name| {
+                    uri("uri").map(|uri| Entry::Uri {
+                        name: normalize_uri(name),
+                        uri,
+                    })
+                }),
+                "rewriteURI" => attribute("uriStartString").and_then(|prefix| {
+                    uri("rewritePrefix").map(|rewrite| Entry::RewriteUri {
+                        prefix: normalize_uri(prefix),
+                        rewrite,
+                    })
+                }),
+                "uriSuffix" => attribute("uriSuffix").and_then(|suffix| {
+                    uri("uri").map(|uri| Entry::UriSuffix {
+                        suffix: normalize_uri(suffix),
+                        uri,
+                    })
+                }),
+                "delegatePublic" => match (attribute("publicIdStartString"), uri("catalog")) {
+                    (Some(prefix), Some(catalog)) => Some(Entry::DelegatePublic {
+                        prefix: normalize_public_id(prefix),
+                        catalog: self.load_referenced(&catalog, prefer),
+                        prefer,
+                    }),
+                    _ => None,
+                },
+                "delegateSystem" => match (attribute("systemIdStartString"), uri("catalog")) {
+                    (Some(prefix), Some(catalog)) => Some(Entry::DelegateSystem {
+                        prefix: normalize_uri(prefix),
+                        catalog: self.load_referenced(&catalog, prefer),
+                    }),
+                    _ => None,
+                },
+                "delegateURI" => match (attribute("uriStartString"), uri("catalog")) {
+                    (Some(prefix), Some(catalog)) => Some(Entry::DelegateUri {
+                        prefix: normalize_uri(prefix),
+                        catalog: self.load_referenced(&catalog, prefer),
+                    }),
+                    _ => None,
+                },
+                "nextCatalog" => {
+                    uri("catalog").map(|c| Entry::NextCatalog(self.load_referenced(&c, prefer)))
+                }
+                _ => None,
+            };
+
+            entries.extend(entry);
+        }
+    }
+}
+
+fn catalog_name(local_part: &str) -> QName<'_> {
+    QName::with_namespace_uri(Some(CATALOG_NS_URI), local_part)
+}
+
+/// Collapses runs of whitespace into a single space, as described in
+/// [section 6.2](https://www.oasis-open.org/committees/download.php/14809/xml-catalogs.html#s.public.norm)
+fn normalize_public_id(public_id: &str) -> String {
+    public_id
+        .split(|c: char| c.is_space_char())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Percent-encodes the characters that are not allowed in a URI, as
+/// described in [section 6.3](https://www.oasis-open.org/committees/download.php/14809/xml-catalogs.html#s.sysid.norm)
+fn normalize_uri(uri: &str) -> String {
+    let mut normalized = String::with_capacity(uri.len());
+
+    for c in uri.chars() {
+        let allowed = c.is_ascii_graphic()
+            && !(c == '"' || c == '<' || c == '>' || c == '\\')
+            && !(c == '^' || c == '`' || c == '{' || c == '|' || c == '}');
+
+        if allowed {
+            normalized.push(c);
+        } else {
+            let mut buffer = [0; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                normalized.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+
+    normalized
+}
+
+/// Converts a `urn:publicid:` URN into the public identifier it
+/// encodes, as described in [section 6.4](https://www.oasis-open.org/committees/download.php/14809/xml-catalogs.html#s.urnunwrap)
+fn unwrap_urn(urn: &str) -> Option<String> {
+    let prefix = urn.get(..PUBLICID_URN_PREFIX.len())?;
+    if !prefix.eq_ignore_ascii_case(PUBLICID_URN_PREFIX) {
+        return None;
+    }
+
+    let mut unwrapped = String::new();
+    let mut rest = &urn[PUBLICID_URN_PREFIX.len()..];
+
+    while let Some(c) = rest.chars().next() {
+        let (replacement, len) = match c {
+            '+' => (" ", 1),
+            ':' => ("//", 1),
+            ';' => ("::", 1),
+            '%' => match rest
+                .get(..3)
+                .map(str::to_ascii_uppercase)
+                .as_ref()
+                .map(|s| &s[..])
+            {
+                Some("%2B") => ("+", 3),
+                Some("%3A") => (":", 3),
+                Some("%2F") => ("/", 3),
+                Some("%3B") => (";", 3),
+                Some("%27") => ("'", 3),
+                Some("%3F") => ("?", 3),
+                Some("%23") => ("#", 3),
+                Some("%25") => ("%", 3),
+                _ => ("%", 1),
+            },
+            _ => {
+                unwrapped.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+
+        unwrapped.push_str(replacement);
+        rest = &rest[len..];
+    }
+
+    Some(unwrapped)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, io};
+
+    use super::{
+        normalize_public_id, normalize_uri, unwrap_urn, Catalog, CatalogResolver, Error, Prefer,
+    };
+    use crate::xinclude::Resolver;
+
+    struct Files(HashMap<&'static str, String>);
+
+    impl Resolver for Files {
+        fn load(&mut self, uri: &str) -> io::Result<String> {
+            self.0
+                .get(uri)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, uri))
+        }
+    }
+
+    fn catalog_xml(entries: &str) -> String {
+        format!(
+            r#"<catalog xmlns="urn:oasis:names:tc:entity:xmlns:xml:catalog">{}</catalog>"#,
+            entries
+        )
+    }
+
+    fn load(files: &[(&'static str, &str)]) -> Catalog {
+        let mut resolver = Files(
+            files
+                .iter()
+                .map(|&(uri, entries)| (uri, catalog_xml(entries)))
+                .collect(),
+        );
+
+        let mut catalog = Catalog::new();
+        catalog
+            .load(files[0].0, &mut resolver)
+            .expect("Unable to load catalog");
+        catalog
+    }
+
+    const DOC_PUBLIC_ID: &str = "-//Example//DTD Doc//EN";
+
+    #[test]
+    fn resolves_public_and_system_ids() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<public publicId="-//Example//DTD  Doc//EN" uri="doc.dtd"/>
+               <system systemId="http://example.com/doc.dtd" uri="/dtds/doc.dtd"/>"#,
+        )]);
+
+        assert_eq!(
+            catalog.resolve_external_id(Some(DOC_PUBLIC_ID), None),
+            Some("file:///c/doc.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_system("http://example.com/doc.dtd"),
+            Some("file:///dtds/doc.dtd".to_owned())
+        );
+        assert_eq!(catalog.resolve_system("http://example.com/other.dtd"), None);
+    }
+
+    #[test]
+    fn system_entries_take_priority_over_public_entries() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<public publicId="-//Example//DTD Doc//EN" uri="public.dtd"/>
+               <system systemId="http://example.com/doc.dtd" uri="system.dtd"/>"#,
+        )]);
+
+        assert_eq!(
+            catalog.resolve_external_id(Some(DOC_PUBLIC_ID), Some("http://example.com/doc.dtd")),
+            Some("file:///c/system.dtd".to_owned())
+        );
+    }
+
+    #[test]
+    fn prefer_system_ignores_public_entries_when_a_system_id_is_given() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<group prefer="system"><public publicId="-//Example//DTD Doc//EN" uri="doc.dtd"/></group>"#,
+        )]);
+
+        assert_eq!(
+            catalog.resolve_external_id(Some(DOC_PUBLIC_ID), Some("http://example.com/doc.dtd")),
+            None
+        );
+        assert_eq!(
+            catalog.resolve_external_id(Some(DOC_PUBLIC_ID), None),
+            Some("file:///c/doc.dtd".to_owned())
+        );
+    }
+
+    #[test]
+    fn rewrites_using_the_longest_prefix_and_suffix() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<rewriteSystem systemIdStartString="http://example.com/" rewritePrefix="all/"/>
+               <rewriteSystem systemIdStartString="http://example.com/dtds/" rewritePrefix="dtds/"/>
+               <systemSuffix systemIdSuffix=".ent" uri="entities.ent"/>
+               <rewriteURI uriStartString="http://example.com/docs/" rewritePrefix="/srv/docs/"/>
+               <uriSuffix uriSuffix="/chapter.xml" uri="chapter.xml"/>"#,
+        )]);
+
+        assert_eq!(
+            catalog.resolve_system("http://example.com/dtds/a/doc.dtd"),
+            Some("file:///c/dtds/a/doc.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_system("http://example.com/index.dtd"),
+            Some("file:///c/all/index.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_system("http://other.com/x.ent"),
+            Some("file:///c/entities.ent".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_uri("http://example.com/docs/a.xml"),
+            Some("file:///srv/docs/a.xml".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_uri("http://other.com/chapter.xml"),
+            Some("file:///c/chapter.xml".to_owned())
+        );
+    }
+
+    #[test]
+    fn uses_xml_base_for_relative_uris() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<group xml:base="http://mirror.example.com/"><uri name="urn:a" uri="a.xml"/></group>
+               <uri name="urn:b" uri="b.xml"/>"#,
+        )]);
+
+        assert_eq!(
+            catalog.resolve_uri("urn:a"),
+            Some("http://mirror.example.com/a.xml".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_uri("urn:b"),
+            Some("file:///c/b.xml".to_owned())
+        );
+    }
+
+    #[test]
+    fn follows_next_catalogs_in_order() {
+        let catalog = load(&[
+            (
+                "file:///c/catalog.xml",
+                r#"<nextCatalog catalog="missing.xml"/>
+                   <nextCatalog catalog="a/next.xml"/>
+                   <nextCatalog catalog="catalog.xml"/>"#,
+            ),
+            (
+                "file:///c/a/next.xml",
+                r#"<system systemId="http://example.com/doc.dtd" uri="doc.dtd"/>"#,
+            ),
+        ]);
+
+        assert_eq!(
+            catalog.resolve_system("http://example.com/doc.dtd"),
+            Some("file:///c/a/doc.dtd".to_owned())
+        );
+    }
+
+    #[test]
+    fn delegation_only_consults_the_delegated_catalogs() {
+        let catalog = load(&[
+            (
+                "file:///c/catalog.xml",
+                r#"<delegateSystem systemIdStartString="http://example.com/" catalog="short.xml"/>
+                   <delegateSystem systemIdStartString="http://example.com/dtds/" catalog="long.xml"/>
+                   <nextCatalog catalog="next.xml"/>"#,
+            ),
+            (
+                "file:///c/short.xml",
+                r#"<system systemId="http://example.com/dtds/a.dtd" uri="short-a.dtd"/>
+                   <system systemId="http://example.com/dtds/b.dtd" uri="short-b.dtd"/>"#,
+            ),
+            (
+                "file:///c/long.xml",
+                r#"<system systemId="http://example.com/dtds/a.dtd" uri="long-a.dtd"/>"#,
+            ),
+            (
+                "file:///c/next.xml",
+                r#"<system systemId="http://example.com/dtds/c.dtd" uri="next-c.dtd"/>"#,
+            ),
+        ]);
+
+        assert_eq!(
+            catalog.resolve_system("http://example.com/dtds/a.dtd"),
+            Some("file:///c/long-a.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_system("http://example.com/dtds/b.dtd"),
+            Some("file:///c/short-b.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_system("http://example.com/dtds/c.dtd"),
+            None
+        );
+    }
+
+    #[test]
+    fn resolves_publicid_urns_as_public_ids() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<public publicId="-//Example//DTD Doc//EN" uri="doc.dtd"/>"#,
+        )]);
+
+        let urn = "urn:publicid:-:Example:DTD+Doc:EN";
+        assert_eq!(
+            catalog.resolve_system(urn),
+            Some("file:///c/doc.dtd".to_owned())
+        );
+        assert_eq!(
+            catalog.resolve_uri(urn),
+            Some("file:///c/doc.dtd".to_owned())
+        );
+    }
+
+    #[test]
+    fn unwraps_urns() {
+        assert_eq!(
+            unwrap_urn("urn:publicid:ISO%2FIEC+10179%3A1996:DTD+DSSSL+Architecture:EN"),
+            Some("ISO/IEC 10179:1996//DTD DSSSL Architecture//EN".to_owned())
+        );
+        assert_eq!(unwrap_urn("urn:isbn:0451450523"), None);
+    }
+
+    #[test]
+    fn normalizes_only_xml_whitespace_in_public_ids() {
+        assert_eq!(
+            normalize_public_id(" -//A//B \r\n\t C//EN "),
+            "-//A//B C//EN"
+        );
+        assert_eq!(normalize_public_id("a\u{a0}b"), "a\u{a0}b");
+    }
+
+    #[test]
+    fn normalizes_uris() {
+        assert_eq!(
+            normalize_uri("http://example.com/a b/\u{e9}.dtd"),
+            "http://example.com/a%20b/%C3%A9.dtd"
+        );
+    }
+
+    #[test]
+    fn catalog_files_loaded_later_use_the_new_preference() {
+        let mut resolver = Files(
+            vec![(
+                "file:///c/catalog.xml",
+                catalog_xml(r#"<public publicId="-//Example//DTD Doc//EN" uri="doc.dtd"/>"#),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let mut catalog = Catalog::new();
+        catalog.set_prefer(Prefer::System);
+        catalog
+            .load("file:///c/catalog.xml", &mut resolver)
+            .unwrap();
+
+        assert_eq!(
+            catalog.resolve_external_id(Some(DOC_PUBLIC_ID), Some("http://example.com/doc.dtd")),
+            None
+        );
+    }
+
+    #[test]
+    fn loading_a_document_that_is_not_a_catalog_fails() {
+        let mut resolver = |_: &str| Ok("<html/>".to_owned());
+
+        match Catalog::new().load("file:///c/catalog.xml", &mut resolver) {
+            Err(Error::NotACatalog(uri)) => assert_eq!(uri, "file:///c/catalog.xml"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolver_loads_through_the_catalog() {
+        let catalog = load(&[(
+            "file:///c/catalog.xml",
+            r#"<rewriteURI uriStartString="http://example.com/" rewritePrefix="/local/"/>"#,
+        )]);
+
+        let mut loaded = Vec::new();
+        {
+            let inner = |uri: &str| {
+                loaded.push(uri.to_owned());
+                Ok(String::new())
+            };
+            let mut resolver = CatalogResolver::new(&catalog, inner);
+            resolver.load("http://example.com/ch1.xml").unwrap();
+            resolver.load("http://other.com/ch2.xml").unwrap();
+        }
+
+        assert_eq!(
+            loaded,
+            ["file:///local/ch1.xml", "http://other.com/ch2.xml"]
+        );
+    }
+}
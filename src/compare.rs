@@ -0,0 +1,634 @@
+//! Structural comparison of documents and nodes.
+//!
+//! Nodes from the DOM compare by identity. `DeepEq` instead compares
+//! what the nodes contain, optionally ignoring details that do not
+//! matter to the application.
+//!
+//! ### Example
+//!
+//! ```
+//! use sxd_document::{compare::DeepEq, parser};
+//!
+//! let expected = parser::parse("<a:x xmlns:a='urn:x' one='1' two='2'/>").unwrap();
+//! let actual = parser::parse("<b:x xmlns:b='urn:x' two='2' one='1'/>").unwrap();
+//!
+//! let comparison = DeepEq::new().compare(
+//!     expected.as_document().root(),
+//!     actual.as_document().root(),
+//! );
+//! assert!(comparison.is_ok());
+//! ```
+
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    dom::{Element, Node, NodeType},
+    str::XmlChar,
+    QName,
+};
+
+/// Compares nodes by their content. By default, prefixes and the
+/// order of attributes are ignored, while comments, processing
+/// instructions and whitespace are significant.
+#[derive(Debug, Clone)]
+pub struct DeepEq {
+    compare_prefixes: bool,
+    ignore_attribute_order: bool,
+    ignore_comments: bool,
+    ignore_processing_instructions: bool,
+    normalize_whitespace: bool,
+}
+
+impl Default for DeepEq {
+    fn default() -> Self {
+        DeepEq {
+            compare_prefixes: false,
+            ignore_attribute_order: true,
+            ignore_comments: false,
+            ignore_processing_instructions: false,
+            normalize_whitespace: false,
+        }
+    }
+}
+
+impl DeepEq {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the preferred prefixes of elements and attributes
+    /// must match, in addition to their namespace URIs.
+    pub fn set_compare_prefixes(mut self, compare_prefixes: bool) -> Self {
+        self.compare_prefixes = compare_prefixes;
+        self
+    }
+
+    /// Set whether attributes may appear in any order.
+    pub fn set_ignore_attribute_order(mut self, ignore_attribute_order: bool) -> Self {
+        self.ignore_attribute_order = ignore_attribute_order;
+        self
+    }
+
+    /// Set whether comments should be skipped.
+    pub fn set_ignore_comments(mut self, ignore_comments: bool) -> Self {
+        self.ignore_comments = ignore_comments;
+        self
+    }
+
+    /// Set whether processing instructions should be skipped.
+    pub fn set_ignore_processing_instructions(
+        mut self,
+        ignore_processing_instructions: bool,
+    ) -> Self {
+        self.ignore_processing_instructions = ignore_processing_instructions;
+        self
+    }
+
+    /// Set whether runs of whitespace in text should be treated as a
+    /// single space, ignoring leading and trailing whitespace. Text
+    /// that is only whitespace is then skipped.
+    pub fn set_normalize_whitespace(mut self, normalize_whitespace: bool) -> Self {
+        self.normalize_whitespace = normalize_whitespace;
+        self
+    }
+
+    /// Whether the nodes have the same content
+    pub fn deep_eq<'a, 'b, A, B>(&self, left: A, right: B) -> bool
+    where
+        A: Into<Node<'a>>,
+        B: Into<Node<'b>>,
+    {
+        self.compare(left, right).is_ok()
+    }
+
+    /// Compares the nodes, returning the first difference in
+    /// document order.
+    pub fn compare<'a, 'b, A, B>(&self, left: A, right: B) -> Result<(), Difference>
+    where
+        A: Into<Node<'a>>,
+        B: Into<Node<'b>>,
+    {
+        let (left, right) = (left.into(), right.into());
+        let path = match left {
+            Node::Root(_) => String::new(),
+            _ => String::from("."),
+        };
+        self.compare_nodes(&path, left, right)
+    }
+
+    fn compare_nodes(&self, path: &str, left: Node<'_>, right: Node<'_>) -> Result<(), Difference> {
+        let difference = |kind| Err(Difference::new(path, kind));
+
+        match (left, right) {
+            (Node::Root(_), Node::Root(_)) => self.compare_children(path, left, right),
+            (Node::Element(l), Node::Element(r)) => {
+                self.compare_names(path, l.name(), r.name())?;
+                self.compare_prefixes(path, l.preferred_prefix(), r.preferred_prefix())?;
+                self.compare_attributes(path, l, r)?;
+                self.compare_children(path, left, right)
+            }
+            (Node::Attribute(l), Node::Attribute(r)) => {
+                self.compare_names(path, l.name(), r.name())?;
+                self.compare_prefixes(path, l.preferred_prefix(), r.preferred_prefix())?;
+                compare_values(path, l.value(), r.value())
+            }
+            (Node::Text(l), Node::Text(r)) => compare_values(
+                path,
+                &self.normalize(l.text().into()),
+                &self.normalize(r.text().into()),
+            ),
+            (Node::EntityReference(l), Node::EntityReference(r)) => {
+                if l.name() != r.name() {
+                    return difference(DifferenceKind::Name(l.name().into(), r.name().into()));
+                }
+                compare_values(path, l.value().unwrap_or(""), r.value().unwrap_or(""))
+            }
+            (Node::Comment(l), Node::Comment(r)) => compare_values(path, l.text(), r.text()),
+            (Node::ProcessingInstruction(l), Node::ProcessingInstruction(r)) => {
+                if l.target() != r.target() {
+                    return difference(DifferenceKind::Name(l.target().into(), r.target().into()));
+                }
+                compare_values(path, l.value().unwrap_or(""), r.value().unwrap_or(""))
+            }
+            (Node::Namespace(l), Node::Namespace(r)) => {
+                if l.prefix() != r.prefix() {
+                    return difference(DifferenceKind::Name(l.prefix().into(), r.prefix().into()));
+                }
+                compare_values(path, l.uri(), r.uri())
+            }
+            _ => difference(DifferenceKind::NodeType(
+                left.node_type(),
+                right.node_type(),
+            )),
+        }
+    }
+
+    fn compare_names(
+        &self,
+        path: &str,
+        left: QName<'_>,
+        right: QName<'_>,
+    ) -> Result<(), Difference> {
+        if left == right {
+            Ok(())
+        } else {
+            let kind = DifferenceKind::Name(expanded_name(left), expanded_name(right));
+            Err(Difference::new(path, kind))
+        }
+    }
+
+    fn compare_prefixes(
+        &self,
+        path: &str,
+        left: Option<&str>,
+        right: Option<&str>,
+    ) -> Result<(), Difference> {
+        if !self.compare_prefixes || left == right {
+            Ok(())
+        } else {
+            let kind = DifferenceKind::Prefix(left.map(Into::into), right.map(Into::into));
+            Err(Difference::new(path, kind))
+        }
+    }
+
+    fn compare_attributes(
+        &self,
+        path: &str,
+        left: Element<'_>,
+        right: Element<'_>,
+    ) -> Result<(), Difference> {
+        let (left_attributes, right_attributes) = (left.attributes(), right.attributes());
+
+        if self.ignore_attribute_order {
+            for l in &left_attributes {
+                let attribute_path = attribute_path(path, l.name());
+                match right.attribute(l.name()) {
+                    Some(r) => self.compare_nodes(&attribute_path, (*l).into(), r.into())?,
+                    None => {
+                        let kind = DifferenceKind::Value(Some(l.value().into()), None);
+                        return Err(Difference::new(&attribute_path, kind));
+                    }
+                }
+            }
+            for r in &right_attributes {
+                if left.attribute(r.name()).is_none() {
+                    let kind = DifferenceKind::Value(None, Some(r.value().into()));
+                    return Err(Difference::new(&attribute_path(path, r.name()), kind));
+                }
+            }
+        } else {
+            for (l, r) in left_attributes.iter().zip(&right_attributes) {
+                self.compare_nodes(&attribute_path(path, l.name()), (*l).into(), (*r).into())?;
+            }
+            if left_attributes.len() != right_attributes.len() {
+                let i = left_attributes.len().min(right_attributes.len());
+                let (l, r) = (left_attributes.get(i), right_attributes.get(i));
+                let name = l.or(r).map(|a| a.name()).expect("An attribute is missing");
+                let kind =
+                    DifferenceKind::Value(l.map(|a| a.value().into()), r.map(|a| a.value().into()));
+                return Err(Difference::new(&attribute_path(path, name), kind));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compare_children(
+        &self,
+        path: &str,
+        left: Node<'_>,
+        right: Node<'_>,
+    ) -> Result<(), Difference> {
+        let left_children = self.significant_children(left);
+        let right_children = self.significant_children(right);
+
+        let mut steps = Steps::default();
+        for (l, r) in left_children.iter().zip(&right_children) {
+            let child_path = format!("{}/{}", path, steps.step(l));
+            match (l, r) {
+                (Child::Text(l), Child::Text(r)) => compare_values(&child_path, l, r)?,
+                (Child::Node(l), Child::Node(r)) => self.compare_nodes(&child_path, *l, *r)?,
+                _ => {
+                    let kind = DifferenceKind::NodeType(l.node_type(), r.node_type());
+                    return Err(Difference::new(&child_path, kind));
+                }
+            }
+        }
+
+        if left_children.len() != right_children.len() {
+            let kind = DifferenceKind::ChildCount(left_children.len(), right_children.len());
+            return Err(Difference::new(path, kind));
+        }
+
+        Ok(())
+    }
+
+    /// The children that take part in the comparison, with adjacent
+    /// text combined
+    fn significant_children<'d>(&self, node: Node<'d>) -> Vec<Child<'d>> {
+        let mut children = Vec::new();
+
+        for child in node.children() {
+            match child {
+                Node::Comment(_) if self.ignore_comments => continue,
+                Node::ProcessingInstruction(_) if self.ignore_processing_instructions => continue,
+                Node::Text(text) => {
+                    if let Some(&mut Child::Text(ref mut previous)) = children.last_mut() {
+                        previous.push_str(text.text());
+                        continue;
+                    }
+                    children.push(Child::Text(text.text().into()));
+                }
+                other => children.push(Child::Node(other)),
+            }
+        }
+
+        if self.normalize_whitespace {
+            children = children
+                .into_iter()
+                .filter_map(|child| match child {
+                    Child::Text(text) => {
+                        let text = self.normalize(text);
+                        if text.is_empty() {
+                            None
+                        } else {
+                            Some(Child::Text(text))
+                        }
+                    }
+                    other => Some(other),
+                })
+                .collect();
+        }
+
+        children
+    }
+
+    fn normalize(&self, text: String) -> String {
+        if self.normalize_whitespace {
+            text.split(|c: char| c.is_space_char())
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            text
+        }
+    }
+}
+
+/// Whether the nodes have the same content, using the default
+/// options of `DeepEq`
+pub fn deep_eq<'a, 'b, A, B>(left: A, right: B) -> bool
+where
+    A: Into<Node<'a>>,
+    B: Into<Node<'b>>,
+{
+    DeepEq::new().deep_eq(left, right)
+}
+
+enum Child<'d> {
+    // Adjacent text nodes, combined
+    Text(String),
+    Node(Node<'d>),
+}
+
+impl<'d> Child<'d> {
+    fn node_type(&self) -> NodeType {
+        match *self {
+            Child::Text(_) => NodeType::Text,
+            Child::Node(n) => n.node_type(),
+        }
+    }
+}
+
+/// Counts the children with each step, so that each step can be
+/// given a position among the matching siblings, as in XPath
+#[derive(Default)]
+struct Steps {
+    counts: HashMap<String, usize>,
+}
+
+impl Steps {
+    fn step(&mut self, child: &Child<'_>) -> String {
+        let step = match *child {
+            Child::Text(_) => String::from("text()"),
+            Child::Node(Node::Element(n)) => expanded_name(n.name()),
+            Child::Node(Node::EntityReference(n)) => format!("&{};", n.name()),
+            Child::Node(Node::Comment(_)) => String::from("comment()"),
+            Child::Node(Node::ProcessingInstruction(n)) => {
+                format!("processing-instruction({})", n.target())
+            }
+            Child::Node(_) => String::from("node()"),
+        };
+
+        let count = self.counts.entry(step.clone()).or_insert(0);
+        *count += 1;
+        format!("{}[{}]", step, count)
+    }
+}
+
+fn attribute_path(path: &str, name: QName<'_>) -> String {
+    format!("{}/@{}", path, expanded_name(name))
+}
+
+/// The name in `{namespace-uri}local-part` form
+fn expanded_name(name: QName<'_>) -> String {
+    match name.namespace_uri() {
+        Some(uri) => format!("{{{}}}{}", uri, name.local_part()),
+        None => name.local_part().into(),
+    }
+}
+
+fn compare_values(path: &str, left: &str, right: &str) -> Result<(), Difference> {
+    if left == right {
+        Ok(())
+    } else {
+        let kind = DifferenceKind::Value(Some(left.into()), Some(right.into()));
+        Err(Difference::new(path, kind))
+    }
+}
+
+/// The first place where two nodes differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    path: String,
+    kind: DifferenceKind,
+}
+
+impl Difference {
+    fn new(path: &str, kind: DifferenceKind) -> Self {
+        Difference {
+            path: path.into(),
+            kind,
+        }
+    }
+
+    /// The location of the difference, in the style of an XPath
+    /// location path. Names in a namespace are written as
+    /// `{namespace-uri}local-part`.
+    pub fn path(&self) -> &str {
+        if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        }
+    }
+
+    pub fn kind(&self) -> &DifferenceKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Nodes differ at {}: {}", self.path(), self.kind)
+    }
+}
+
+impl error::Error for Difference {
+    fn description(&self) -> &str {
+        "Nodes differ"
+    }
+}
+
+/// How two nodes differ. Each variant holds the detail of the left
+/// node followed by that of the right node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DifferenceKind {
+    NodeType(NodeType, NodeType),
+    /// The names differ. Names in a namespace are written as
+    /// `{namespace-uri}local-part`.
+    Name(String, String),
+    Prefix(Option<String>, Option<String>),
+    /// The text or values differ. An attribute that is only present
+    /// on one of the elements has a value of `None` on the other.
+    Value(Option<String>, Option<String>),
+    ChildCount(usize, usize),
+}
+
+impl fmt::Display for DifferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::DifferenceKind::*;
+
+        match *self {
+            NodeType(l, r) => write!(f, "node type {:?} != {:?}", l, r),
+            Name(ref l, ref r) => write!(f, "name {} != {}", l, r),
+            Prefix(ref l, ref r) => write!(f, "prefix {:?} != {:?}", l, r),
+            Value(ref l, ref r) => write!(f, "value {:?} != {:?}", l, r),
+            ChildCount(l, r) => write!(f, "{} children != {} children", l, r),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deep_eq, DeepEq, DifferenceKind};
+    use crate::{parser, Package};
+
+    fn parse(xml: &str) -> Package {
+        parser::parse(xml).expect("Failed to parse the XML string")
+    }
+
+    fn compare(options: DeepEq, left: &str, right: &str) -> Result<(), (String, DifferenceKind)> {
+        let (left, right) = (parse(left), parse(right));
+        options
+            .compare(left.as_document().root(), right.as_document().root())
+            .map_err(|d| (d.path().to_owned(), d.kind().clone()))
+    }
+
+    #[test]
+    fn identical_documents_are_equal() {
+        let left = parse("<a x='1'><b>text</b><!--c--><?pi v?></a>");
+        let right = parse("<a x='1'><b>text</b><!--c--><?pi v?></a>");
+
+        assert!(deep_eq(
+            left.as_document().root(),
+            right.as_document().root()
+        ));
+    }
+
+    #[test]
+    fn prefixes_are_ignored_unless_requested() {
+        let left = "<a:x xmlns:a='urn:x'/>";
+        let right = "<b:x xmlns:b='urn:x'/>";
+
+        assert_eq!(Ok(()), compare(DeepEq::new(), left, right));
+        assert_eq!(
+            Err((
+                "/{urn:x}x[1]".into(),
+                DifferenceKind::Prefix(Some("a".into()), Some("b".into()))
+            )),
+            compare(DeepEq::new().set_compare_prefixes(true), left, right)
+        );
+    }
+
+    #[test]
+    fn namespaces_are_part_of_names() {
+        assert_eq!(
+            Err((
+                "/a[1]/b[1]".into(),
+                DifferenceKind::Name("b".into(), "{urn:b}b".into())
+            )),
+            compare(DeepEq::new(), "<a><b/></a>", "<a><b xmlns='urn:b'/></a>")
+        );
+    }
+
+    #[test]
+    fn attribute_order_can_be_significant() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let left = doc.create_element("a");
+        left.set_attribute_value("x", "1");
+        left.set_attribute_value("y", "2");
+        let right = doc.create_element("a");
+        right.set_attribute_value("y", "2");
+        right.set_attribute_value("x", "1");
+
+        assert_eq!(Ok(()), DeepEq::new().compare(left, right));
+
+        let difference = DeepEq::new()
+            .set_ignore_attribute_order(false)
+            .compare(left, right)
+            .unwrap_err();
+        assert_eq!("./@x", difference.path());
+        assert_eq!(
+            &DifferenceKind::Name("x".into(), "y".into()),
+            difference.kind()
+        );
+    }
+
+    #[test]
+    fn missing_attributes_are_reported() {
+        assert_eq!(
+            Err((
+                "/a[1]/@y".into(),
+                DifferenceKind::Value(None, Some("2".into()))
+            )),
+            compare(DeepEq::new(), "<a x='1'/>", "<a x='1' y='2'/>")
+        );
+    }
+
+    #[test]
+    fn comments_and_processing_instructions_can_be_ignored() {
+        let left = "<a><!--c--><b/><?pi?></a>";
+        let right = "<a><b/></a>";
+
+        assert_eq!(
+            Err((
+                "/a[1]/comment()[1]".into(),
+                DifferenceKind::NodeType(
+                    crate::dom::NodeType::Comment,
+                    crate::dom::NodeType::Element
+                )
+            )),
+            compare(DeepEq::new(), left, right)
+        );
+
+        let options = DeepEq::new()
+            .set_ignore_comments(true)
+            .set_ignore_processing_instructions(true);
+        assert_eq!(Ok(()), compare(options, left, right));
+    }
+
+    #[test]
+    fn whitespace_can_be_normalized() {
+        let left = "<a>\n  <b>one   two</b>\n</a>";
+        let right = "<a><b> one two </b></a>";
+
+        assert!(compare(DeepEq::new(), left, right).is_err());
+        assert_eq!(
+            Ok(()),
+            compare(DeepEq::new().set_normalize_whitespace(true), left, right)
+        );
+    }
+
+    #[test]
+    fn only_xml_whitespace_is_normalized() {
+        let options = DeepEq::new().set_normalize_whitespace(true);
+
+        assert!(compare(options, "<a>x&#160;y</a>", "<a>x y</a>").is_err());
+    }
+
+    #[test]
+    fn differing_text_is_reported_with_its_position() {
+        assert_eq!(
+            Err((
+                "/a[1]/b[2]/text()[1]".into(),
+                DifferenceKind::Value(Some("two".into()), Some("three".into()))
+            )),
+            compare(
+                DeepEq::new(),
+                "<a><b>one</b><b>two</b></a>",
+                "<a><b>one</b><b>three</b></a>"
+            )
+        );
+    }
+
+    #[test]
+    fn extra_children_are_reported() {
+        assert_eq!(
+            Err(("/a[1]".into(), DifferenceKind::ChildCount(1, 2))),
+            compare(DeepEq::new(), "<a><b/></a>", "<a><b/><c/></a>")
+        );
+    }
+
+    #[test]
+    fn subtrees_can_be_compared() {
+        let left = parse("<a><b>1</b></a>");
+        let right = parse("<c><b>1</b></c>");
+        let left_b = left.as_document().root().children()[0]
+            .element()
+            .unwrap()
+            .children()[0]
+            .element()
+            .unwrap();
+        let right_b = right.as_document().root().children()[0]
+            .element()
+            .unwrap()
+            .children()[0]
+            .element()
+            .unwrap();
+
+        assert!(deep_eq(left_b, right_b));
+    }
+}
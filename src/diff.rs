@@ -0,0 +1,1126 @@
+//! Finds the changes between two documents and applies them to
+//! another document.
+//!
+//! A `Patch` is itself an XML document, in the spirit of [RFC
+//! 5261](https://tools.ietf.org/html/rfc5261), so it can be written
+//! with the `writer` and read back with the `parser`:
+//!
+//! ```xml
+//! <diff>
+//!   <remove sel="/node()[1]/node()[3]"/>
+//!   <add sel="/node()[1]" pos="0"><item/></add>
+//!   <move sel="/node()[1]/node()[4]" to="/node()[1]" pos="1"/>
+//!   <replace sel="/node()[1]/node()[2]/node()[1]">new text</replace>
+//!   <attribute sel="/node()[1]" name="id" namespace="urn:example" value="7"/>
+//!   <remove-attribute sel="/node()[1]" name="old"/>
+//! </diff>
+//! ```
+//!
+//! Each `sel` is a path from the root, where `node()[n]` selects the
+//! *n*th child. Operations are applied in order, and each path refers
+//! to the document as left by the operations before it. Positions
+//! count from zero and refer to the children once the node being
+//! placed has been removed from its previous position.
+//!
+//! ### Example
+//!
+//! ```
+//! use sxd_document::{diff, parser};
+//!
+//! let old = parser::parse("<list><a/><b/></list>").unwrap();
+//! let new = parser::parse("<list><b/><c x='1'/></list>").unwrap();
+//!
+//! let patch = diff::diff(old.as_document(), new.as_document());
+//!
+//! let target = parser::parse("<list><a/><b/></list>").unwrap();
+//! diff::apply_patch(target.as_document(), &patch).expect("Unable to apply");
+//! ```
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    error, fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::{
+    compare::DeepEq,
+    dom::{ChildOfElement, ChildOfRoot, Document, Element, Node, ParentOfChild},
+    Package, QName,
+};
+
+/// The location of a node, as the index of each of its ancestors and
+/// itself among their siblings. The empty path is the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(Vec<usize>);
+
+impl Path {
+    pub fn indexes(&self) -> &[usize] {
+        &self.0
+    }
+
+    fn child(&self, index: usize) -> Path {
+        let mut indexes = Vec::with_capacity(self.0.len() + 1);
+        indexes.extend_from_slice(&self.0);
+        indexes.push(index);
+        Path(indexes)
+    }
+
+    fn resolve<'d>(&self, document: Document<'d>) -> Result<Node<'d>, Error> {
+        let mut node = Node::Root(document.root());
+        for &index in &self.0 {
+            node = node
+                .children()
+                .get(index)
+                .cloned()
+                .ok_or_else(|| Error::PathNotFound(self.to_string()))?;
+        }
+        Ok(node)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for index in &self.0 {
+            write!(f, "/node()[{}]", index + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Path {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Path, Error> {
+        if s == "/" {
+            return Ok(Path::default());
+        }
+
+        let invalid = || Error::InvalidPath(s.to_owned());
+        let mut indexes = Vec::new();
+
+        for step in s.split('/').skip(1) {
+            let position = if step.starts_with("node()[") && step.ends_with(']') {
+                &step["node()[".len()..step.len() - 1]
+            } else {
+                return Err(invalid());
+            };
+            match position.parse::<usize>() {
+                Ok(position) if position > 0 => indexes.push(position - 1),
+                _ => return Err(invalid()),
+            }
+        }
+
+        if indexes.is_empty() || !s.starts_with('/') {
+            return Err(invalid());
+        }
+
+        Ok(Path(indexes))
+    }
+}
+
+/// A change to a document
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation<'p> {
+    /// Inserts a copy of the node as a child of `parent`
+    Insert {
+        parent: Path,
+        index: usize,
+        node: ChildOfElement<'p>,
+    },
+    Delete {
+        path: Path,
+    },
+    /// Moves the node to become a child of `parent`
+    Move {
+        path: Path,
+        parent: Path,
+        index: usize,
+    },
+    /// Replaces the text of a text node or comment, or the value of a
+    /// processing instruction
+    UpdateText {
+        path: Path,
+        text: String,
+    },
+    SetAttribute {
+        path: Path,
+        name: QName<'p>,
+        value: &'p str,
+    },
+    RemoveAttribute {
+        path: Path,
+        name: QName<'p>,
+    },
+}
+
+/// A sequence of operations, stored as an XML document. See the
+/// module documentation for the format.
+pub struct Patch {
+    package: Package,
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        let package = Package::new();
+        {
+            let doc = package.as_document();
+            doc.root().append_child(doc.create_element("diff"));
+        }
+        Patch { package }
+    }
+}
+
+impl Patch {
+    /// Creates a patch without any operations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses a document, such as one read by the `parser`, as a patch.
+    /// Fails if the document is not a valid patch.
+    pub fn from_package(package: Package) -> Result<Patch, Error> {
+        {
+            let doc = package.as_document();
+            let root = doc
+                .root()
+                .children()
+                .into_iter()
+                .find_map(ChildOfRoot::element);
+            match root {
+                Some(root) if root.name() == QName::new("diff") => {
+                    for element in operation_elements(root) {
+                        operation(element)?;
+                    }
+                }
+                _ => return Err(Error::InvalidOperation("diff".into())),
+            }
+        }
+        Ok(Patch { package })
+    }
+
+    /// The document holding the operations, which may be written with
+    /// the `writer`
+    pub fn as_document(&self) -> Document<'_> {
+        self.package.as_document()
+    }
+
+    /// The operations of the patch. Fails if the document has been
+    /// changed so that it is no longer a valid patch.
+    pub fn operations(&self) -> Result<Vec<Operation<'_>>, Error> {
+        operation_elements(self.diff_element()?)
+            .map(operation)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diff_element()
+            .map_or(true, |diff| operation_elements(diff).next().is_none())
+    }
+
+    fn diff_element(&self) -> Result<Element<'_>, Error> {
+        self.as_document()
+            .root()
+            .children()
+            .into_iter()
+            .find_map(ChildOfRoot::element)
+            .ok_or_else(|| Error::InvalidOperation("diff".into()))
+    }
+}
+
+fn operation_elements(diff: Element<'_>) -> impl Iterator<Item = Element<'_>> {
+    diff.children_iter().filter_map(ChildOfElement::element)
+}
+
+fn operation(element: Element<'_>) -> Result<Operation<'_>, Error> {
+    let name = element.name();
+    let invalid = || Error::InvalidOperation(name.local_part().to_owned());
+    let attribute = |name: &str| element.attribute_value(name).ok_or_else(invalid);
+    let index = |name: &str| attribute(name)?.parse::<usize>().map_err(|_| invalid());
+    let attribute_name = || {
+        let namespace_uri = element.attribute_value("namespace");
+        attribute("name").map(|local_part| QName::with_namespace_uri(namespace_uri, local_part))
+    };
+
+    if name.namespace_uri().is_some() {
+        return Err(invalid());
+    }
+
+    let path = attribute("sel")?.parse()?;
+
+    let operation = match name.local_part() {
+        "add" => {
+            let children = element.children();
+            if children.len() != 1 {
+                return Err(invalid());
+            }
+            Operation::Insert {
+                parent: path,
+                index: index("pos")?,
+                node: children[0],
+            }
+        }
+        "remove" => Operation::Delete { path },
+        "move" => Operation::Move {
+            path,
+            parent: attribute("to")?.parse()?,
+            index: index("pos")?,
+        },
+        "replace" => Operation::UpdateText {
+            path,
+            text: Node::from(element).string_value(),
+        },
+        "attribute" => Operation::SetAttribute {
+            path,
+            name: attribute_name()?,
+            value: attribute("value")?,
+        },
+        "remove-attribute" => Operation::RemoveAttribute {
+            path,
+            name: attribute_name()?,
+        },
+        _ => return Err(invalid()),
+    };
+
+    Ok(operation)
+}
+
+/// Finds the operations that change the `old` document into the
+/// `new` one. Children are matched by their kind and name, keeping
+/// as many in their original order as possible; an identical child
+/// that has been reordered among its siblings is moved. A node that
+/// changes parent is removed and inserted again. Namespace
+/// declarations and preferred prefixes are not compared.
+pub fn diff(old: Document<'_>, new: Document<'_>) -> Patch {
+    let package = Package::new();
+
+    {
+        let doc = package.as_document();
+        let operations = doc.create_element("diff");
+        doc.root().append_child(operations);
+
+        // Operations are recorded while changing a copy of the old
+        // document, so each path refers to the copy as it is at that
+        // moment
+        let work = Package::new();
+        let work_doc = work.as_document();
+        for child in old.root().children_iter() {
+            if let Some(copy) = root_child(work_doc.import_node(child, true)) {
+                work_doc.root().append_child(copy);
+            }
+        }
+
+        let differ = Differ {
+            operations,
+            equality: DeepEq::new().set_compare_prefixes(true),
+        };
+        differ.diff_children(
+            ParentOfChild::Root(work_doc.root()),
+            ParentOfChild::Root(new.root()),
+            &Path::default(),
+        );
+    }
+
+    Patch { package }
+}
+
+struct Differ<'p> {
+    operations: Element<'p>,
+    equality: DeepEq,
+}
+
+impl<'p> Differ<'p> {
+    fn record(&self, name: &str, path: &Path) -> Element<'p> {
+        let element = self.operations.document().create_element(name);
+        element.set_attribute_value("sel", &path.to_string());
+        self.operations.append_child(element);
+        element
+    }
+
+    fn record_attribute(&self, operation: &str, path: &Path, name: QName<'_>) -> Element<'p> {
+        let record = self.record(operation, path);
+        record.set_attribute_value("name", name.local_part());
+        if let Some(namespace_uri) = name.namespace_uri() {
+            record.set_attribute_value("namespace", namespace_uri);
+        }
+        record
+    }
+
+    fn diff_element(&self, work: Element<'_>, new: Element<'_>, path: &Path) {
+        for attribute in new.attributes() {
+            let (name, value) = (attribute.name(), attribute.value());
+            if work.attribute_value(name) != Some(value) {
+                self.record_attribute("attribute", path, name)
+                    .set_attribute_value("value", value);
+                work.set_attribute_value(name, value);
+            }
+        }
+
+        for attribute in work.attributes() {
+            let name = attribute.name();
+            if new.attribute(name).is_none() {
+                self.record_attribute("remove-attribute", path, name);
+                work.remove_attribute(name);
+            }
+        }
+
+        self.diff_children(
+            ParentOfChild::Element(work),
+            ParentOfChild::Element(new),
+            path,
+        );
+    }
+
+    /// Changes the children of `work`, found at `parent_path`, to
+    /// match those of `new`
+    fn diff_children(&self, work: ParentOfChild<'_>, new: ParentOfChild<'_>, parent_path: &Path) {
+        let olds = children(work);
+        let news = children(new);
+
+        let mut partners = vec![None; news.len()];
+        let mut matched = vec![false; olds.len()];
+
+        let old_keys: Vec<_> = olds.iter().map(|&c| key(c)).collect();
+        let new_keys: Vec<_> = news.iter().map(|&c| key(c)).collect();
+        for (i, j) in longest_common_subsequence(&old_keys, &new_keys) {
+            partners[j] = Some(i);
+            matched[i] = true;
+        }
+
+        // Unpaired children that are identical have been reordered.
+        // Only children with the same hash need to be compared.
+        let mut unmatched = HashMap::new();
+        for (i, &o) in olds.iter().enumerate() {
+            if !matched[i] {
+                unmatched
+                    .entry(subtree_hash(o))
+                    .or_insert_with(Vec::new)
+                    .push(i);
+            }
+        }
+        for (j, &n) in news.iter().enumerate() {
+            if partners[j].is_some() {
+                continue;
+            }
+            let candidates = match unmatched.get_mut(&subtree_hash(n)) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+            let moved = candidates
+                .iter()
+                .position(|&i| self.equality.deep_eq(olds[i], n));
+            if let Some(position) = moved {
+                let i = candidates.remove(position);
+                partners[j] = Some(i);
+                matched[i] = true;
+            }
+        }
+
+        // The kept children stay in order, so each position follows
+        // from how many of them have been removed or placed
+        let mut removed = 0;
+        let mut placed = Vec::new();
+        let mut kept_index = vec![0; olds.len()];
+        for (i, &o) in olds.iter().enumerate() {
+            if matched[i] {
+                kept_index[i] = placed.len();
+                placed.push(false);
+            } else {
+                self.record("remove", &parent_path.child(i - removed));
+                remove_from_parent(o);
+                removed += 1;
+            }
+        }
+
+        // The first `j` children are placed, and are followed by the
+        // kept children that have not been placed yet
+        let mut next_kept = 0;
+        for (j, &n) in news.iter().enumerate() {
+            while next_kept < placed.len() && placed[next_kept] {
+                next_kept += 1;
+            }
+            match partners[j] {
+                Some(i) => {
+                    let k = kept_index[i];
+                    if k != next_kept {
+                        let position = j + placed[next_kept..k].iter().filter(|&&p| !p).count();
+                        let record = self.record("move", &parent_path.child(position));
+                        record.set_attribute_value("to", &parent_path.to_string());
+                        record.set_attribute_value("pos", &j.to_string());
+                        insert_child(work, j, olds[i]);
+                    }
+                    placed[k] = true;
+                }
+                None => {
+                    let record = self.record("add", parent_path);
+                    record.set_attribute_value("pos", &j.to_string());
+                    record.append_child(record.document().import_node(n, true));
+                    insert_child(work, j, work_document(work).import_node(n, true));
+                }
+            }
+        }
+
+        for (j, (o, &n)) in children(work).into_iter().zip(&news).enumerate() {
+            let text = match (o, n) {
+                (ChildOfElement::Element(o), ChildOfElement::Element(n)) => {
+                    self.diff_element(o, n, &parent_path.child(j));
+                    continue;
+                }
+                (ChildOfElement::Text(o), ChildOfElement::Text(n)) if o.text() != n.text() => {
+                    o.set_text(n.text());
+                    n.text()
+                }
+                (ChildOfElement::Comment(o), ChildOfElement::Comment(n))
+                    if o.text() != n.text() =>
+                {
+                    o.set_text(n.text());
+                    n.text()
+                }
+                (
+                    ChildOfElement::ProcessingInstruction(o),
+                    ChildOfElement::ProcessingInstruction(n),
+                ) if o.value() != n.value() => {
+                    o.set_value(n.value());
+                    n.value().unwrap_or("")
+                }
+                _ => continue,
+            };
+
+            let record = self.record("replace", &parent_path.child(j));
+            record.append_child(record.document().create_text(text));
+        }
+    }
+}
+
+/// Children with the same key may be paired with each other
+fn key(node: ChildOfElement<'_>) -> String {
+    match node {
+        ChildOfElement::Element(n) => {
+            let name = n.name();
+            format!(
+                "<{}|{}",
+                name.namespace_uri().unwrap_or(""),
+                name.local_part()
+            )
+        }
+        ChildOfElement::Text(_) => String::from("#text"),
+        ChildOfElement::EntityReference(n) => {
+            format!("&{};{}", n.name(), n.value().unwrap_or(""))
+        }
+        ChildOfElement::Comment(_) => String::from("#comment"),
+        ChildOfElement::ProcessingInstruction(n) => format!("?{}", n.target()),
+    }
+}
+
+/// A hash of the parts of a subtree that `DeepEq` compares, so that
+/// deeply equal subtrees have the same hash
+fn subtree_hash(node: ChildOfElement<'_>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_node(node, &mut hasher);
+    hasher.finish()
+}
+
+fn hash_node(node: ChildOfElement<'_>, hasher: &mut DefaultHasher) {
+    match node {
+        ChildOfElement::Element(n) => {
+            0u8.hash(hasher);
+            hash_name(n.name(), hasher);
+            n.preferred_prefix().hash(hasher);
+
+            // Attributes may be in any order
+            let attributes = n.attributes().iter().fold(0u64, |sum, a| {
+                let mut attribute = DefaultHasher::new();
+                hash_name(a.name(), &mut attribute);
+                a.preferred_prefix().hash(&mut attribute);
+                a.value().hash(&mut attribute);
+                sum.wrapping_add(attribute.finish())
+            });
+            attributes.hash(hasher);
+
+            // Adjacent text is compared as one
+            let mut in_text = false;
+            for child in n.children() {
+                match child {
+                    ChildOfElement::Text(t) => {
+                        if !in_text {
+                            1u8.hash(hasher);
+                            in_text = true;
+                        }
+                        hasher.write(t.text().as_bytes());
+                    }
+                    other => {
+                        in_text = false;
+                        hash_node(other, hasher);
+                    }
+                }
+            }
+            0xffu8.hash(hasher);
+        }
+        ChildOfElement::Text(n) => {
+            1u8.hash(hasher);
+            hasher.write(n.text().as_bytes());
+        }
+        ChildOfElement::EntityReference(n) => {
+            2u8.hash(hasher);
+            n.name().hash(hasher);
+            n.value().unwrap_or("").hash(hasher);
+        }
+        ChildOfElement::Comment(n) => {
+            3u8.hash(hasher);
+            n.text().hash(hasher);
+        }
+        ChildOfElement::ProcessingInstruction(n) => {
+            4u8.hash(hasher);
+            n.target().hash(hasher);
+            n.value().unwrap_or("").hash(hasher);
+        }
+    }
+}
+
+fn hash_name(name: QName<'_>, hasher: &mut DefaultHasher) {
+    name.namespace_uri().hash(hasher);
+    name.local_part().hash(hasher);
+}
+
+/// The pairs of indexes of the longest sequence of equal items that
+/// occur in the same order in both slices. The common prefix and
+/// suffix are paired directly, and the rest uses Hirschberg's
+/// algorithm, which needs space linear in the length of the slices.
+fn longest_common_subsequence<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut pairs: Vec<_> = (0..prefix).map(|i| (i, i)).collect();
+    hirschberg(
+        &a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix],
+        (prefix, prefix),
+        &mut pairs,
+    );
+    pairs.extend((0..suffix).map(|k| (a.len() - suffix + k, b.len() - suffix + k)));
+    pairs
+}
+
+fn hirschberg<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    if a.is_empty() || b.is_empty() {
+        return;
+    }
+    if a.len() == 1 {
+        if let Some(j) = b.iter().position(|y| *y == a[0]) {
+            pairs.push((offset.0, offset.1 + j));
+        }
+        return;
+    }
+
+    // Split `b` where the halves of `a` have the longest common
+    // subsequences with its start and end
+    let middle = a.len() / 2;
+    let upper = lcs_lengths(a[..middle].iter(), b.iter());
+    let lower = lcs_lengths(a[middle..].iter().rev(), b.iter().rev());
+    let split = (0..=b.len())
+        .max_by_key(|&k| (upper[k] + lower[b.len() - k], std::cmp::Reverse(k)))
+        .expect("There is always a split");
+
+    hirschberg(&a[..middle], &b[..split], offset, pairs);
+    hirschberg(
+        &a[middle..],
+        &b[split..],
+        (offset.0 + middle, offset.1 + split),
+        pairs,
+    );
+}
+
+/// The length of the longest common subsequence of `a` with each
+/// prefix of `b`, keeping only one row of lengths
+fn lcs_lengths<'t, T, A, B>(a: A, b: B) -> Vec<usize>
+where
+    T: PartialEq + 't,
+    A: Iterator<Item = &'t T>,
+    B: Iterator<Item = &'t T> + Clone,
+{
+    let mut lengths = vec![0; b.clone().count() + 1];
+    for x in a {
+        // The length for the previous item of `a` and `b[..j]`
+        let mut diagonal = 0;
+        for (j, y) in b.clone().enumerate() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if x == y {
+                diagonal + 1
+            } else {
+                above.max(lengths[j])
+            };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+fn children(parent: ParentOfChild<'_>) -> Vec<ChildOfElement<'_>> {
+    match parent {
+        ParentOfChild::Root(n) => n.children_iter().map(Into::into).collect(),
+        ParentOfChild::Element(n) => n.children(),
+    }
+}
+
+fn index_of<'d>(parent: ParentOfChild<'d>, child: ChildOfElement<'d>) -> Option<usize> {
+    children(parent).iter().position(|&c| c == child)
+}
+
+fn work_document(parent: ParentOfChild<'_>) -> Document<'_> {
+    match parent {
+        ParentOfChild::Root(n) => n.document(),
+        ParentOfChild::Element(n) => n.document(),
+    }
+}
+
+fn insert_child<'d>(parent: ParentOfChild<'d>, index: usize, child: ChildOfElement<'d>) -> bool {
+    match parent {
+        ParentOfChild::Root(n) => match root_child(child) {
+            Some(child) => {
+                n.insert_child(index, child);
+                true
+            }
+            None => false,
+        },
+        ParentOfChild::Element(n) => {
+            n.insert_child(index, child);
+            true
+        }
+    }
+}
+
+fn remove_from_parent(child: ChildOfElement<'_>) {
+    match child {
+        ChildOfElement::Element(n) => n.remove_from_parent(),
+        ChildOfElement::Text(n) => n.remove_from_parent(),
+        ChildOfElement::EntityReference(n) => n.remove_from_parent(),
+        ChildOfElement::Comment(n) => n.remove_from_parent(),
+        ChildOfElement::ProcessingInstruction(n) => n.remove_from_parent(),
+    }
+}
+
+fn root_child(child: ChildOfElement<'_>) -> Option<ChildOfRoot<'_>> {
+    match child {
+        ChildOfElement::Element(n) => Some(n.into()),
+        ChildOfElement::Comment(n) => Some(n.into()),
+        ChildOfElement::ProcessingInstruction(n) => Some(n.into()),
+        ChildOfElement::Text(_) | ChildOfElement::EntityReference(_) => None,
+    }
+}
+
+/// Applies each operation of the patch to the document, in order.
+/// When an operation fails, the operations before it remain applied.
+pub fn apply_patch(document: Document<'_>, patch: &Patch) -> Result<(), Error> {
+    for operation in patch.operations()? {
+        apply(document, operation)?;
+    }
+    Ok(())
+}
+
+fn apply(document: Document<'_>, operation: Operation<'_>) -> Result<(), Error> {
+    match operation {
+        Operation::Insert {
+            parent,
+            index,
+            node,
+        } => {
+            let target = parent_of_child(parent.resolve(document)?, &parent)?;
+            if index > children(target).len() {
+                return Err(Error::PathNotFound(parent.to_string()));
+            }
+            if !insert_child(target, index, document.import_node(node, true)) {
+                return Err(Error::InvalidTarget(parent.to_string()));
+            }
+        }
+        Operation::Delete { path } => {
+            remove_from_parent(child_of_element(path.resolve(document)?, &path)?);
+        }
+        Operation::Move {
+            path,
+            parent,
+            index,
+        } => {
+            let node = path.resolve(document)?;
+            let child = child_of_element(node, &path)?;
+            let target = parent_of_child(parent.resolve(document)?, &parent)?;
+
+            let target_node = Node::from(target);
+            if target_node == node || target_node.ancestors().any(|a| a == node) {
+                return Err(Error::InvalidTarget(parent.to_string()));
+            }
+            // The position does not count the node itself
+            let mut siblings = children(target).len();
+            if index_of(target, child).is_some() {
+                siblings -= 1;
+            }
+            if index > siblings {
+                return Err(Error::PathNotFound(parent.to_string()));
+            }
+            if !insert_child(target, index, child) {
+                return Err(Error::InvalidTarget(parent.to_string()));
+            }
+        }
+        Operation::UpdateText { path, text } => match path.resolve(document)? {
+            Node::Text(n) => n.set_text(&text),
+            Node::Comment(n) => n.set_text(&text),
+            Node::ProcessingInstruction(n) => n.set_value(Some(&text)),
+            _ => return Err(Error::InvalidTarget(path.to_string())),
+        },
+        Operation::SetAttribute { path, name, value } => {
+            element(path.resolve(document)?, &path)?.set_attribute_value(name, value);
+        }
+        Operation::RemoveAttribute { path, name } => {
+            element(path.resolve(document)?, &path)?.remove_attribute(name);
+        }
+    }
+
+    Ok(())
+}
+
+fn element<'d>(node: Node<'d>, path: &Path) -> Result<Element<'d>, Error> {
+    node.element()
+        .ok_or_else(|| Error::InvalidTarget(path.to_string()))
+}
+
+fn parent_of_child<'d>(node: Node<'d>, path: &Path) -> Result<ParentOfChild<'d>, Error> {
+    match node {
+        Node::Root(n) => Ok(ParentOfChild::Root(n)),
+        Node::Element(n) => Ok(ParentOfChild::Element(n)),
+        _ => Err(Error::InvalidTarget(path.to_string())),
+    }
+}
+
+fn child_of_element<'d>(node: Node<'d>, path: &Path) -> Result<ChildOfElement<'d>, Error> {
+    match node {
+        Node::Element(n) => Ok(n.into()),
+        Node::Text(n) => Ok(n.into()),
+        Node::EntityReference(n) => Ok(n.into()),
+        Node::Comment(n) => Ok(n.into()),
+        Node::ProcessingInstruction(n) => Ok(n.into()),
+        _ => Err(Error::InvalidTarget(path.to_string())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// An element of the patch is not a known operation, or lacks a
+    /// required attribute
+    InvalidOperation(String),
+    /// A path is not of the form `/node()[1]/node()[2]`
+    InvalidPath(String),
+    /// No node exists at the path
+    PathNotFound(String),
+    /// The node at the path cannot be changed by the operation
+    InvalidTarget(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Error::*;
+
+        match *self {
+            InvalidOperation(ref name) => write!(f, "Invalid patch operation: {}", name),
+            InvalidPath(ref path) => write!(f, "Invalid path: {}", path),
+            PathNotFound(ref path) => write!(f, "No node at path: {}", path),
+            InvalidTarget(ref path) => write!(f, "Operation cannot be applied at path: {}", path),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "Unable to apply patch"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_patch, diff, longest_common_subsequence, Error, Operation, Patch, Path};
+    use crate::{compare::deep_eq, parser, writer, Package};
+
+    fn parse(xml: &str) -> Package {
+        parser::parse(xml).expect("Failed to parse the XML string")
+    }
+
+    fn round_trip(old: &str, new: &str) -> Patch {
+        let (old_p, new_p) = (parse(old), parse(new));
+        let patch = diff(old_p.as_document(), new_p.as_document());
+
+        let target = parse(old);
+        apply_patch(target.as_document(), &patch).expect("Unable to apply the patch");
+        assert!(
+            deep_eq(target.as_document().root(), new_p.as_document().root()),
+            "Patch did not change {} into {}",
+            old,
+            new
+        );
+
+        patch
+    }
+
+    fn serialize(patch: &Patch) -> String {
+        let mut output = Vec::new();
+        writer::format_document(&patch.as_document(), &mut output)
+            .expect("Unable to write the patch");
+        String::from_utf8(output).expect("The patch was not UTF-8")
+    }
+
+    #[test]
+    fn identical_documents_have_an_empty_patch() {
+        let patch = round_trip(
+            "<a x='1'><b>t</b><!--c--></a>",
+            "<a x='1'><b>t</b><!--c--></a>",
+        );
+        assert!(patch.is_empty());
+    }
+
+    #[test]
+    fn inserted_and_deleted_children_are_found() {
+        let patch = round_trip("<a><b/><c/><d/></a>", "<a><b/><e>new</e><d/></a>");
+        let operations = patch.operations().expect("Invalid patch");
+
+        assert_eq!(2, operations.len());
+        assert_eq!(
+            Operation::Delete {
+                path: "/node()[1]/node()[2]".parse().unwrap()
+            },
+            operations[0]
+        );
+        match operations[1] {
+            Operation::Insert {
+                ref parent,
+                index,
+                node,
+            } => {
+                assert_eq!("/node()[1]", parent.to_string());
+                assert_eq!(1, index);
+                assert_eq!("e", node.element().unwrap().name().local_part());
+            }
+            ref other => panic!("Expected an insertion, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reordered_children_are_moved() {
+        let patch = round_trip(
+            "<a><b>1</b><c>2</c><d>3</d></a>",
+            "<a><d>3</d><b>1</b><c>2</c></a>",
+        );
+        let operations = patch.operations().expect("Invalid patch");
+
+        assert_eq!(1, operations.len());
+        assert_eq!(
+            Operation::Move {
+                path: "/node()[1]/node()[3]".parse().unwrap(),
+                parent: "/node()[1]".parse().unwrap(),
+                index: 0,
+            },
+            operations[0]
+        );
+    }
+
+    #[test]
+    fn moves_are_found_regardless_of_attribute_order() {
+        let patch = round_trip(
+            "<a><c/><d/><b x='1' y='2'>t<!--c--></b></a>",
+            "<a><b y='2' x='1'>t<!--c--></b><c/><d/></a>",
+        );
+        let operations = patch.operations().expect("Invalid patch");
+
+        assert_eq!(1, operations.len());
+        match operations[0] {
+            Operation::Move { index, .. } => assert_eq!(0, index),
+            ref other => panic!("Expected a move, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_and_attribute_changes_are_found() {
+        let patch = round_trip(
+            "<a x='1' y='2'>old<!--c--><?pi v?></a>",
+            "<a x='1' z='3'>new<!--d--><?pi w?></a>",
+        );
+        let operations = patch.operations().expect("Invalid patch");
+
+        assert_eq!(5, operations.len());
+        assert!(operations.contains(&Operation::UpdateText {
+            path: "/node()[1]/node()[1]".parse().unwrap(),
+            text: "new".into(),
+        }));
+        match operations[0] {
+            Operation::SetAttribute { name, value, .. } => {
+                assert_eq!("z", name.local_part());
+                assert_eq!("3", value);
+            }
+            ref other => panic!("Expected a set attribute, found {:?}", other),
+        }
+        match operations[1] {
+            Operation::RemoveAttribute { name, .. } => assert_eq!("y", name.local_part()),
+            ref other => panic!("Expected a remove attribute, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_and_root_changes_apply() {
+        round_trip(
+            "<a><b><c x='1'>t</c></b><e/></a>",
+            "<a><e><f/></e><b><c x='2'>u</c>v</b></a>",
+        );
+        round_trip("<a><b/></a>", "<z><b/></z>");
+        round_trip(
+            "<a xmlns='urn:x'><b/></a>",
+            "<a xmlns='urn:x'><b xmlns:p='urn:p' p:q='1'/></a>",
+        );
+    }
+
+    #[test]
+    fn many_reordered_siblings_apply() {
+        let old: String = (0..60)
+            .map(|i| format!("<e{}>{}</e{}>", i % 7, i, i % 7))
+            .collect();
+        let new: String = (0..60)
+            .rev()
+            .filter(|i| i % 5 != 0)
+            .map(|i| match i % 9 {
+                0 => format!("<e{}>{}<n/></e{}>", i % 7, i, i % 7),
+                4 => format!("<new>{}</new><e{}>{}</e{}>", i, i % 7, i, i % 7),
+                _ => format!("<e{}>{}</e{}>", i % 7, i, i % 7),
+            })
+            .collect();
+
+        round_trip(&format!("<a>{}</a>", old), &format!("<a>{}</a>", new));
+    }
+
+    #[test]
+    fn patches_survive_serialization() {
+        let old = "<a><b>1</b><c/></a>";
+        let new_p = parse("<a><c/><b>2</b><d y='1'/></a>");
+        let patch = diff(parse(old).as_document(), new_p.as_document());
+
+        let read = Patch::from_package(parse(&serialize(&patch))).expect("Invalid patch");
+        assert_eq!(
+            patch.operations().map(|o| o.len()),
+            read.operations().map(|o| o.len())
+        );
+
+        let target = parse(old);
+        apply_patch(target.as_document(), &read).expect("Unable to apply the patch");
+        assert!(deep_eq(
+            target.as_document().root(),
+            new_p.as_document().root()
+        ));
+    }
+
+    #[test]
+    fn invalid_patches_are_rejected() {
+        let unknown = parse("<diff><frobnicate sel='/'/></diff>");
+        assert_eq!(
+            Some(Error::InvalidOperation("frobnicate".into())),
+            Patch::from_package(unknown).err()
+        );
+
+        let bad_path = parse("<diff><remove sel='/node()[0]'/></diff>");
+        assert_eq!(
+            Some(Error::InvalidPath("/node()[0]".into())),
+            Patch::from_package(bad_path).err()
+        );
+    }
+
+    #[test]
+    fn missing_targets_are_reported() {
+        let patch = Patch::from_package(parse("<diff><remove sel='/node()[1]/node()[4]'/></diff>"))
+            .expect("Invalid patch");
+        let target = parse("<a><b/></a>");
+
+        assert_eq!(
+            Err(Error::PathNotFound("/node()[1]/node()[4]".into())),
+            apply_patch(target.as_document(), &patch)
+        );
+    }
+
+    #[test]
+    fn moving_a_node_into_itself_fails() {
+        let patch = Patch::from_package(parse(
+            "<diff><move sel='/node()[1]/node()[1]' to='/node()[1]/node()[1]/node()[1]' pos='0'/></diff>",
+        ))
+        .expect("Invalid patch");
+        let target = parse("<a><b><c/></b></a>");
+
+        assert_eq!(
+            Err(Error::InvalidTarget(
+                "/node()[1]/node()[1]/node()[1]".into()
+            )),
+            apply_patch(target.as_document(), &patch)
+        );
+    }
+
+    #[test]
+    fn moving_a_node_past_the_end_fails() {
+        let patch = Patch::from_package(parse(
+            "<diff><move sel='/node()[1]/node()[1]' to='/node()[1]' pos='2'/></diff>",
+        ))
+        .expect("Invalid patch");
+        let target = parse("<a><b/><c/></a>");
+
+        assert_eq!(
+            Err(Error::PathNotFound("/node()[1]".into())),
+            apply_patch(target.as_document(), &patch)
+        );
+    }
+
+    #[test]
+    fn a_patch_without_its_diff_element_is_invalid() {
+        let patch = Patch::from_package(parse("<diff><remove sel='/node()[1]'/></diff>"))
+            .expect("Invalid patch");
+        patch.as_document().root().clear_children();
+        let target = parse("<a/>");
+
+        assert_eq!(
+            Err(Error::InvalidOperation("diff".into())),
+            patch.operations()
+        );
+        assert_eq!(
+            Err(Error::InvalidOperation("diff".into())),
+            apply_patch(target.as_document(), &patch)
+        );
+    }
+
+    #[test]
+    fn common_subsequence_is_the_longest() {
+        let pairs = longest_common_subsequence(b"xabcdy", b"xbadcy");
+        assert_eq!(4, pairs.len());
+        assert_eq!(Some(&(0, 0)), pairs.first());
+        assert_eq!(Some(&(5, 5)), pairs.last());
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        assert!(pairs.iter().all(|&(i, j)| b"xabcdy"[i] == b"xbadcy"[j]));
+
+        assert_eq!(
+            vec![(1, 0), (3, 1)],
+            longest_common_subsequence(b"abcd", b"bd")
+        );
+        assert!(longest_common_subsequence(b"abc", b"").is_empty());
+    }
+
+    #[test]
+    fn paths_are_formatted_and_parsed() {
+        let path: Path = "/node()[1]/node()[3]".parse().unwrap();
+        assert_eq!(&[0, 2], path.indexes());
+        assert_eq!("/node()[1]/node()[3]", path.to_string());
+        assert_eq!("/", Path::default().to_string());
+        assert_eq!(Ok(Path::default()), "/".parse());
+        assert!("node()[1]".parse::<Path>().is_err());
+    }
+}
@@ -1,9 +1,9 @@
 //! A traditional DOM tree interface for navigating and manipulating
 //! XML documents.
 
-use std::{fmt, hash};
+use std::{cmp::Ordering, fmt, hash, ops::Range};
 
-use super::{raw, QName};
+use super::{raw, str::XmlChar, uri, QName};
 
 type SiblingFn<T> = unsafe fn(&raw::Connections, T) -> raw::SiblingIter<'_>;
 
@@ -30,6 +30,7 @@ impl<'d> Document<'d> {
     wrapper!(wrap_element, Element, raw::Element);
     wrapper!(wrap_attribute, Attribute, raw::Attribute);
     wrapper!(wrap_text, Text, raw::Text);
+    wrapper!(wrap_entity_reference, EntityReference, raw::EntityReference);
     wrapper!(wrap_comment, Comment, raw::Comment);
     wrapper!(wrap_pi, ProcessingInstruction, raw::ProcessingInstruction);
 
@@ -62,6 +63,9 @@ impl<'d> Document<'d> {
         match node {
             raw::ChildOfElement::Element(n) => ChildOfElement::Element(self.wrap_element(n)),
             raw::ChildOfElement::Text(n) => ChildOfElement::Text(self.wrap_text(n)),
+            raw::ChildOfElement::EntityReference(n) => {
+                ChildOfElement::EntityReference(self.wrap_entity_reference(n))
+            }
             raw::ChildOfElement::Comment(n) => ChildOfElement::Comment(self.wrap_comment(n)),
             raw::ChildOfElement::ProcessingInstruction(n) => {
                 ChildOfElement::ProcessingInstruction(self.wrap_pi(n))
@@ -84,6 +88,12 @@ impl<'d> Document<'d> {
         self.wrap_text(self.storage.create_text(text))
     }
 
+    /// Creates a reference to a general entity, with its replacement
+    /// text when it is known
+    pub fn create_entity_reference(self, name: &str, value: Option<&str>) -> EntityReference<'d> {
+        self.wrap_entity_reference(self.storage.create_entity_reference(name, value))
+    }
+
     pub fn create_comment(self, text: &str) -> Comment<'d> {
         self.wrap_comment(self.storage.create_comment(text))
     }
@@ -96,6 +106,92 @@ impl<'d> Document<'d> {
         self.wrap_pi(self.storage.create_processing_instruction(target, value))
     }
 
+    /// Copies a node, which may belong to another document, into this
+    /// document. The copy is detached, ready to be appended. When
+    /// `deep` is true, the descendants of an element are copied too.
+    ///
+    /// An imported element declares every namespace that was in scope
+    /// for the original, including the default namespace, so that the
+    /// names of its attributes and descendants keep their prefixes
+    /// wherever it is appended.
+    pub fn import_node<'o, N>(self, node: N, deep: bool) -> ChildOfElement<'d>
+    where
+        N: Into<ChildOfElement<'o>>,
+    {
+        match node.into() {
+            ChildOfElement::Element(n) => self.import_element(n, deep).into(),
+            ChildOfElement::Text(n) => self.create_text(n.text()).into(),
+            ChildOfElement::EntityReference(n) => {
+                self.create_entity_reference(n.name(), n.value()).into()
+            }
+            ChildOfElement::Comment(n) => self.create_comment(n.text()).into(),
+            ChildOfElement::ProcessingInstruction(n) => self
+                .create_processing_instruction(n.target(), n.value())
+                .into(),
+        }
+    }
+
+    /// Copies an element into this document. See `import_node`.
+    pub fn import_element(self, element: Element<'_>, deep: bool) -> Element<'d> {
+        self.import_element_in(element, deep, true)
+    }
+
+    /// A top-level copy declares every namespace in scope, while
+    /// nested copies only declare those that differ from their parent.
+    fn import_element_in(self, element: Element<'_>, deep: bool, top_level: bool) -> Element<'d> {
+        let copy = self.create_element(element.name());
+        copy.set_preferred_prefix(element.preferred_prefix());
+
+        let parent = element.parent().and_then(ParentOfChild::element);
+        let inherited = match parent {
+            Some(parent) if !top_level => parent.namespaces_in_scope(),
+            _ => Vec::new(),
+        };
+
+        for ns in element.namespaces_in_scope() {
+            let declared = ns.prefix() == crate::XML_NS_PREFIX
+                || inherited
+                    .iter()
+                    .any(|i| i.prefix() == ns.prefix() && i.uri() == ns.uri());
+            if !declared {
+                copy.register_prefix(ns.prefix(), ns.uri());
+            }
+        }
+
+        if top_level {
+            copy.set_default_namespace_uri(element.recursive_default_namespace_uri());
+        } else {
+            copy.set_default_namespace_uri(element.default_namespace_uri());
+        }
+
+        for attribute in element.attributes() {
+            let attribute_copy = copy.set_attribute_value(attribute.name(), attribute.value());
+            attribute_copy.set_preferred_prefix(attribute.preferred_prefix());
+        }
+
+        if deep {
+            for child in element.children_iter() {
+                let child_copy = match child {
+                    ChildOfElement::Element(n) => self.import_element_in(n, true, false).into(),
+                    other => self.import_node(other, true),
+                };
+                copy.append_child(child_copy);
+            }
+        }
+
+        copy
+    }
+
+    /// The URI the document was retrieved from, used as the base for
+    /// resolving relative URIs
+    pub fn document_uri(self) -> Option<&'d str> {
+        self.root().node().uri()
+    }
+
+    pub fn set_document_uri(self, uri: Option<&str>) {
+        self.storage.root_set_uri(self.connections.root(), uri);
+    }
+
     fn siblings<T>(self, f: SiblingFn<T>, node: T) -> Vec<ChildOfElement<'d>> {
         // This is safe because we don't allow the connection
         // information to leak outside of this method.
@@ -105,8 +201,308 @@ impl<'d> Document<'d> {
                 .collect()
         }
     }
+
+    fn child_count(self, parent: raw::ParentOfChild) -> usize {
+        // This is safe because we don't allow the connection
+        // information to leak outside of this method.
+        unsafe {
+            match parent {
+                raw::ParentOfChild::Root(_) => self.connections.root_children().len(),
+                raw::ParentOfChild::Element(n) => self.connections.element_children(n).len(),
+            }
+        }
+    }
+
+    fn root_child(self, index: usize) -> Option<ChildOfRoot<'d>> {
+        // This is safe because we make a copy of the child, and the
+        // children are never deallocated.
+        unsafe {
+            self.connections
+                .root_children()
+                .get(index)
+                .map(|&n| self.wrap_child_of_root(n))
+        }
+    }
+
+    fn child(self, parent: raw::ParentOfChild, index: usize) -> Option<ChildOfElement<'d>> {
+        match parent {
+            raw::ParentOfChild::Root(_) => self.root_child(index).map(Into::into),
+            // This is safe because we make a copy of the child, and
+            // the children are never deallocated.
+            raw::ParentOfChild::Element(n) => unsafe {
+                self.connections
+                    .element_children(n)
+                    .get(index)
+                    .map(|&n| self.wrap_child_of_element(n))
+            },
+        }
+    }
+
+    fn child_position(
+        self,
+        parent: raw::ParentOfChild,
+        node: raw::ChildOfElement,
+    ) -> Option<usize> {
+        (0..self.child_count(parent))
+            .position(|i| self.child(parent, i).map(|c| c.as_raw()) == Some(node))
+    }
+
+    fn siblings_iter(
+        self,
+        parent: Option<raw::ParentOfChild>,
+        node: raw::ChildOfElement,
+        direction: Direction,
+    ) -> Siblings<'d> {
+        let position = parent.and_then(|parent| {
+            self.child_position(parent, node)
+                .map(|position| (parent, position))
+        });
+
+        let (parent, positions) = match position {
+            Some((parent, position)) => {
+                let positions = match direction {
+                    Direction::Preceding => 0..position,
+                    Direction::Following => position + 1..self.child_count(parent),
+                };
+                (parent, positions)
+            }
+            None => (raw::ParentOfChild::Root(self.connections.root()), 0..0),
+        };
+
+        Siblings {
+            document: self,
+            parent,
+            positions,
+        }
+    }
 }
 
+#[derive(Copy, Clone)]
+enum Direction {
+    Preceding,
+    Following,
+}
+
+/// Adds methods that insert children relative to other children,
+/// built on `insert_child` and `index_of`
+macro_rules! positional_insertion(
+    ($child:ident) => (
+        /// Inserts the child at the start of the children
+        pub fn prepend_child<C>(&self, child: C)
+        where
+            C: Into<$child<'d>>,
+        {
+            self.insert_child(0, child);
+        }
+
+        /// Inserts the child immediately before `reference`.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `reference` is not a child of this node.
+        pub fn insert_before<R, C>(&self, reference: R, child: C)
+        where
+            R: Into<$child<'d>>,
+            C: Into<$child<'d>>,
+        {
+            let (reference, child) = (reference.into(), child.into());
+            let index = self
+                .index_of(reference)
+                .expect("The reference is not a child of this node");
+            if reference != child {
+                self.insert_child(self.index_after_moving(child, index), child);
+            }
+        }
+
+        /// Inserts the child immediately after `reference`.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `reference` is not a child of this node.
+        pub fn insert_after<R, C>(&self, reference: R, child: C)
+        where
+            R: Into<$child<'d>>,
+            C: Into<$child<'d>>,
+        {
+            let (reference, child) = (reference.into(), child.into());
+            let index = self
+                .index_of(reference)
+                .expect("The reference is not a child of this node");
+            if reference != child {
+                self.insert_child(self.index_after_moving(child, index + 1), child);
+            }
+        }
+
+        /// Puts the `new` child in the place of the `old` child, which
+        /// is removed.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `old` is not a child of this node.
+        pub fn replace_child<O, N>(&self, old: O, new: N)
+        where
+            O: Into<$child<'d>>,
+            N: Into<$child<'d>>,
+        {
+            let (old, new) = (old.into(), new.into());
+            let index = self
+                .index_of(old)
+                .expect("The child to replace is not a child of this node");
+            if old != new {
+                let index = self.index_after_moving(new, index);
+                self.remove_child(old);
+                self.insert_child(index, new);
+            }
+        }
+
+        /// The index that refers to the same position once `child`
+        /// has been removed from its current position
+        fn index_after_moving(&self, child: $child<'d>, index: usize) -> usize {
+            match self.index_of(child) {
+                Some(current) if current < index => index - 1,
+                _ => index,
+            }
+        }
+    )
+);
+
+/// Adds methods that walk the tree around a node, delegating to
+/// `Node`
+macro_rules! traversal(
+    () => (
+        /// The nodes below this node, in document order
+        pub fn descendants(&self) -> Traversal<'d> {
+            Node::from(*self).descendants()
+        }
+
+        /// This node followed by the nodes below it, in document
+        /// order
+        pub fn descendants_or_self(&self) -> Traversal<'d> {
+            Node::from(*self).descendants_or_self()
+        }
+
+        /// The parent of this node, its parent, and so on up to the
+        /// root
+        pub fn ancestors(&self) -> Ancestors<'d> {
+            Node::from(*self).ancestors()
+        }
+
+        /// The nodes after this node in document order, excluding
+        /// its descendants
+        pub fn following(&self) -> Traversal<'d> {
+            Node::from(*self).following()
+        }
+
+        /// The nodes before this node in document order, excluding
+        /// its ancestors
+        pub fn preceding(&self) -> Traversal<'d> {
+            Node::from(*self).preceding()
+        }
+    )
+);
+
+/// Adds `base_uri` and `resolve_uri` to a node that takes its base
+/// URI from its parent
+macro_rules! parent_base_uri(
+    () => (
+        /// The base URI of the parent, or the document URI when the
+        /// node has no parent
+        pub fn base_uri(&self) -> Option<String> {
+            match self.parent() {
+                Some(parent) => parent.base_uri(),
+                None => self.document.document_uri().map(String::from),
+            }
+        }
+
+        /// Resolves a URI reference against the base URI
+        pub fn resolve_uri(&self, reference: &str) -> String {
+            resolve_against(self.base_uri(), reference)
+        }
+    )
+);
+
+/// Adds methods that read the text below a `Root` or `Element`
+macro_rules! text_content(
+    () => (
+        /// The text of every text node below this node, concatenated
+        /// in document order. This is the XPath string-value.
+        pub fn string_value(&self) -> String {
+            Node::from(*self).string_value()
+        }
+
+        /// The same as `string_value`, under the name used by the DOM
+        pub fn text_content(&self) -> String {
+            self.string_value()
+        }
+
+        /// The text of the text nodes that are children of this node,
+        /// concatenated in order. Text inside nested elements is
+        /// skipped.
+        pub fn direct_text(&self) -> String {
+            let mut text = String::new();
+            for child in self.children_iter() {
+                match Node::from(child) {
+                    Node::Text(n) => text.push_str(n.text()),
+                    Node::EntityReference(n) => text.push_str(n.value().unwrap_or("")),
+                    _ => {}
+                }
+            }
+            text
+        }
+    )
+);
+
+/// Adds methods that tidy the text nodes below a `Root` or `Element`
+macro_rules! normalization(
+    () => (
+        /// Merges adjacent text nodes below this node and removes
+        /// empty ones, as the DOM `normalize` does
+        pub fn normalize(&self) {
+            normalize_descendants(Node::from(*self), false)
+        }
+
+        /// The same as `normalize`, but also removes text nodes that
+        /// contain only whitespace, except where the effective
+        /// `xml:space` is `preserve`
+        pub fn normalize_and_remove_whitespace(&self) {
+            normalize_descendants(Node::from(*self), true)
+        }
+    )
+);
+
+/// Adds methods that find the siblings of a node without
+/// allocating. `$parent` is the method of `raw::Connections` that
+/// finds the parent of the node.
+macro_rules! sibling_navigation(
+    ($parent:ident) => (
+        /// The sibling nodes that come before this node, in document
+        /// order
+        pub fn preceding_siblings_iter(&self) -> Siblings<'d> {
+            let parent = self.document.connections.$parent(self.node).map(Into::into);
+            self.document
+                .siblings_iter(parent, self.node.into(), Direction::Preceding)
+        }
+
+        /// The sibling nodes that come after this node, in document
+        /// order
+        pub fn following_siblings_iter(&self) -> Siblings<'d> {
+            let parent = self.document.connections.$parent(self.node).map(Into::into);
+            self.document
+                .siblings_iter(parent, self.node.into(), Direction::Following)
+        }
+
+        /// The sibling node immediately before this node
+        pub fn previous_sibling(&self) -> Option<ChildOfElement<'d>> {
+            self.preceding_siblings_iter().next_back()
+        }
+
+        /// The sibling node immediately after this node
+        pub fn next_sibling(&self) -> Option<ChildOfElement<'d>> {
+            self.following_siblings_iter().next()
+        }
+    )
+);
+
 impl<'d> PartialEq for Document<'d> {
     fn eq(&self, other: &Document<'d>) -> bool {
         (
@@ -139,6 +535,10 @@ macro_rules! node(
             fn node(&self) -> &'d $raw { unsafe { &*self.node } }
 
             pub fn document(&self) -> Document<'d> { self.document }
+
+            pub fn id(&self) -> NodeId {
+                NodeId { node: self.node as usize, prefix: 0 }
+            }
         }
 
         impl<'d> PartialEq for $name<'d> {
@@ -193,6 +593,30 @@ impl<'d> Root<'d> {
         self.append_children(children);
     }
 
+    /// Inserts the child so that it has the given index among the
+    /// children, removing it from its previous parent. An index past
+    /// the last child appends the child.
+    pub fn insert_child<C>(&self, index: usize, child: C)
+    where
+        C: Into<ChildOfRoot<'d>>,
+    {
+        let child = child.into();
+        self.document
+            .connections
+            .insert_root_child(index, child.as_raw());
+    }
+
+    /// The position of the child among the children
+    pub fn index_of<C>(&self, child: C) -> Option<usize>
+    where
+        C: Into<ChildOfRoot<'d>>,
+    {
+        let child = child.into();
+        self.document.connections.root_child_index(child.as_raw())
+    }
+
+    positional_insertion!(ChildOfRoot);
+
     pub fn remove_child<C>(&self, child: C)
     where
         C: Into<ChildOfRoot<'d>>,
@@ -217,6 +641,98 @@ impl<'d> Root<'d> {
                 .collect()
         }
     }
+
+    /// The children of the root, without allocating. The children
+    /// are looked up as the iterator advances, so the document may be
+    /// modified while iterating.
+    pub fn children_iter(&self) -> RootChildren<'d> {
+        let parent = raw::ParentOfChild::Root(self.node);
+        RootChildren {
+            document: self.document,
+            positions: 0..self.document.child_count(parent),
+        }
+    }
+
+    pub fn first_child(&self) -> Option<ChildOfRoot<'d>> {
+        self.children_iter().next()
+    }
+
+    pub fn last_child(&self) -> Option<ChildOfRoot<'d>> {
+        self.children_iter().next_back()
+    }
+
+    traversal!();
+    text_content!();
+    normalization!();
+
+    /// The base URI of the root is the document URI
+    pub fn base_uri(&self) -> Option<String> {
+        self.document.document_uri().map(String::from)
+    }
+
+    /// Resolves a URI reference against the base URI
+    pub fn resolve_uri(&self, reference: &str) -> String {
+        resolve_against(self.base_uri(), reference)
+    }
+
+    /// The document type declaration, if the document was parsed
+    /// from text that had one
+    pub fn document_type(&self) -> Option<DocumentType<'d>> {
+        self.node()
+            .document_type()
+            .map(|document_type| DocumentType {
+                name: document_type.name.as_slice(),
+                public_id: document_type.public_id.map(|id| id.as_slice()),
+                system_id: document_type.system_id.map(|id| id.as_slice()),
+                location: document_type.location.map(|l| l.as_slice()),
+            })
+    }
+
+    pub(crate) fn set_document_type(&self, document_type: Option<DocumentType<'_>>) {
+        let storage = self.document.storage;
+        let document_type = document_type.map(|document_type| raw::DocumentType {
+            name: storage.intern(document_type.name),
+            public_id: document_type.public_id.map(|id| storage.intern(id)),
+            system_id: document_type.system_id.map(|id| storage.intern(id)),
+            location: document_type.location.map(|l| storage.intern(l)),
+        });
+        storage.root_set_document_type(self.node, document_type);
+    }
+
+    pub(crate) fn lexical(&self) -> Option<LexicalRoot<'d, 'd>> {
+        self.node().lexical().map(|lexical| LexicalRoot {
+            prolog: lexical.prolog.as_slice(),
+            leading: lexical
+                .leading
+                .iter()
+                .map(|&(child, ref text)| {
+                    (self.document.wrap_child_of_root(child), text.as_slice())
+                })
+                .collect(),
+            trailing: lexical.trailing.as_slice(),
+        })
+    }
+
+    pub(crate) fn set_lexical(&self, lexical: Option<LexicalRoot<'_, 'd>>) {
+        let storage = self.document.storage;
+        let lexical = lexical.map(|lexical| raw::RootLexical {
+            prolog: storage.intern(lexical.prolog),
+            leading: lexical
+                .leading
+                .into_iter()
+                .map(|(child, text)| (child.as_raw(), storage.intern(text)))
+                .collect(),
+            trailing: storage.intern(lexical.trailing),
+        });
+        storage.root_set_lexical(self.node, lexical);
+    }
+}
+
+fn resolve_against(base: Option<String>, reference: &str) -> String {
+    match base {
+        Some(base) => uri::resolve(&base, reference),
+        None => reference.to_owned(),
+    }
 }
 
 impl<'d> fmt::Debug for Root<'d> {
@@ -225,8 +741,20 @@ impl<'d> fmt::Debug for Root<'d> {
     }
 }
 
+/// An identifier for a node that does not borrow its document, so
+/// it can be stored freely. The identifier is stable for as long as
+/// the document exists, but may be reused once it has been dropped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId {
+    node: usize,
+    // Namespaces are identified by their element and prefix
+    prefix: usize,
+}
+
 /// A mapping from a prefix to a URI
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Namespace<'d> {
+    element: Element<'d>,
     prefix: &'d str,
     uri: &'d str,
 }
@@ -238,6 +766,82 @@ impl<'d> Namespace<'d> {
     pub fn uri(&self) -> &'d str {
         self.uri
     }
+    /// The element the mapping is in scope for
+    pub fn parent(&self) -> Element<'d> {
+        self.element
+    }
+    pub fn id(&self) -> NodeId {
+        NodeId {
+            node: self.element.node as usize,
+            prefix: self.prefix.as_ptr() as usize,
+        }
+    }
+}
+
+/// The name and external identifier given by a document type
+/// declaration
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DocumentType<'d> {
+    pub(crate) name: &'d str,
+    pub(crate) public_id: Option<&'d str>,
+    pub(crate) system_id: Option<&'d str>,
+    pub(crate) location: Option<&'d str>,
+}
+
+impl<'d> DocumentType<'d> {
+    /// The name of the root element the document should have
+    pub fn name(&self) -> &'d str {
+        self.name
+    }
+
+    pub fn public_id(&self) -> Option<&'d str> {
+        self.public_id
+    }
+
+    pub fn system_id(&self) -> Option<&'d str> {
+        self.system_id
+    }
+
+    /// Where the external subset can be found. This is the local
+    /// resource a `Catalog` given to the `Parser` maps the external
+    /// identifier to, or otherwise the system identifier.
+    pub fn location(&self) -> Option<&'d str> {
+        self.location.or(self.system_id)
+    }
+}
+
+/// The text surrounding the children of the root, as it appeared in
+/// the parsed document
+pub(crate) struct LexicalRoot<'s, 'd> {
+    pub prolog: &'s str,
+    pub leading: Vec<(ChildOfRoot<'d>, &'s str)>,
+    pub trailing: &'s str,
+}
+
+/// The tags of an element, as they appeared in the parsed document
+pub(crate) struct LexicalElement<'s, 'd> {
+    pub open: &'s str,
+    pub items: Vec<LexicalItem<'s, 'd>>,
+    pub close: &'s str,
+    pub end: Option<&'s str>,
+}
+
+pub(crate) enum LexicalItem<'s, 'd> {
+    Namespace {
+        prefix: Option<&'s str>,
+        uri: Option<&'s str>,
+        text: &'s str,
+    },
+    /// An attribute that is still present on the element. When the
+    /// value has been changed, only the text before `value_start` may
+    /// be reused.
+    Attribute {
+        attribute: Attribute<'d>,
+        prefix: Option<&'s str>,
+        unchanged: bool,
+        text: &'s str,
+        value_start: usize,
+    },
 }
 
 node!(
@@ -275,6 +879,46 @@ impl<'d> Element<'d> {
             .element_default_namespace_uri(self.node)
     }
 
+    /// The language of this element, as given by the closest
+    /// `xml:lang` attribute on this element or its ancestors. An empty
+    /// value means that the language is explicitly unknown.
+    pub fn effective_lang(&self) -> Option<&'d str> {
+        self.document.connections.element_inherited_attribute_value(
+            self.node,
+            (crate::XML_NS_URI, "lang"),
+            |_| true,
+        )
+    }
+
+    /// How whitespace in this element should be handled, as given by
+    /// the closest `xml:space` attribute on this element or its
+    /// ancestors. Values other than `default` and `preserve` are
+    /// ignored.
+    pub fn effective_space(&self) -> Space {
+        let value = self.document.connections.element_inherited_attribute_value(
+            self.node,
+            (crate::XML_NS_URI, "space"),
+            |v| v == "default" || v == "preserve",
+        );
+
+        match value {
+            Some("preserve") => Space::Preserve,
+            _ => Space::Default,
+        }
+    }
+
+    /// Checks the effective language against a language range, as
+    /// the XPath `lang()` function does. The range matches when it
+    /// equals the language or a prefix of it ending before a `-`,
+    /// ignoring case, following the basic filtering of RFC 4647. The
+    /// range `*` matches any language.
+    pub fn lang_matches(&self, range: &str) -> bool {
+        match self.effective_lang() {
+            Some(lang) if !lang.is_empty() => lang_range_matches(lang, range),
+            _ => false,
+        }
+    }
+
     /// Map a prefix to a namespace URI. Any existing prefix on this
     /// element will be replaced.
     pub fn register_prefix(&self, prefix: &str, namespace_uri: &str) {
@@ -311,7 +955,24 @@ impl<'d> Element<'d> {
         self.document
             .connections
             .element_namespaces_in_scope(self.node)
-            .map(|(prefix, uri)| Namespace { prefix, uri })
+            .map(|(prefix, uri)| Namespace {
+                element: *self,
+                prefix,
+                uri,
+            })
+            .collect()
+    }
+
+    /// The namespaces registered on this element itself, without
+    /// those of its ancestors
+    pub fn registered_namespaces(&self) -> Vec<Namespace<'d>> {
+        self.node()
+            .registered_prefixes()
+            .map(|(prefix, uri)| Namespace {
+                element: *self,
+                prefix,
+                uri,
+            })
             .collect()
     }
 
@@ -325,6 +986,37 @@ impl<'d> Element<'d> {
             .element_set_preferred_prefix(self.node, prefix);
     }
 
+    /// Creates a detached copy of this element with the same name,
+    /// preferred prefix, default namespace, registered namespaces and
+    /// attributes, but without any children
+    pub fn shallow_clone(&self) -> Element<'d> {
+        let copy = self.document.create_element(self.name());
+        copy.set_preferred_prefix(self.preferred_prefix());
+        copy.set_default_namespace_uri(self.default_namespace_uri());
+
+        for ns in self.registered_namespaces() {
+            copy.register_prefix(ns.prefix(), ns.uri());
+        }
+
+        for attribute in self.attributes() {
+            let attribute_copy = copy.set_attribute_value(attribute.name(), attribute.value());
+            attribute_copy.set_preferred_prefix(attribute.preferred_prefix());
+        }
+
+        copy
+    }
+
+    /// Creates a detached copy of this element and all of its
+    /// descendants. See `shallow_clone` for what is copied from each
+    /// element.
+    pub fn deep_clone(&self) -> Element<'d> {
+        let copy = self.shallow_clone();
+        for child in self.children_iter() {
+            copy.append_child(child.deep_clone());
+        }
+        copy
+    }
+
     pub fn parent(&self) -> Option<ParentOfChild<'d>> {
         self.document
             .connections
@@ -332,6 +1024,29 @@ impl<'d> Element<'d> {
             .map(|n| self.document.wrap_parent_of_child(n))
     }
 
+    /// The base URI of this element, found by resolving the
+    /// `xml:base` attributes of this element and its ancestors
+    /// against the document URI
+    pub fn base_uri(&self) -> Option<String> {
+        let mut bases = Vec::new();
+        let mut element = Some(*self);
+
+        while let Some(e) = element {
+            bases.extend(e.attribute_value((crate::XML_NS_URI, "base")));
+            element = e.parent().and_then(ParentOfChild::element);
+        }
+
+        let document_uri = self.document.document_uri().map(String::from);
+        bases.iter().rev().fold(document_uri, |base, reference| {
+            Some(resolve_against(base, reference))
+        })
+    }
+
+    /// Resolves a URI reference against the base URI
+    pub fn resolve_uri(&self, reference: &str) -> String {
+        resolve_against(self.base_uri(), reference)
+    }
+
     pub fn remove_from_parent(&self) {
         self.document
             .connections
@@ -367,6 +1082,32 @@ impl<'d> Element<'d> {
         self.append_children(children);
     }
 
+    /// Inserts the child so that it has the given index among the
+    /// children, removing it from its previous parent. An index past
+    /// the last child appends the child.
+    pub fn insert_child<C>(&self, index: usize, child: C)
+    where
+        C: Into<ChildOfElement<'d>>,
+    {
+        let child = child.into();
+        self.document
+            .connections
+            .insert_element_child(self.node, index, child.as_raw());
+    }
+
+    /// The position of the child among the children
+    pub fn index_of<C>(&self, child: C) -> Option<usize>
+    where
+        C: Into<ChildOfElement<'d>>,
+    {
+        let child = child.into();
+        self.document
+            .connections
+            .element_child_index(self.node, child.as_raw())
+    }
+
+    positional_insertion!(ChildOfElement);
+
     pub fn remove_child<C>(&self, child: C)
     where
         C: Into<ChildOfElement<'d>>,
@@ -394,6 +1135,30 @@ impl<'d> Element<'d> {
         }
     }
 
+    /// The children of the element, without allocating. The children
+    /// are looked up as the iterator advances, so the document may be
+    /// modified while iterating.
+    pub fn children_iter(&self) -> ElementChildren<'d> {
+        let parent = raw::ParentOfChild::Element(self.node);
+        ElementChildren {
+            document: self.document,
+            parent,
+            positions: 0..self.document.child_count(parent),
+        }
+    }
+
+    pub fn first_child(&self) -> Option<ChildOfElement<'d>> {
+        self.children_iter().next()
+    }
+
+    pub fn last_child(&self) -> Option<ChildOfElement<'d>> {
+        self.children_iter().next_back()
+    }
+
+    traversal!();
+    text_content!();
+    normalization!();
+
     pub fn preceding_siblings(&self) -> Vec<ChildOfElement<'d>> {
         self.document
             .siblings(raw::Connections::element_preceding_siblings, self.node)
@@ -404,6 +1169,8 @@ impl<'d> Element<'d> {
             .siblings(raw::Connections::element_following_siblings, self.node)
     }
 
+    sibling_navigation!(element_parent);
+
     pub fn attribute<'n, N>(&self, name: N) -> Option<Attribute<'d>>
     where
         N: Into<QName<'n>>,
@@ -414,6 +1181,86 @@ impl<'d> Element<'d> {
             .map(|n| self.document.wrap_attribute(n))
     }
 
+    pub(crate) fn lexical(&self) -> Option<LexicalElement<'d, 'd>> {
+        let lexical = self.node().lexical()?;
+        let attributes = self.attributes();
+
+        let items = lexical
+            .items
+            .iter()
+            .filter_map(|item| match *item {
+                raw::LexicalItem::Namespace {
+                    prefix,
+                    uri,
+                    ref text,
+                } => Some(LexicalItem::Namespace {
+                    prefix: prefix.map(|p| p.as_slice()),
+                    uri: uri.map(|u| u.as_slice()),
+                    text: text.as_slice(),
+                }),
+                raw::LexicalItem::Attribute {
+                    attribute,
+                    ref text,
+                    value_start,
+                } => {
+                    // Attributes are replaced when their value is
+                    // set, so the original may no longer be present
+                    let original: &'d raw::Attribute = unsafe { &*attribute };
+                    let unchanged = attributes.iter().any(|a| a.node == attribute);
+                    let current = attributes
+                        .iter()
+                        .find(|a| a.node == attribute || a.name() == original.name());
+
+                    current.map(|&current| LexicalItem::Attribute {
+                        attribute: current,
+                        prefix: original.preferred_prefix(),
+                        unchanged,
+                        text: text.as_slice(),
+                        value_start,
+                    })
+                }
+            })
+            .collect();
+
+        Some(LexicalElement {
+            open: lexical.open.as_slice(),
+            items,
+            close: lexical.close.as_slice(),
+            end: lexical.end.map(|e| e.as_slice()),
+        })
+    }
+
+    pub(crate) fn set_lexical(&self, lexical: Option<LexicalElement<'_, 'd>>) {
+        let storage = self.document.storage;
+        let lexical = lexical.map(|lexical| raw::ElementLexical {
+            open: storage.intern(lexical.open),
+            items: lexical
+                .items
+                .into_iter()
+                .map(|item| match item {
+                    LexicalItem::Namespace { prefix, uri, text } => raw::LexicalItem::Namespace {
+                        prefix: prefix.map(|p| storage.intern(p)),
+                        uri: uri.map(|u| storage.intern(u)),
+                        text: storage.intern(text),
+                    },
+                    LexicalItem::Attribute {
+                        attribute,
+                        text,
+                        value_start,
+                        ..
+                    } => raw::LexicalItem::Attribute {
+                        attribute: attribute.node,
+                        text: storage.intern(text),
+                        value_start,
+                    },
+                })
+                .collect(),
+            close: storage.intern(lexical.close),
+            end: lexical.end.map(|e| storage.intern(e)),
+        });
+        storage.element_set_lexical(self.node, lexical);
+    }
+
     pub fn attributes(&self) -> Vec<Attribute<'d>> {
         // This is safe because we make a copy of the children, and
         // the children are never deallocated.
@@ -462,6 +1309,11 @@ impl<'d> Element<'d> {
         self.append_child(text);
         text
     }
+
+    /// The same as `set_text`, under the name used by the DOM
+    pub fn set_text_content(&self, text: &str) -> Text<'_> {
+        self.set_text(text)
+    }
 }
 
 impl<'d> fmt::Debug for Element<'d> {
@@ -470,11 +1322,75 @@ impl<'d> fmt::Debug for Element<'d> {
     }
 }
 
-node!(
-    Attribute,
-    raw::Attribute,
-    "Metadata about the current element"
-);
+/// The whitespace handling requested by `xml:space`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Space {
+    /// The application's default handling applies
+    Default,
+    /// All whitespace should be preserved
+    Preserve,
+}
+
+fn normalize_descendants(node: Node<'_>, remove_whitespace: bool) {
+    let elements: Vec<_> = node
+        .descendants_or_self()
+        .filter_map(Node::element)
+        .collect();
+    for element in elements {
+        let remove_whitespace = remove_whitespace && element.effective_space() == Space::Default;
+
+        let mut runs: Vec<Vec<Text<'_>>> = Vec::new();
+        let mut in_run = false;
+        for child in element.children() {
+            match child {
+                ChildOfElement::Text(text) => {
+                    match runs.last_mut() {
+                        Some(run) if in_run => run.push(text),
+                        _ => runs.push(vec![text]),
+                    }
+                    in_run = true;
+                }
+                _ => in_run = false,
+            }
+        }
+
+        // Each run is combined into its first node with a single
+        // string, so a long run is not copied once per node
+        for run in runs {
+            let first = run[0];
+            if run.len() > 1 {
+                let merged: String = run.iter().map(|text| text.text()).collect();
+                first.set_text(&merged);
+                for text in &run[1..] {
+                    text.remove_from_parent();
+                }
+            }
+
+            let merged = first.text();
+            if merged.is_empty() || remove_whitespace && merged.chars().all(|c| c.is_space_char()) {
+                first.remove_from_parent();
+            }
+        }
+    }
+}
+
+fn lang_range_matches(lang: &str, range: &str) -> bool {
+    if range == "*" {
+        return true;
+    }
+
+    let (lang, range) = (lang.as_bytes(), range.as_bytes());
+
+    lang.len() >= range.len()
+        && lang[..range.len()].eq_ignore_ascii_case(range)
+        && (lang.len() == range.len() || lang[range.len()] == b'-')
+}
+
+node!(
+    Attribute,
+    raw::Attribute,
+    "Metadata about the current element"
+);
 
 impl<'d> Attribute<'d> {
     pub fn name(&self) -> QName<'d> {
@@ -501,6 +1417,8 @@ impl<'d> Attribute<'d> {
             .map(|n| self.document.wrap_element(n))
     }
 
+    parent_base_uri!();
+
     pub fn remove_from_parent(&self) {
         self.document
             .connections
@@ -522,6 +1440,11 @@ impl<'d> fmt::Debug for Attribute<'d> {
 node!(Text, raw::Text, "Textual data");
 
 impl<'d> Text<'d> {
+    /// Creates a detached copy of this node
+    pub fn deep_clone(&self) -> Text<'d> {
+        self.document.create_text(self.text())
+    }
+
     pub fn text(&self) -> &'d str {
         self.node().text()
     }
@@ -537,6 +1460,8 @@ impl<'d> Text<'d> {
             .map(|n| self.document.wrap_element(n))
     }
 
+    parent_base_uri!();
+
     pub fn remove_from_parent(&self) {
         self.document.connections.remove_text_from_parent(self.node);
     }
@@ -550,6 +1475,16 @@ impl<'d> Text<'d> {
         self.document
             .siblings(raw::Connections::text_following_siblings, self.node)
     }
+
+    sibling_navigation!(text_parent);
+
+    pub(crate) fn lexical(&self) -> Option<&'d str> {
+        self.node().lexical()
+    }
+
+    pub(crate) fn set_lexical(&self, lexical: Option<&str>) {
+        self.document.storage.text_set_lexical(self.node, lexical)
+    }
 }
 
 impl<'d> fmt::Debug for Text<'d> {
@@ -558,9 +1493,77 @@ impl<'d> fmt::Debug for Text<'d> {
     }
 }
 
+node!(
+    EntityReference,
+    raw::EntityReference,
+    "A reference to a general entity that was not expanded"
+);
+
+impl<'d> EntityReference<'d> {
+    /// Creates a detached copy of this node
+    pub fn deep_clone(&self) -> EntityReference<'d> {
+        self.document
+            .create_entity_reference(self.name(), self.value())
+    }
+
+    pub fn name(&self) -> &'d str {
+        self.node().name()
+    }
+
+    /// The replacement text of the entity, if it was declared
+    pub fn value(&self) -> Option<&'d str> {
+        self.node().value()
+    }
+
+    pub fn parent(&self) -> Option<Element<'d>> {
+        self.document
+            .connections
+            .entity_reference_parent(self.node)
+            .map(|n| self.document.wrap_element(n))
+    }
+
+    pub fn remove_from_parent(&self) {
+        self.document
+            .connections
+            .remove_entity_reference_from_parent(self.node);
+    }
+
+    pub fn preceding_siblings(&self) -> Vec<ChildOfElement<'d>> {
+        self.document.siblings(
+            raw::Connections::entity_reference_preceding_siblings,
+            self.node,
+        )
+    }
+
+    pub fn following_siblings(&self) -> Vec<ChildOfElement<'d>> {
+        self.document.siblings(
+            raw::Connections::entity_reference_following_siblings,
+            self.node,
+        )
+    }
+
+    sibling_navigation!(entity_reference_parent);
+}
+
+impl<'d> fmt::Debug for EntityReference<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EntityReference {{ name: {:?}, value: {:?} }}",
+            self.name(),
+            self.value()
+        )
+    }
+}
+
 node!(Comment, raw::Comment, "Information only relevant to humans");
 
 impl<'d> Comment<'d> {
+    /// Creates a detached copy of this node
+    pub fn deep_clone(&self) -> Comment<'d> {
+        self.document.create_comment(self.text())
+    }
+
     pub fn text(&self) -> &'d str {
         self.node().text()
     }
@@ -576,6 +1579,8 @@ impl<'d> Comment<'d> {
             .map(|n| self.document.wrap_parent_of_child(n))
     }
 
+    parent_base_uri!();
+
     pub fn remove_from_parent(&self) {
         self.document
             .connections
@@ -591,6 +1596,18 @@ impl<'d> Comment<'d> {
         self.document
             .siblings(raw::Connections::comment_following_siblings, self.node)
     }
+
+    sibling_navigation!(comment_parent);
+
+    pub(crate) fn lexical(&self) -> Option<&'d str> {
+        self.node().lexical()
+    }
+
+    pub(crate) fn set_lexical(&self, lexical: Option<&str>) {
+        self.document
+            .storage
+            .comment_set_lexical(self.node, lexical)
+    }
 }
 
 impl<'d> fmt::Debug for Comment<'d> {
@@ -606,6 +1623,12 @@ node!(
 );
 
 impl<'d> ProcessingInstruction<'d> {
+    /// Creates a detached copy of this node
+    pub fn deep_clone(&self) -> ProcessingInstruction<'d> {
+        self.document
+            .create_processing_instruction(self.target(), self.value())
+    }
+
     pub fn target(&self) -> &'d str {
         self.node().target()
     }
@@ -632,6 +1655,8 @@ impl<'d> ProcessingInstruction<'d> {
             .map(|n| self.document.wrap_parent_of_child(n))
     }
 
+    parent_base_uri!();
+
     pub fn remove_from_parent(&self) {
         self.document
             .connections
@@ -651,6 +1676,18 @@ impl<'d> ProcessingInstruction<'d> {
             self.node,
         )
     }
+
+    sibling_navigation!(processing_instruction_parent);
+
+    pub(crate) fn lexical(&self) -> Option<&'d str> {
+        self.node().lexical()
+    }
+
+    pub(crate) fn set_lexical(&self, lexical: Option<&str>) {
+        self.document
+            .storage
+            .processing_instruction_set_lexical(self.node, lexical)
+    }
 }
 
 impl<'d> fmt::Debug for ProcessingInstruction<'d> {
@@ -693,6 +1730,16 @@ impl<'d> ChildOfRoot<'d> {
         ProcessingInstruction
     );
 
+    /// Creates a detached copy of this node and all of its
+    /// descendants
+    pub fn deep_clone(&self) -> ChildOfRoot<'d> {
+        match *self {
+            ChildOfRoot::Element(n) => n.deep_clone().into(),
+            ChildOfRoot::Comment(n) => n.deep_clone().into(),
+            ChildOfRoot::ProcessingInstruction(n) => n.deep_clone().into(),
+        }
+    }
+
     fn as_raw(&self) -> raw::ChildOfRoot {
         match *self {
             ChildOfRoot::Element(n) => raw::ChildOfRoot::Element(n.node),
@@ -709,6 +1756,7 @@ impl<'d> ChildOfRoot<'d> {
 pub enum ChildOfElement<'d> {
     Element(Element<'d>),
     Text(Text<'d>),
+    EntityReference(EntityReference<'d>),
     Comment(Comment<'d>),
     ProcessingInstruction(ProcessingInstruction<'d>),
 }
@@ -716,6 +1764,12 @@ pub enum ChildOfElement<'d> {
 impl<'d> ChildOfElement<'d> {
     unpack!(ChildOfElement, element, Element, Element);
     unpack!(ChildOfElement, text, Text, Text);
+    unpack!(
+        ChildOfElement,
+        entity_reference,
+        EntityReference,
+        EntityReference
+    );
     unpack!(ChildOfElement, comment, Comment, Comment);
     unpack!(
         ChildOfElement,
@@ -724,10 +1778,23 @@ impl<'d> ChildOfElement<'d> {
         ProcessingInstruction
     );
 
+    /// Creates a detached copy of this node and all of its
+    /// descendants
+    pub fn deep_clone(&self) -> ChildOfElement<'d> {
+        match *self {
+            ChildOfElement::Element(n) => n.deep_clone().into(),
+            ChildOfElement::Text(n) => n.deep_clone().into(),
+            ChildOfElement::EntityReference(n) => n.deep_clone().into(),
+            ChildOfElement::Comment(n) => n.deep_clone().into(),
+            ChildOfElement::ProcessingInstruction(n) => n.deep_clone().into(),
+        }
+    }
+
     fn as_raw(&self) -> raw::ChildOfElement {
         match *self {
             ChildOfElement::Element(n) => raw::ChildOfElement::Element(n.node),
             ChildOfElement::Text(n) => raw::ChildOfElement::Text(n.node),
+            ChildOfElement::EntityReference(n) => raw::ChildOfElement::EntityReference(n.node),
             ChildOfElement::Comment(n) => raw::ChildOfElement::Comment(n.node),
             ChildOfElement::ProcessingInstruction(n) => {
                 raw::ChildOfElement::ProcessingInstruction(n.node)
@@ -746,6 +1813,377 @@ pub enum ParentOfChild<'d> {
 impl<'d> ParentOfChild<'d> {
     unpack!(ParentOfChild, root, Root, Root);
     unpack!(ParentOfChild, element, Element, Element);
+
+    fn base_uri(self) -> Option<String> {
+        match self {
+            ParentOfChild::Root(n) => n.base_uri(),
+            ParentOfChild::Element(n) => n.base_uri(),
+        }
+    }
+}
+
+/// The kinds of `Node`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NodeType {
+    Root,
+    Element,
+    Attribute,
+    Text,
+    EntityReference,
+    Comment,
+    ProcessingInstruction,
+    Namespace,
+}
+
+/// Any node of a document
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Node<'d> {
+    Root(Root<'d>),
+    Element(Element<'d>),
+    Attribute(Attribute<'d>),
+    Text(Text<'d>),
+    EntityReference(EntityReference<'d>),
+    Comment(Comment<'d>),
+    ProcessingInstruction(ProcessingInstruction<'d>),
+    Namespace(Namespace<'d>),
+}
+
+impl<'d> Node<'d> {
+    unpack!(Node, root, Root, Root);
+    unpack!(Node, element, Element, Element);
+    unpack!(Node, attribute, Attribute, Attribute);
+    unpack!(Node, text, Text, Text);
+    unpack!(Node, entity_reference, EntityReference, EntityReference);
+    unpack!(Node, comment, Comment, Comment);
+    unpack!(
+        Node,
+        processing_instruction,
+        ProcessingInstruction,
+        ProcessingInstruction
+    );
+    unpack!(Node, namespace, Namespace, Namespace);
+
+    pub fn document(self) -> Document<'d> {
+        match self {
+            Node::Root(n) => n.document,
+            Node::Element(n) => n.document,
+            Node::Attribute(n) => n.document,
+            Node::Text(n) => n.document,
+            Node::EntityReference(n) => n.document,
+            Node::Comment(n) => n.document,
+            Node::ProcessingInstruction(n) => n.document,
+            Node::Namespace(n) => n.element.document,
+        }
+    }
+
+    pub fn id(self) -> NodeId {
+        match self {
+            Node::Root(n) => n.id(),
+            Node::Element(n) => n.id(),
+            Node::Attribute(n) => n.id(),
+            Node::Text(n) => n.id(),
+            Node::EntityReference(n) => n.id(),
+            Node::Comment(n) => n.id(),
+            Node::ProcessingInstruction(n) => n.id(),
+            Node::Namespace(n) => n.id(),
+        }
+    }
+
+    /// Compares the position of two nodes in document order. The
+    /// namespaces of an element come before its attributes, which
+    /// come before its children. Nodes that are not part of the same
+    /// tree are given an arbitrary, but consistent, order.
+    ///
+    /// The cost is proportional to the depth of the nodes plus the
+    /// number of siblings of the two ancestors that differ. No
+    /// positions are cached, so sorting many nodes repeats this work
+    /// for each comparison.
+    pub fn document_order_cmp(self, other: Node<'d>) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+
+        let path = self.path_from_top();
+        let other_path = other.path_from_top();
+
+        if path[0] != other_path[0] {
+            return path[0].id().cmp(&other_path[0].id());
+        }
+
+        let common = path
+            .iter()
+            .zip(&other_path)
+            .take_while(|&(a, b)| a == b)
+            .count();
+
+        match (path.get(common), other_path.get(common)) {
+            (Some(&a), Some(&b)) => a.sibling_order_cmp(b),
+            // One node is an ancestor of the other
+            (None, _) => Ordering::Less,
+            (_, None) => Ordering::Greater,
+        }
+    }
+
+    /// The topmost ancestor of the node, down to the node itself
+    fn path_from_top(self) -> Vec<Node<'d>> {
+        let mut path = vec![self];
+        let mut node = self;
+
+        while let Some(parent) = node.parent() {
+            path.push(parent);
+            node = parent;
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Compares two different nodes that have the same parent
+    fn sibling_order_cmp(self, other: Node<'d>) -> Ordering {
+        fn rank(node: Node<'_>) -> u8 {
+            match node {
+                Node::Namespace(_) => 0,
+                Node::Attribute(_) => 1,
+                _ => 2,
+            }
+        }
+
+        rank(self).cmp(&rank(other)).then_with(|| {
+            let self_first = match (self, other) {
+                (Node::Namespace(a), Node::Namespace(b)) => {
+                    let namespaces = a.parent().namespaces_in_scope();
+                    let found = namespaces
+                        .iter()
+                        .find(|ns| ns.prefix == a.prefix || ns.prefix == b.prefix);
+                    found.map(|ns| ns.prefix) == Some(a.prefix)
+                }
+                (Node::Attribute(a), Node::Attribute(b)) => {
+                    let attributes = a.parent().map(|e| e.attributes()).unwrap_or_default();
+                    attributes.into_iter().find(|&n| n == a || n == b) == Some(a)
+                }
+                _ => match (self.raw_position(), other.raw_position()) {
+                    (Some((parent, a)), Some((_, b))) => {
+                        let document = self.document();
+                        let found = (0..document.child_count(parent))
+                            .filter_map(|i| document.child(parent, i))
+                            .map(|c| c.as_raw())
+                            .find(|&c| c == a || c == b);
+                        found == Some(a)
+                    }
+                    _ => return Ordering::Equal,
+                },
+            };
+
+            if self_first {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        })
+    }
+
+    pub fn node_type(self) -> NodeType {
+        match self {
+            Node::Root(_) => NodeType::Root,
+            Node::Element(_) => NodeType::Element,
+            Node::Attribute(_) => NodeType::Attribute,
+            Node::Text(_) => NodeType::Text,
+            Node::EntityReference(_) => NodeType::EntityReference,
+            Node::Comment(_) => NodeType::Comment,
+            Node::ProcessingInstruction(_) => NodeType::ProcessingInstruction,
+            Node::Namespace(_) => NodeType::Namespace,
+        }
+    }
+
+    /// The parent of the node. The parent of an attribute or a
+    /// namespace is the element it belongs to.
+    pub fn parent(self) -> Option<Node<'d>> {
+        match self {
+            Node::Root(_) => None,
+            Node::Element(n) => n.parent().map(Into::into),
+            Node::Attribute(n) => n.parent().map(Into::into),
+            Node::Text(n) => n.parent().map(Into::into),
+            Node::EntityReference(n) => n.parent().map(Into::into),
+            Node::Comment(n) => n.parent().map(Into::into),
+            Node::ProcessingInstruction(n) => n.parent().map(Into::into),
+            Node::Namespace(n) => Some(n.parent().into()),
+        }
+    }
+
+    /// The children of the root or of an element. Other nodes have no
+    /// children.
+    pub fn children(self) -> Vec<Node<'d>> {
+        match self {
+            Node::Root(n) => n.children_iter().map(Into::into).collect(),
+            Node::Element(n) => n.children_iter().map(Into::into).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The name of an element, attribute, processing instruction or
+    /// namespace, as defined by [XPath 1.0, section
+    /// 5](https://www.w3.org/TR/xpath/#data-model). The name of a
+    /// namespace is its prefix.
+    pub fn expanded_name(self) -> Option<QName<'d>> {
+        match self {
+            Node::Element(n) => Some(n.name()),
+            Node::Attribute(n) => Some(n.name()),
+            Node::ProcessingInstruction(n) => Some(n.target().into()),
+            Node::Namespace(n) => Some(n.prefix().into()),
+            _ => None,
+        }
+    }
+
+    /// The text of the node, as defined by [XPath 1.0, section
+    /// 5](https://www.w3.org/TR/xpath/#data-model). For the root and
+    /// elements, this is the text of all descendants.
+    pub fn string_value(self) -> String {
+        match self {
+            Node::Root(_) | Node::Element(_) => {
+                let mut value = String::new();
+                for node in self.descendants() {
+                    match node {
+                        Node::Text(n) => value.push_str(n.text()),
+                        Node::EntityReference(n) => value.push_str(n.value().unwrap_or("")),
+                        _ => {}
+                    }
+                }
+                value
+            }
+            Node::Attribute(n) => n.value().to_owned(),
+            Node::Text(n) => n.text().to_owned(),
+            Node::EntityReference(n) => n.value().unwrap_or("").to_owned(),
+            Node::Comment(n) => n.text().to_owned(),
+            Node::ProcessingInstruction(n) => n.value().unwrap_or("").to_owned(),
+            Node::Namespace(n) => n.uri().to_owned(),
+        }
+    }
+
+    /// The parent of the node and the node as one of its children
+    fn raw_position(self) -> Option<(raw::ParentOfChild, raw::ChildOfElement)> {
+        let connections = self.document().connections;
+        match self {
+            Node::Root(_) | Node::Attribute(_) | Node::Namespace(_) => None,
+            Node::Element(n) => connections
+                .element_parent(n.node)
+                .map(|p| (p, n.node.into())),
+            Node::Text(n) => connections
+                .text_parent(n.node)
+                .map(|p| (p.into(), n.node.into())),
+            Node::EntityReference(n) => connections
+                .entity_reference_parent(n.node)
+                .map(|p| (p.into(), n.node.into())),
+            Node::Comment(n) => connections
+                .comment_parent(n.node)
+                .map(|p| (p, n.node.into())),
+            Node::ProcessingInstruction(n) => connections
+                .processing_instruction_parent(n.node)
+                .map(|p| (p, n.node.into())),
+        }
+    }
+
+    fn raw_children(self) -> Option<(raw::ParentOfChild, Range<usize>)> {
+        let parent = match self {
+            Node::Root(n) => raw::ParentOfChild::Root(n.node),
+            Node::Element(n) => raw::ParentOfChild::Element(n.node),
+            _ => return None,
+        };
+        Some((parent, 0..self.document().child_count(parent)))
+    }
+
+    /// The nodes below this node, in document order
+    pub fn descendants(self) -> Traversal<'d> {
+        Traversal {
+            document: self.document(),
+            start: None,
+            stack: self.raw_children().into_iter().collect(),
+        }
+    }
+
+    /// This node followed by the nodes below it, in document order
+    pub fn descendants_or_self(self) -> Traversal<'d> {
+        Traversal {
+            start: Some(self),
+            ..self.descendants()
+        }
+    }
+
+    /// The parent of this node, its parent, and so on up to the root
+    pub fn ancestors(self) -> Ancestors<'d> {
+        let next = match self.parent() {
+            Some(Node::Root(n)) => Some(raw::ParentOfChild::Root(n.node)),
+            Some(Node::Element(n)) => Some(raw::ParentOfChild::Element(n.node)),
+            _ => None,
+        };
+
+        Ancestors {
+            document: self.document(),
+            next,
+        }
+    }
+
+    /// The siblings in the given direction of this node and of each
+    /// of its ancestors, starting with those of this node
+    fn sibling_levels(self, direction: Direction) -> Vec<(raw::ParentOfChild, Range<usize>)> {
+        let document = self.document();
+        let mut levels = Vec::new();
+        let mut node = self;
+
+        while let Some((parent, child)) = node.raw_position() {
+            let siblings = document.siblings_iter(Some(parent), child, direction);
+            levels.push((siblings.parent, siblings.positions));
+            node = document.wrap_parent_of_child(parent).into();
+        }
+
+        levels
+    }
+
+    /// The nodes after this node in document order, excluding its
+    /// descendants. The nodes after an attribute or a namespace
+    /// include the descendants of its element.
+    pub fn following(self) -> Traversal<'d> {
+        if let Some(element) = self.owner_element() {
+            let mut following = Node::Element(element).following();
+            following
+                .stack
+                .extend(Node::Element(element).raw_children());
+            return following;
+        }
+
+        let mut stack = self.sibling_levels(Direction::Following);
+        // The siblings of this node are visited first
+        stack.reverse();
+
+        Traversal {
+            document: self.document(),
+            start: None,
+            stack,
+        }
+    }
+
+    /// The element an attribute or a namespace belongs to
+    fn owner_element(self) -> Option<Element<'d>> {
+        match self {
+            Node::Attribute(n) => n.parent(),
+            Node::Namespace(n) => Some(n.parent()),
+            _ => None,
+        }
+    }
+
+    /// The nodes before this node in document order, excluding its
+    /// ancestors
+    pub fn preceding(self) -> Traversal<'d> {
+        if let Some(element) = self.owner_element() {
+            return Node::Element(element).preceding();
+        }
+
+        // The siblings of the outermost ancestor are visited first
+        Traversal {
+            document: self.document(),
+            start: None,
+            stack: self.sibling_levels(Direction::Preceding),
+        }
+    }
 }
 
 macro_rules! conversion_trait(
@@ -778,11 +2216,56 @@ conversion_trait!(
     ChildOfElement, {
         Element               => ChildOfElement::Element,
         Text                  => ChildOfElement::Text,
+        EntityReference       => ChildOfElement::EntityReference,
         Comment               => ChildOfElement::Comment,
         ProcessingInstruction => ChildOfElement::ProcessingInstruction
     }
 );
 
+conversion_trait!(
+    Node, {
+        Root                  => Node::Root,
+        Element               => Node::Element,
+        Attribute             => Node::Attribute,
+        Text                  => Node::Text,
+        EntityReference       => Node::EntityReference,
+        Comment               => Node::Comment,
+        ProcessingInstruction => Node::ProcessingInstruction,
+        Namespace             => Node::Namespace
+    }
+);
+
+impl<'d> From<ChildOfRoot<'d>> for Node<'d> {
+    fn from(v: ChildOfRoot<'d>) -> Node<'d> {
+        match v {
+            ChildOfRoot::Element(n) => Node::Element(n),
+            ChildOfRoot::Comment(n) => Node::Comment(n),
+            ChildOfRoot::ProcessingInstruction(n) => Node::ProcessingInstruction(n),
+        }
+    }
+}
+
+impl<'d> From<ChildOfElement<'d>> for Node<'d> {
+    fn from(v: ChildOfElement<'d>) -> Node<'d> {
+        match v {
+            ChildOfElement::Element(n) => Node::Element(n),
+            ChildOfElement::Text(n) => Node::Text(n),
+            ChildOfElement::EntityReference(n) => Node::EntityReference(n),
+            ChildOfElement::Comment(n) => Node::Comment(n),
+            ChildOfElement::ProcessingInstruction(n) => Node::ProcessingInstruction(n),
+        }
+    }
+}
+
+impl<'d> From<ParentOfChild<'d>> for Node<'d> {
+    fn from(v: ParentOfChild<'d>) -> Node<'d> {
+        match v {
+            ParentOfChild::Root(n) => Node::Root(n),
+            ParentOfChild::Element(n) => Node::Element(n),
+        }
+    }
+}
+
 impl<'d> From<ChildOfRoot<'d>> for ChildOfElement<'d> {
     fn from(v: ChildOfRoot<'d>) -> ChildOfElement<'d> {
         match v {
@@ -793,38 +2276,182 @@ impl<'d> From<ChildOfRoot<'d>> for ChildOfElement<'d> {
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::{
-        super::{Package, QName},
-        ChildOfElement, ChildOfRoot, ParentOfChild,
-    };
-
-    macro_rules! assert_qname_eq(
-        ($l:expr, $r:expr) => (assert_eq!(Into::<QName<'_>>::into($l), $r.into()));
-    );
+/// The children of a `Root`. See `Root::children_iter`.
+#[derive(Clone)]
+pub struct RootChildren<'d> {
+    document: Document<'d>,
+    positions: Range<usize>,
+}
 
-    #[test]
-    fn the_root_belongs_to_a_document() {
-        let package = Package::new();
-        let doc = package.as_document();
+impl<'d> Iterator for RootChildren<'d> {
+    type Item = ChildOfRoot<'d>;
 
-        let root = doc.root();
+    fn next(&mut self) -> Option<ChildOfRoot<'d>> {
+        let document = self.document;
+        self.positions.next().and_then(|i| document.root_child(i))
+    }
+}
 
-        assert_eq!(doc, root.document());
+impl<'d> DoubleEndedIterator for RootChildren<'d> {
+    fn next_back(&mut self) -> Option<ChildOfRoot<'d>> {
+        let document = self.document;
+        self.positions
+            .next_back()
+            .and_then(|i| document.root_child(i))
     }
+}
 
-    #[test]
-    fn root_can_have_element_children() {
-        let package = Package::new();
-        let doc = package.as_document();
+/// The children of an `Element`. See `Element::children_iter`.
+#[derive(Clone)]
+pub struct ElementChildren<'d> {
+    document: Document<'d>,
+    parent: raw::ParentOfChild,
+    positions: Range<usize>,
+}
 
-        let root = doc.root();
-        let element = doc.create_element("alpha");
+impl<'d> Iterator for ElementChildren<'d> {
+    type Item = ChildOfElement<'d>;
 
-        root.append_child(element);
+    fn next(&mut self) -> Option<ChildOfElement<'d>> {
+        let (document, parent) = (self.document, self.parent);
+        self.positions
+            .next()
+            .and_then(|i| document.child(parent, i))
+    }
+}
 
-        let children = root.children();
+impl<'d> DoubleEndedIterator for ElementChildren<'d> {
+    fn next_back(&mut self) -> Option<ChildOfElement<'d>> {
+        let (document, parent) = (self.document, self.parent);
+        self.positions
+            .next_back()
+            .and_then(|i| document.child(parent, i))
+    }
+}
+
+/// The siblings of a node, in document order. Like
+/// `ElementChildren`, the siblings are looked up as the iterator
+/// advances.
+#[derive(Clone)]
+pub struct Siblings<'d> {
+    document: Document<'d>,
+    parent: raw::ParentOfChild,
+    positions: Range<usize>,
+}
+
+impl<'d> Iterator for Siblings<'d> {
+    type Item = ChildOfElement<'d>;
+
+    fn next(&mut self) -> Option<ChildOfElement<'d>> {
+        let (document, parent) = (self.document, self.parent);
+        self.positions
+            .next()
+            .and_then(|i| document.child(parent, i))
+    }
+}
+
+impl<'d> DoubleEndedIterator for Siblings<'d> {
+    fn next_back(&mut self) -> Option<ChildOfElement<'d>> {
+        let (document, parent) = (self.document, self.parent);
+        self.positions
+            .next_back()
+            .and_then(|i| document.child(parent, i))
+    }
+}
+
+/// Nodes visited in document order, descending into the children of
+/// each node. Only the path to the current node is stored, so no
+/// allocation is made per node. See `Node::descendants`.
+#[derive(Clone)]
+pub struct Traversal<'d> {
+    document: Document<'d>,
+    start: Option<Node<'d>>,
+    // The unvisited children of each level, innermost last
+    stack: Vec<(raw::ParentOfChild, Range<usize>)>,
+}
+
+impl<'d> Iterator for Traversal<'d> {
+    type Item = Node<'d>;
+
+    fn next(&mut self) -> Option<Node<'d>> {
+        if let Some(start) = self.start.take() {
+            return Some(start);
+        }
+
+        loop {
+            let child = {
+                let &mut (parent, ref mut positions) = self.stack.last_mut()?;
+                positions.next().map(|i| self.document.child(parent, i))
+            };
+
+            match child {
+                Some(Some(child)) => {
+                    let child = Node::from(child);
+                    self.stack.extend(child.raw_children());
+                    return Some(child);
+                }
+                // The child was removed while iterating
+                Some(None) => continue,
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// The ancestors of a node, nearest first. See `Node::ancestors`.
+#[derive(Clone)]
+pub struct Ancestors<'d> {
+    document: Document<'d>,
+    next: Option<raw::ParentOfChild>,
+}
+
+impl<'d> Iterator for Ancestors<'d> {
+    type Item = Node<'d>;
+
+    fn next(&mut self) -> Option<Node<'d>> {
+        let parent = self.next?;
+        self.next = match parent {
+            raw::ParentOfChild::Root(_) => None,
+            raw::ParentOfChild::Element(n) => self.document.connections.element_parent(n),
+        };
+        Some(self.document.wrap_parent_of_child(parent).into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        super::{Package, QName},
+        ChildOfElement, ChildOfRoot, Node, NodeType, ParentOfChild,
+    };
+
+    macro_rules! assert_qname_eq(
+        ($l:expr, $r:expr) => (assert_eq!(Into::<QName<'_>>::into($l), $r.into()));
+    );
+
+    #[test]
+    fn the_root_belongs_to_a_document() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+
+        assert_eq!(doc, root.document());
+    }
+
+    #[test]
+    fn root_can_have_element_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+        let element = doc.create_element("alpha");
+
+        root.append_child(element);
+
+        let children = root.children();
         assert_eq!(1, children.len());
         assert_eq!(children[0], ChildOfRoot::Element(element));
     }
@@ -913,227 +2540,1048 @@ mod test {
     }
 
     #[test]
-    fn root_can_remove_children() {
+    fn root_can_remove_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+        let element = doc.create_element("alpha");
+        root.append_child(element);
+
+        root.remove_child(element);
+
+        assert!(root.children().is_empty());
+        assert!(element.parent().is_none());
+    }
+
+    #[test]
+    fn root_can_clear_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+        let element = doc.create_element("alpha");
+        root.append_child(element);
+
+        root.clear_children();
+
+        assert!(root.children().is_empty());
+        assert!(element.parent().is_none());
+    }
+
+    #[test]
+    fn root_child_knows_its_parent() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+        let alpha = doc.create_element("alpha");
+
+        root.append_child(alpha);
+
+        assert_eq!(Some(ParentOfChild::Root(root)), alpha.parent());
+    }
+
+    #[test]
+    fn elements_belong_to_a_document() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element("alpha");
+
+        assert_eq!(doc, element.document());
+    }
+
+    #[test]
+    fn elements_can_have_element_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+
+        alpha.append_child(beta);
+
+        let children = alpha.children();
+
+        assert_eq!(children[0], ChildOfElement::Element(beta));
+    }
+
+    #[test]
+    fn elements_can_append_multiple_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+        let gamma = doc.create_element("gamma");
+
+        alpha.append_children(&[beta, gamma]);
+
+        let children = alpha.children();
+        assert_eq!(2, children.len());
+        assert_eq!(children[0], ChildOfElement::Element(beta));
+        assert_eq!(children[1], ChildOfElement::Element(gamma));
+    }
+
+    #[test]
+    fn elements_can_replace_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+        let gamma = doc.create_element("gamma");
+        let zeta = doc.create_element("zeta");
+        alpha.append_child(zeta);
+
+        alpha.replace_children(&[beta, gamma]);
+
+        let children = alpha.children();
+        assert_eq!(2, children.len());
+        assert_eq!(children[0], ChildOfElement::Element(beta));
+        assert_eq!(children[1], ChildOfElement::Element(gamma));
+    }
+
+    #[test]
+    fn elements_can_remove_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+        alpha.append_child(beta);
+
+        alpha.remove_child(beta);
+
+        assert!(alpha.children().is_empty());
+        assert!(beta.parent().is_none());
+    }
+
+    #[test]
+    fn elements_can_be_removed_from_parent() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+        alpha.append_child(beta);
+
+        beta.remove_from_parent();
+
+        assert!(alpha.children().is_empty());
+        assert!(beta.parent().is_none());
+    }
+
+    #[test]
+    fn elements_can_clear_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+        alpha.append_child(beta);
+
+        alpha.clear_children();
+
+        assert!(alpha.children().is_empty());
+        assert!(beta.parent().is_none());
+    }
+
+    #[test]
+    fn element_children_are_ordered() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let greek = doc.create_element("greek");
+        let alpha = doc.create_element("alpha");
+        let omega = doc.create_element("omega");
+
+        greek.append_child(alpha);
+        greek.append_child(omega);
+
+        let children = greek.children();
+
+        assert_eq!(children[0], ChildOfElement::Element(alpha));
+        assert_eq!(children[1], ChildOfElement::Element(omega));
+    }
+
+    #[test]
+    fn element_children_know_their_parent() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let alpha = doc.create_element("alpha");
+        let beta = doc.create_element("beta");
+
+        alpha.append_child(beta);
+
+        assert_eq!(Some(ParentOfChild::Element(alpha)), beta.parent());
+    }
+
+    #[test]
+    fn elements_know_preceding_siblings() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_element("c");
+        let d = doc.create_element("d");
+
+        parent.append_child(a);
+        parent.append_child(b);
+        parent.append_child(c);
+        parent.append_child(d);
+
+        assert_eq!(
+            vec![ChildOfElement::Element(a), ChildOfElement::Element(b)],
+            c.preceding_siblings()
+        );
+    }
+
+    #[test]
+    fn elements_know_following_siblings() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_element("c");
+        let d = doc.create_element("d");
+
+        parent.append_child(a);
+        parent.append_child(b);
+        parent.append_child(c);
+        parent.append_child(d);
+
+        assert_eq!(
+            vec![ChildOfElement::Element(c), ChildOfElement::Element(d)],
+            b.following_siblings()
+        );
+    }
+
+    #[test]
+    fn elements_insert_children_at_positions() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_element("c");
+        let d = doc.create_element("d");
+
+        parent.append_child(b);
+        parent.prepend_child(a);
+        parent.insert_after(b, d);
+        parent.insert_before(d, c);
+
+        let names: Vec<_> = parent
+            .children()
+            .iter()
+            .map(|c| c.element().unwrap().name().local_part())
+            .collect();
+        assert_eq!(vec!["a", "b", "c", "d"], names);
+
+        assert_eq!(Some(2), parent.index_of(c));
+        assert_eq!(None, parent.index_of(parent));
+    }
+
+    #[test]
+    fn elements_insert_children_by_index() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_text("a");
+        let b = doc.create_text("b");
+        let c = doc.create_text("c");
+
+        parent.insert_child(0, b);
+        parent.insert_child(0, a);
+        parent.insert_child(10, c);
+
+        assert_eq!(
+            vec![
+                ChildOfElement::Text(a),
+                ChildOfElement::Text(b),
+                ChildOfElement::Text(c)
+            ],
+            parent.children()
+        );
+    }
+
+    #[test]
+    fn inserting_an_existing_child_moves_it() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_element("c");
+        parent.append_children(vec![a, b, c]);
+
+        parent.insert_after(c, a);
+        assert_eq!(
+            vec![
+                ChildOfElement::Element(b),
+                ChildOfElement::Element(c),
+                ChildOfElement::Element(a)
+            ],
+            parent.children()
+        );
+
+        parent.insert_before(b, c);
+        assert_eq!(
+            vec![
+                ChildOfElement::Element(c),
+                ChildOfElement::Element(b),
+                ChildOfElement::Element(a)
+            ],
+            parent.children()
+        );
+    }
+
+    #[test]
+    fn inserting_a_child_removes_it_from_its_previous_parent() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let old_parent = doc.create_element("old");
+        let new_parent = doc.create_element("new");
+        let child = doc.create_element("child");
+        old_parent.append_child(child);
+
+        new_parent.prepend_child(child);
+
+        assert!(old_parent.children().is_empty());
+        assert_eq!(Some(ParentOfChild::Element(new_parent)), child.parent());
+    }
+
+    #[test]
+    fn elements_replace_a_child() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_element("c");
+        parent.append_children(vec![a, b, c]);
+
+        let replacement = doc.create_comment("replacement");
+        parent.replace_child(b, replacement);
+
+        assert_eq!(
+            vec![
+                ChildOfElement::Element(a),
+                ChildOfElement::Comment(replacement),
+                ChildOfElement::Element(c)
+            ],
+            parent.children()
+        );
+        assert_eq!(None, b.parent());
+
+        parent.replace_child(replacement, a);
+        assert_eq!(
+            vec![ChildOfElement::Element(a), ChildOfElement::Element(c)],
+            parent.children()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn inserting_before_a_node_that_is_not_a_child_panics() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let stranger = doc.create_element("stranger");
+
+        parent.insert_before(stranger, doc.create_element("child"));
+    }
+
+    #[test]
+    fn root_inserts_children_at_positions() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+        let element = doc.create_element("e");
+        let before = doc.create_comment("before");
+        let after = doc.create_processing_instruction("after", None);
+
+        root.append_child(element);
+        root.insert_before(element, before);
+        root.insert_after(element, after);
+
+        assert_eq!(
+            vec![
+                ChildOfRoot::Comment(before),
+                ChildOfRoot::Element(element),
+                ChildOfRoot::ProcessingInstruction(after)
+            ],
+            root.children()
+        );
+
+        let replacement = doc.create_element("r");
+        root.replace_child(element, replacement);
+        assert_eq!(Some(1), root.index_of(replacement));
+        assert_eq!(3, root.children().len());
+    }
+
+    #[test]
+    fn elements_iterate_over_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_text("b");
+        let c = doc.create_comment("c");
+
+        parent.append_child(a);
+        parent.append_child(b);
+        parent.append_child(c);
+
+        let children: Vec<_> = parent.children_iter().collect();
+        assert_eq!(parent.children(), children);
+
+        let reversed: Vec<_> = parent.children_iter().rev().collect();
+        assert_eq!(
+            vec![
+                ChildOfElement::Comment(c),
+                ChildOfElement::Text(b),
+                ChildOfElement::Element(a)
+            ],
+            reversed
+        );
+
+        assert_eq!(Some(ChildOfElement::Element(a)), parent.first_child());
+        assert_eq!(Some(ChildOfElement::Comment(c)), parent.last_child());
+    }
+
+    #[test]
+    fn root_iterates_over_children() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let root = doc.root();
+        assert_eq!(None, root.first_child());
+
+        let comment = doc.create_comment("c");
+        let element = doc.create_element("e");
+        root.append_child(comment);
+        root.append_child(element);
+
+        let children: Vec<_> = root.children_iter().collect();
+        assert_eq!(root.children(), children);
+        assert_eq!(Some(ChildOfRoot::Comment(comment)), root.first_child());
+        assert_eq!(Some(ChildOfRoot::Element(element)), root.last_child());
+    }
+
+    #[test]
+    fn children_can_be_removed_while_iterating() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        parent.append_child(a);
+        parent.append_child(b);
+
+        let mut seen = Vec::new();
+        for child in parent.children_iter() {
+            seen.push(child);
+            parent.clear_children();
+        }
+
+        assert_eq!(vec![ChildOfElement::Element(a)], seen);
+    }
+
+    #[test]
+    fn nodes_navigate_to_siblings() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let a = doc.create_element("a");
+        let b = doc.create_text("b");
+        let c = doc.create_processing_instruction("c", None);
+        let d = doc.create_comment("d");
+
+        parent.append_child(a);
+        parent.append_child(b);
+        parent.append_child(c);
+        parent.append_child(d);
+
+        assert_eq!(
+            b.preceding_siblings(),
+            b.preceding_siblings_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            b.following_siblings(),
+            b.following_siblings_iter().collect::<Vec<_>>()
+        );
+
+        assert_eq!(None, a.previous_sibling());
+        assert_eq!(Some(ChildOfElement::Text(b)), a.next_sibling());
+        assert_eq!(Some(ChildOfElement::Element(a)), b.previous_sibling());
+        assert_eq!(Some(ChildOfElement::Comment(d)), c.next_sibling());
+        assert_eq!(
+            Some(ChildOfElement::ProcessingInstruction(c)),
+            d.previous_sibling()
+        );
+        assert_eq!(None, d.next_sibling());
+    }
+
+    #[test]
+    fn children_of_the_root_navigate_to_siblings() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let comment = doc.create_comment("c");
+        let element = doc.create_element("e");
+        doc.root().append_child(comment);
+        doc.root().append_child(element);
+
+        assert_eq!(
+            Some(ChildOfElement::Element(element)),
+            comment.next_sibling()
+        );
+        assert_eq!(
+            Some(ChildOfElement::Comment(comment)),
+            element.previous_sibling()
+        );
+    }
+
+    #[test]
+    fn nodes_without_a_parent_have_no_siblings() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element("e");
+
+        assert_eq!(None, element.next_sibling());
+        assert_eq!(0, element.preceding_siblings_iter().count());
+    }
+
+    #[test]
+    fn elements_can_be_shallow_cloned() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element(("urn:e", "e"));
+        element.set_preferred_prefix(Some("p"));
+        element.set_default_namespace_uri(Some("urn:default"));
+        element.register_prefix("q", "urn:q");
+        element
+            .set_attribute_value(("urn:q", "a"), "1")
+            .set_preferred_prefix(Some("q"));
+        element.append_child(doc.create_text("child"));
+        doc.root().append_child(element);
+
+        let copy = element.shallow_clone();
+
+        assert_ne!(element, copy);
+        assert_eq!(None, copy.parent());
+        assert_qname_eq!(("urn:e", "e"), copy.name());
+        assert_eq!(Some("p"), copy.preferred_prefix());
+        assert_eq!(Some("urn:default"), copy.default_namespace_uri());
+        assert_eq!(Some("urn:q"), copy.namespace_uri_for_prefix("q"));
+
+        let attribute = copy.attribute(("urn:q", "a")).unwrap();
+        assert_eq!("1", attribute.value());
+        assert_eq!(Some("q"), attribute.preferred_prefix());
+        assert_ne!(element.attribute(("urn:q", "a")), Some(attribute));
+
+        assert!(copy.children().is_empty());
+    }
+
+    #[test]
+    fn elements_can_be_deep_cloned() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element("e");
+        let child = doc.create_element("child");
+        let text = doc.create_text("text");
+        let comment = doc.create_comment("comment");
+        let pi = doc.create_processing_instruction("pi", Some("value"));
+        let entity = doc.create_entity_reference("ent", Some("value"));
+        element.append_child(child);
+        child.append_children(vec![
+            ChildOfElement::from(text),
+            comment.into(),
+            pi.into(),
+            entity.into(),
+        ]);
+
+        let copy = element.deep_clone();
+
+        let child_copy = copy.children()[0].element().unwrap();
+        assert_ne!(child, child_copy);
+        assert_qname_eq!("child", child_copy.name());
+
+        let grandchildren = child_copy.children();
+        assert_eq!(4, grandchildren.len());
+        assert_eq!("text", grandchildren[0].text().unwrap().text());
+        assert_eq!("comment", grandchildren[1].comment().unwrap().text());
+        assert_eq!(
+            Some("value"),
+            grandchildren[2].processing_instruction().unwrap().value()
+        );
+        assert_eq!("ent", grandchildren[3].entity_reference().unwrap().name());
+
+        // The original is unchanged
+        assert_eq!(4, child.children().len());
+        assert_eq!(Some(ParentOfChild::Element(element)), child.parent());
+    }
+
+    #[test]
+    fn leaf_nodes_can_be_cloned() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let parent = doc.create_element("parent");
+        let text = doc.create_text("text");
+        parent.append_child(text);
+
+        let copy = ChildOfElement::from(text).deep_clone();
+
+        assert_ne!(ChildOfElement::Text(text), copy);
+        assert_eq!("text", copy.text().unwrap().text());
+        assert_eq!(None, copy.text().unwrap().parent());
+    }
+
+    #[test]
+    fn nodes_can_be_imported_from_another_document() {
+        let source_package = Package::new();
+        let source = source_package.as_document();
+
+        let outer = source.create_element(("urn:o", "outer"));
+        outer.register_prefix("o", "urn:o");
+        outer.set_default_namespace_uri(Some("urn:d"));
+        let inner = source.create_element(("urn:d", "inner"));
+        inner
+            .set_attribute_value(("urn:o", "a"), "1")
+            .set_preferred_prefix(Some("o"));
+        inner.append_child(source.create_text("text"));
+        outer.append_child(inner);
+
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let copy = doc.import_node(inner, true).element().unwrap();
+        doc.root().append_child(copy);
+
+        assert_eq!(doc, copy.document());
+        assert_qname_eq!(("urn:d", "inner"), copy.name());
+        assert_eq!(Some("urn:o"), copy.namespace_uri_for_prefix("o"));
+        assert_eq!(Some("urn:d"), copy.default_namespace_uri());
+        assert_eq!(Some("1"), copy.attribute_value(("urn:o", "a")));
+        assert_eq!("text", copy.children()[0].text().unwrap().text());
+
+        // The source is unchanged
+        assert_eq!(Some(ParentOfChild::Element(outer)), inner.parent());
+    }
+
+    #[test]
+    fn elements_can_be_imported_without_children() {
+        let source_package = Package::new();
+        let source = source_package.as_document();
+
+        let element = source.create_element("e");
+        element.set_attribute_value("a", "1");
+        element.append_child(source.create_comment("c"));
+
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let copy = doc.import_element(element, false);
+
+        assert_eq!(Some("1"), copy.attribute_value("a"));
+        assert!(copy.children().is_empty());
+        assert_eq!(None, copy.parent());
+    }
+
+    #[test]
+    fn nested_imported_elements_only_declare_new_namespaces() {
+        let source_package = Package::new();
+        let source = source_package.as_document();
+
+        let outer = source.create_element("outer");
+        outer.register_prefix("a", "urn:a");
+        let inner = source.create_element("inner");
+        inner.register_prefix("b", "urn:b");
+        outer.append_child(inner);
+
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let copy = doc.import_element(outer, true);
+        let inner_copy = copy.children()[0].element().unwrap();
+
+        let prefixes: Vec<_> = inner_copy
+            .registered_namespaces()
+            .iter()
+            .map(|ns| ns.prefix())
+            .collect();
+        assert_eq!(vec!["b"], prefixes);
+    }
+
+    #[test]
+    fn nodes_traverse_in_document_order() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        // <a><b>c</b><!--d--><e><f/></e></a>
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_text("c");
+        let d = doc.create_comment("d");
+        let e = doc.create_element("e");
+        let f = doc.create_element("f");
+
+        doc.root().append_child(a);
+        a.append_children(vec![b.into(), ChildOfElement::from(d), e.into()]);
+        b.append_child(c);
+        e.append_child(f);
+
+        let root = Node::Root(doc.root());
+        let (a, b, c, d, e, f) = (
+            Node::from(a),
+            Node::from(b),
+            Node::from(c),
+            Node::from(d),
+            Node::from(e),
+            Node::from(f),
+        );
+
+        let descendants: Vec<_> = doc.root().descendants().collect();
+        assert_eq!(vec![a, b, c, d, e, f], descendants);
+
+        let descendants: Vec<_> = b.descendants_or_self().collect();
+        assert_eq!(vec![b, c], descendants);
+
+        let ancestors: Vec<_> = f.ancestors().collect();
+        assert_eq!(vec![e, a, root], ancestors);
+
+        let following: Vec<_> = c.following().collect();
+        assert_eq!(vec![d, e, f], following);
+
+        let preceding: Vec<_> = f.preceding().collect();
+        assert_eq!(vec![b, c, d], preceding);
+    }
+
+    #[test]
+    fn the_root_has_no_ancestors_or_following_nodes() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let root = doc.root();
-        let element = doc.create_element("alpha");
-        root.append_child(element);
-
-        root.remove_child(element);
+        doc.root().append_child(doc.create_element("a"));
 
-        assert!(root.children().is_empty());
-        assert!(element.parent().is_none());
+        assert_eq!(0, doc.root().ancestors().count());
+        assert_eq!(0, doc.root().following().count());
+        assert_eq!(0, doc.root().preceding().count());
     }
 
     #[test]
-    fn root_can_clear_children() {
+    fn leaf_nodes_have_no_descendants() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let root = doc.root();
-        let element = doc.create_element("alpha");
-        root.append_child(element);
-
-        root.clear_children();
+        let text = doc.create_text("t");
 
-        assert!(root.children().is_empty());
-        assert!(element.parent().is_none());
+        assert_eq!(0, Node::from(text).descendants().count());
+        assert_eq!(
+            vec![Node::Text(text)],
+            Node::from(text).descendants_or_self().collect::<Vec<_>>()
+        );
     }
 
     #[test]
-    fn root_child_knows_its_parent() {
+    fn string_value_includes_text_of_nested_elements() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let root = doc.root();
-        let alpha = doc.create_element("alpha");
-
-        root.append_child(alpha);
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        a.append_child(doc.create_text("one "));
+        a.append_child(doc.create_comment("ignored"));
+        a.append_child(b);
+        b.set_text_content("two");
+        a.append_child(doc.create_text(" three"));
+        doc.root().append_child(a);
 
-        assert_eq!(Some(ParentOfChild::Root(root)), alpha.parent());
+        assert_eq!("one two three", a.string_value());
+        assert_eq!("one two three", a.text_content());
+        assert_eq!("one two three", doc.root().string_value());
+        assert_eq!("two", b.text_content());
     }
 
     #[test]
-    fn elements_belong_to_a_document() {
+    fn direct_text_skips_nested_elements() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let element = doc.create_element("alpha");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        a.append_child(doc.create_text("one"));
+        a.append_child(b);
+        b.set_text("two");
+        a.append_child(doc.create_entity_reference("amp", Some("&")));
+        doc.root().append_child(a);
 
-        assert_eq!(doc, element.document());
+        assert_eq!("one&", a.direct_text());
+        assert_eq!("", doc.root().direct_text());
+        assert_eq!("", doc.create_element("empty").string_value());
     }
 
     #[test]
-    fn elements_can_have_element_children() {
+    fn normalize_merges_adjacent_text_and_removes_empty_text() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-
-        alpha.append_child(beta);
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        a.append_child(doc.create_text("one"));
+        a.append_child(doc.create_text(""));
+        a.append_child(doc.create_text(" two"));
+        a.append_child(b);
+        b.append_child(doc.create_text(""));
+        a.append_child(doc.create_text("  "));
+        doc.root().append_child(a);
 
-        let children = alpha.children();
+        doc.root().normalize();
 
-        assert_eq!(children[0], ChildOfElement::Element(beta));
+        let children = a.children();
+        assert_eq!(3, children.len());
+        assert_eq!(Some("one two"), children[0].text().map(|t| t.text()));
+        assert_eq!(Some(b), children[1].element());
+        assert_eq!(Some("  "), children[2].text().map(|t| t.text()));
+        assert!(b.children().is_empty());
     }
 
     #[test]
-    fn elements_can_append_multiple_children() {
+    fn normalize_can_remove_whitespace_only_text() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-        let gamma = doc.create_element("gamma");
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let pre = doc.create_element("pre");
+        pre.set_attribute_value((crate::XML_NS_URI, "space"), "preserve");
+        a.append_child(doc.create_text("\n  "));
+        a.append_child(b);
+        a.append_child(doc.create_text(" \t"));
+        a.append_child(pre);
+        b.append_child(doc.create_text(" x "));
+        pre.append_child(doc.create_text("  "));
 
-        alpha.append_children(&[beta, gamma]);
+        a.normalize_and_remove_whitespace();
 
-        let children = alpha.children();
-        assert_eq!(2, children.len());
-        assert_eq!(children[0], ChildOfElement::Element(beta));
-        assert_eq!(children[1], ChildOfElement::Element(gamma));
+        assert_eq!(2, a.children().len());
+        assert_eq!(Some(" x "), b.children()[0].text().map(|t| t.text()));
+        assert_eq!(Some("  "), pre.children()[0].text().map(|t| t.text()));
     }
 
     #[test]
-    fn elements_can_replace_children() {
+    fn nodes_have_common_operations() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-        let gamma = doc.create_element("gamma");
-        let zeta = doc.create_element("zeta");
-        alpha.append_child(zeta);
+        let element = doc.create_element(("urn:e", "e"));
+        let attribute = element.set_attribute_value("a", "1");
+        let text = doc.create_text("hello");
+        let pi = doc.create_processing_instruction("target", Some("data"));
+        doc.root().append_child(element);
+        element.append_child(text);
+        element.append_child(pi);
 
-        alpha.replace_children(&[beta, gamma]);
+        let element = Node::from(element);
+        let attribute = Node::from(attribute);
 
-        let children = alpha.children();
-        assert_eq!(2, children.len());
-        assert_eq!(children[0], ChildOfElement::Element(beta));
-        assert_eq!(children[1], ChildOfElement::Element(gamma));
-    }
+        assert_eq!(NodeType::Element, element.node_type());
+        assert_eq!(NodeType::Attribute, attribute.node_type());
+        assert_eq!(doc, attribute.document());
 
-    #[test]
-    fn elements_can_remove_children() {
-        let package = Package::new();
-        let doc = package.as_document();
+        assert_eq!(Some(element), attribute.parent());
+        assert_eq!(Some(Node::Root(doc.root())), element.parent());
+        assert_eq!(None, Node::Root(doc.root()).parent());
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-        alpha.append_child(beta);
+        assert_eq!(vec![Node::from(text), Node::from(pi)], element.children());
+        assert!(attribute.children().is_empty());
 
-        alpha.remove_child(beta);
+        assert_eq!(
+            Some(QName::with_namespace_uri(Some("urn:e"), "e")),
+            element.expanded_name()
+        );
+        assert_eq!(Some(QName::new("target")), Node::from(pi).expanded_name());
+        assert_eq!(None, Node::from(text).expanded_name());
 
-        assert!(alpha.children().is_empty());
-        assert!(beta.parent().is_none());
+        assert_eq!("hello", element.string_value());
+        assert_eq!("1", attribute.string_value());
+        assert_eq!("data", Node::from(pi).string_value());
     }
 
     #[test]
-    fn elements_can_be_removed_from_parent() {
+    fn namespaces_are_nodes() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-        alpha.append_child(beta);
+        let element = doc.create_element("e");
+        element.register_prefix("p", "urn:p");
 
-        beta.remove_from_parent();
+        let namespace = element
+            .namespaces_in_scope()
+            .into_iter()
+            .find(|ns| ns.prefix() == "p")
+            .unwrap();
+        let namespace = Node::from(namespace);
 
-        assert!(alpha.children().is_empty());
-        assert!(beta.parent().is_none());
+        assert_eq!(NodeType::Namespace, namespace.node_type());
+        assert_eq!(Some(Node::Element(element)), namespace.parent());
+        assert_eq!(Some(QName::new("p")), namespace.expanded_name());
+        assert_eq!("urn:p", namespace.string_value());
     }
 
     #[test]
-    fn elements_can_clear_children() {
+    fn nodes_convert_from_other_enums() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-        alpha.append_child(beta);
-
-        alpha.clear_children();
+        let element = doc.create_element("e");
 
-        assert!(alpha.children().is_empty());
-        assert!(beta.parent().is_none());
+        assert_eq!(Node::Element(element), ChildOfRoot::from(element).into());
+        assert_eq!(Node::Element(element), ChildOfElement::from(element).into());
+        assert_eq!(
+            Node::Element(element),
+            ParentOfChild::Element(element).into()
+        );
     }
 
     #[test]
-    fn element_children_are_ordered() {
+    fn attributes_are_followed_by_the_children_of_their_element() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let greek = doc.create_element("greek");
-        let alpha = doc.create_element("alpha");
-        let omega = doc.create_element("omega");
-
-        greek.append_child(alpha);
-        greek.append_child(omega);
+        let element = doc.create_element("e");
+        let child = doc.create_element("c");
+        let attribute = element.set_attribute_value("a", "1");
+        element.append_child(child);
 
-        let children = greek.children();
+        let following: Vec<_> = Node::from(attribute).following().collect();
+        assert_eq!(vec![Node::Element(child)], following);
 
-        assert_eq!(children[0], ChildOfElement::Element(alpha));
-        assert_eq!(children[1], ChildOfElement::Element(omega));
+        let ancestors: Vec<_> = Node::from(attribute).ancestors().collect();
+        assert_eq!(vec![Node::Element(element)], ancestors);
     }
 
     #[test]
-    fn element_children_know_their_parent() {
+    fn nodes_compare_in_document_order() {
+        use std::cmp::Ordering;
+
         let package = Package::new();
         let doc = package.as_document();
 
-        let alpha = doc.create_element("alpha");
-        let beta = doc.create_element("beta");
-
-        alpha.append_child(beta);
+        let a = doc.create_element("a");
+        let b = doc.create_element("b");
+        let c = doc.create_text("c");
+        let d = doc.create_element("d");
+        doc.root().append_child(a);
+        a.append_child(b);
+        b.append_child(c);
+        a.append_child(d);
+        let attribute = a.set_attribute_value("x", "1");
+        a.register_prefix("p", "urn:p");
+        let namespace = a
+            .namespaces_in_scope()
+            .into_iter()
+            .find(|ns| ns.prefix() == "p")
+            .unwrap();
+
+        let mut nodes = vec![
+            Node::from(d),
+            Node::from(c),
+            Node::from(attribute),
+            Node::from(b),
+            Node::from(namespace),
+            Node::from(a),
+            Node::Root(doc.root()),
+        ];
+        nodes.sort_by(|x, y| x.document_order_cmp(*y));
 
-        assert_eq!(Some(ParentOfChild::Element(alpha)), beta.parent());
+        assert_eq!(
+            vec![
+                Node::Root(doc.root()),
+                Node::from(a),
+                Node::from(namespace),
+                Node::from(attribute),
+                Node::from(b),
+                Node::from(c),
+                Node::from(d),
+            ],
+            nodes
+        );
+        assert_eq!(Ordering::Equal, Node::from(b).document_order_cmp(b.into()));
     }
 
     #[test]
-    fn elements_know_preceding_siblings() {
+    fn siblings_of_the_same_kind_compare_by_position() {
         let package = Package::new();
         let doc = package.as_document();
 
-        let parent = doc.create_element("parent");
         let a = doc.create_element("a");
         let b = doc.create_element("b");
-        let c = doc.create_element("c");
-        let d = doc.create_element("d");
+        let c = doc.create_comment("c");
+        a.append_child(b);
+        a.append_child(c);
+        let x = a.set_attribute_value("x", "1");
+        let y = a.set_attribute_value("y", "2");
 
-        parent.append_child(a);
-        parent.append_child(b);
-        parent.append_child(c);
-        parent.append_child(d);
+        let mut nodes = vec![Node::from(c), Node::from(y), Node::from(b), Node::from(x)];
+        nodes.sort_by(|l, r| l.document_order_cmp(*r));
 
         assert_eq!(
-            vec![ChildOfElement::Element(a), ChildOfElement::Element(b)],
-            c.preceding_siblings()
+            vec![Node::from(x), Node::from(y), Node::from(b), Node::from(c)],
+            nodes
         );
     }
 
     #[test]
-    fn elements_know_following_siblings() {
+    fn nodes_in_separate_trees_have_a_consistent_order() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let a = Node::from(doc.create_element("a"));
+        let b = Node::from(doc.create_element("b"));
+
+        assert_eq!(a.document_order_cmp(b), b.document_order_cmp(a).reverse());
+    }
+
+    #[test]
+    fn node_ids_identify_nodes() {
+        use std::collections::HashMap;
+
         let package = Package::new();
         let doc = package.as_document();
 
-        let parent = doc.create_element("parent");
         let a = doc.create_element("a");
         let b = doc.create_element("b");
-        let c = doc.create_element("c");
-        let d = doc.create_element("d");
 
-        parent.append_child(a);
-        parent.append_child(b);
-        parent.append_child(c);
-        parent.append_child(d);
+        let mut names = HashMap::new();
+        names.insert(a.id(), "a");
+        names.insert(Node::from(b).id(), "b");
 
-        assert_eq!(
-            vec![ChildOfElement::Element(c), ChildOfElement::Element(d)],
-            b.following_siblings()
-        );
+        assert_eq!(Some(&"a"), names.get(&Node::from(a).id()));
+        assert_eq!(Some(&"b"), names.get(&b.id()));
+        assert_ne!(a.id(), doc.root().id());
     }
 
     #[test]
@@ -1617,6 +4065,207 @@ mod test {
         assert_qname_eq!(element.name(), "hello");
     }
 
+    #[test]
+    fn package_can_be_sent_to_another_thread() {
+        let package = ::std::thread::spawn(|| {
+            let package = Package::new();
+            {
+                let doc = package.as_document();
+
+                let element = doc.create_element("hello");
+                element.set_attribute_value("planet", "Earth");
+                doc.root().append_child(element);
+            }
+
+            package
+        })
+        .join()
+        .expect("Thread panicked");
+
+        let doc = package.as_document();
+        let element = doc.root().children()[0].element().unwrap();
+        assert_qname_eq!(element.name(), "hello");
+        assert_eq!(element.attribute_value("planet"), Some("Earth"));
+    }
+
+    #[test]
+    fn elements_inherit_the_language_of_ancestors() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let outer = doc.create_element("outer");
+        let inner = doc.create_element("inner");
+        outer.append_child(inner);
+
+        assert_eq!(inner.effective_lang(), None);
+
+        outer.set_attribute_value((crate::XML_NS_URI, "lang"), "en-GB");
+        assert_eq!(inner.effective_lang(), Some("en-GB"));
+
+        inner.set_attribute_value((crate::XML_NS_URI, "lang"), "");
+        assert_eq!(inner.effective_lang(), Some(""));
+        assert_eq!(outer.effective_lang(), Some("en-GB"));
+    }
+
+    #[test]
+    fn language_ranges_match_subtags_ignoring_case() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element("element");
+        element.set_attribute_value((crate::XML_NS_URI, "lang"), "en-US");
+
+        assert!(element.lang_matches("en"));
+        assert!(element.lang_matches("EN-us"));
+        assert!(element.lang_matches("*"));
+        assert!(!element.lang_matches("e"));
+        assert!(!element.lang_matches("en-US-x"));
+        assert!(!element.lang_matches("fr"));
+    }
+
+    #[test]
+    fn language_ranges_do_not_match_an_unknown_language() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element("element");
+        assert!(!element.lang_matches("*"));
+
+        element.set_attribute_value((crate::XML_NS_URI, "lang"), "");
+        assert!(!element.lang_matches("*"));
+    }
+
+    #[test]
+    fn elements_inherit_the_space_handling_of_ancestors() {
+        use super::Space;
+
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let outer = doc.create_element("outer");
+        let middle = doc.create_element("middle");
+        let inner = doc.create_element("inner");
+        outer.append_child(middle);
+        middle.append_child(inner);
+
+        assert_eq!(inner.effective_space(), Space::Default);
+
+        outer.set_attribute_value((crate::XML_NS_URI, "space"), "preserve");
+        assert_eq!(inner.effective_space(), Space::Preserve);
+
+        middle.set_attribute_value((crate::XML_NS_URI, "space"), "bogus");
+        assert_eq!(inner.effective_space(), Space::Preserve);
+
+        inner.set_attribute_value((crate::XML_NS_URI, "space"), "default");
+        assert_eq!(inner.effective_space(), Space::Default);
+    }
+
+    #[test]
+    fn base_uri_is_the_document_uri_by_default() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let element = doc.create_element("element");
+        doc.root().append_child(element);
+        assert_eq!(element.base_uri(), None);
+        assert_eq!(element.resolve_uri("a.html"), "a.html");
+
+        doc.set_document_uri(Some("http://example.com/docs/index.xml"));
+        assert_eq!(
+            doc.document_uri(),
+            Some("http://example.com/docs/index.xml")
+        );
+        assert_eq!(
+            doc.root().base_uri(),
+            Some("http://example.com/docs/index.xml".to_owned())
+        );
+        assert_eq!(
+            element.resolve_uri("a.html"),
+            "http://example.com/docs/a.html"
+        );
+    }
+
+    #[test]
+    fn base_uri_combines_ancestor_xml_base_attributes() {
+        let package = Package::new();
+        let doc = package.as_document();
+        doc.set_document_uri(Some("http://example.com/docs/index.xml"));
+
+        let outer = doc.create_element("outer");
+        let middle = doc.create_element("middle");
+        let inner = doc.create_element("inner");
+        let text = doc.create_text("text");
+        doc.root().append_child(outer);
+        outer.append_child(middle);
+        middle.append_child(inner);
+        inner.append_child(text);
+
+        outer.set_attribute_value((crate::XML_NS_URI, "base"), "guide/");
+        inner.set_attribute_value((crate::XML_NS_URI, "base"), "../images/");
+        let href = inner.set_attribute_value("href", "logo.png");
+
+        assert_eq!(
+            middle.base_uri(),
+            Some("http://example.com/docs/guide/".to_owned())
+        );
+        assert_eq!(
+            inner.base_uri(),
+            Some("http://example.com/docs/images/".to_owned())
+        );
+        assert_eq!(
+            href.resolve_uri(href.value()),
+            "http://example.com/docs/images/logo.png"
+        );
+        assert_eq!(text.resolve_uri("/top.html"), "http://example.com/top.html");
+    }
+
+    #[test]
+    fn base_uri_without_a_document_uri_uses_xml_base_alone() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        let outer = doc.create_element("outer");
+        let inner = doc.create_element("inner");
+        let comment = doc.create_comment("comment");
+        outer.append_child(inner);
+        inner.append_child(comment);
+
+        outer.set_attribute_value((crate::XML_NS_URI, "base"), "http://example.com/a/");
+        inner.set_attribute_value((crate::XML_NS_URI, "base"), "b/");
+
+        assert_eq!(
+            comment.base_uri(),
+            Some("http://example.com/a/b/".to_owned())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "compile_failure")]
+    fn nodes_cannot_be_sent_to_another_thread() {
+        let package = Package::new();
+        let doc = package.as_document();
+        let element = doc.create_element("hello");
+
+        ::std::thread::spawn(move || element.set_attribute_value("planet", "Earth"));
+    }
+
+    #[test]
+    #[cfg(feature = "compile_failure")]
+    fn documents_cannot_be_sent_to_another_thread() {
+        let package = Package::new();
+        let doc = package.as_document();
+
+        ::std::thread::spawn(move || doc.create_element("hello"));
+    }
+
+    #[test]
+    #[cfg(feature = "compile_failure")]
+    fn packages_cannot_be_shared_between_threads() {
+        fn assert_sync<T: Sync>(_: &T) {}
+
+        assert_sync(&Package::new());
+    }
+
     #[test]
     #[cfg(feature = "compile_failure")]
     fn nodes_cannot_live_outside_of_the_document() {
@@ -0,0 +1,539 @@
+//! An immutable document that can be shared between threads.
+//!
+//! A `Package` may be frozen once it has been built. The frozen
+//! package can no longer be modified, which allows it to be `Sync`;
+//! many threads can navigate the same document at once, for example
+//! by sharing it behind an `Arc`.
+//!
+//! ### Example
+//!
+//! ```
+//! use std::{sync::Arc, thread};
+//! use sxd_document::parser;
+//!
+//! let package = parser::parse("<codes><code>AB</code><code>CD</code></codes>")
+//!     .expect("Failed to parse");
+//! let frozen = Arc::new(package.freeze());
+//!
+//! let workers: Vec<_> = (0..2)
+//!     .map(|i| {
+//!         let frozen = Arc::clone(&frozen);
+//!         thread::spawn(move || {
+//!             let codes = frozen.root().children()[0].element().unwrap();
+//!             let code = codes.children()[i].element().unwrap();
+//!             code.children()[0].text().unwrap().text().to_owned()
+//!         })
+//!     })
+//!     .collect();
+//!
+//! let codes: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+//! assert_eq!(codes, ["AB", "CD"]);
+//! ```
+
+use std::{fmt, hash};
+
+use super::{raw, Package, QName};
+
+/// A `Package` that can no longer be modified
+pub struct FrozenPackage {
+    package: Package,
+}
+
+// The only operations available on a frozen package read from the
+// nodes and the interned strings; none of them reach the `Cell` /
+// `RefCell` state used by the arenas and string pool while
+// allocating. Without mutation, concurrent reads are safe.
+unsafe impl Sync for FrozenPackage {}
+
+impl FrozenPackage {
+    pub(crate) fn new(package: Package) -> FrozenPackage {
+        FrozenPackage { package }
+    }
+
+    /// Converts back into a `Package` that may be modified
+    pub fn thaw(self) -> Package {
+        self.package
+    }
+
+    pub fn root(&self) -> Root<'_> {
+        Root::wrap(self, self.package.connections.root())
+    }
+}
+
+impl fmt::Debug for FrozenPackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FrozenPackage")
+    }
+}
+
+macro_rules! node(
+    ($name:ident, $raw:ty, $doc:expr) => (
+        #[doc = $doc]
+        #[derive(Copy,Clone)]
+        pub struct $name<'d> {
+            package: &'d FrozenPackage,
+            node: *mut $raw,
+        }
+
+        // A handle is only a shared reference into a frozen package
+        unsafe impl<'d> Send for $name<'d> {}
+        unsafe impl<'d> Sync for $name<'d> {}
+
+        impl<'d> $name<'d> {
+            fn wrap(package: &'d FrozenPackage, node: *mut $raw) -> $name<'d> {
+                $name { package, node }
+            }
+
+            #[allow(dead_code)]
+            fn node(&self) -> &'d $raw { unsafe { &*self.node } }
+
+            #[allow(dead_code)]
+            fn connections(&self) -> &'d raw::Connections {
+                &self.package.package.connections
+            }
+        }
+
+        impl<'d> PartialEq for $name<'d> {
+            fn eq(&self, other: &$name<'d>) -> bool {
+                self.node == other.node
+            }
+        }
+
+        impl<'d> Eq for $name<'d> {}
+
+        impl<'d> hash::Hash for $name<'d> {
+            fn hash<H>(&self, state: &mut H)
+                where H: hash::Hasher
+            {
+                self.node.hash(state)
+            }
+        }
+    )
+);
+
+fn wrap_parent_of_child(package: &FrozenPackage, node: raw::ParentOfChild) -> ParentOfChild<'_> {
+    match node {
+        raw::ParentOfChild::Root(n) => ParentOfChild::Root(Root::wrap(package, n)),
+        raw::ParentOfChild::Element(n) => ParentOfChild::Element(Element::wrap(package, n)),
+    }
+}
+
+fn wrap_child_of_root(package: &FrozenPackage, node: raw::ChildOfRoot) -> ChildOfRoot<'_> {
+    match node {
+        raw::ChildOfRoot::Element(n) => ChildOfRoot::Element(Element::wrap(package, n)),
+        raw::ChildOfRoot::Comment(n) => ChildOfRoot::Comment(Comment::wrap(package, n)),
+        raw::ChildOfRoot::ProcessingInstruction(n) => {
+            ChildOfRoot::ProcessingInstruction(ProcessingInstruction::wrap(package, n))
+        }
+    }
+}
+
+fn wrap_child_of_element(package: &FrozenPackage, node: raw::ChildOfElement) -> ChildOfElement<'_> {
+    match node {
+        raw::ChildOfElement::Element(n) => ChildOfElement::Element(Element::wrap(package, n)),
+        raw::ChildOfElement::Text(n) => ChildOfElement::Text(Text::wrap(package, n)),
+        raw::ChildOfElement::EntityReference(n) => {
+            ChildOfElement::EntityReference(EntityReference::wrap(package, n))
+        }
+        raw::ChildOfElement::Comment(n) => ChildOfElement::Comment(Comment::wrap(package, n)),
+        raw::ChildOfElement::ProcessingInstruction(n) => {
+            ChildOfElement::ProcessingInstruction(ProcessingInstruction::wrap(package, n))
+        }
+    }
+}
+
+node!(
+    Root,
+    raw::Root,
+    "The logical ancestor of every other node type"
+);
+
+impl<'d> Root<'d> {
+    pub fn children(&self) -> Vec<ChildOfRoot<'d>> {
+        // This is safe because the package can no longer be mutated.
+        unsafe {
+            self.connections()
+                .root_children()
+                .iter()
+                .map(|n| wrap_child_of_root(self.package, *n))
+                .collect()
+        }
+    }
+}
+
+impl<'d> fmt::Debug for Root<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Root")
+    }
+}
+
+node!(
+    Element,
+    raw::Element,
+    "Elements are named containers of attributes and children"
+);
+
+impl<'d> Element<'d> {
+    pub fn name(&self) -> QName<'d> {
+        self.node().name()
+    }
+
+    pub fn default_namespace_uri(&self) -> Option<&'d str> {
+        self.node().default_namespace_uri()
+    }
+
+    pub fn recursive_default_namespace_uri(&self) -> Option<&'d str> {
+        self.connections().element_default_namespace_uri(self.node)
+    }
+
+    pub fn namespace_uri_for_prefix(&self, prefix: &str) -> Option<&'d str> {
+        self.connections()
+            .element_namespace_uri_for_prefix(self.node, prefix)
+    }
+
+    pub fn preferred_prefix(&self) -> Option<&'d str> {
+        self.node().preferred_prefix()
+    }
+
+    pub fn parent(&self) -> Option<ParentOfChild<'d>> {
+        self.connections()
+            .element_parent(self.node)
+            .map(|n| wrap_parent_of_child(self.package, n))
+    }
+
+    pub fn children(&self) -> Vec<ChildOfElement<'d>> {
+        // This is safe because the package can no longer be mutated.
+        unsafe {
+            self.connections()
+                .element_children(self.node)
+                .iter()
+                .map(|n| wrap_child_of_element(self.package, *n))
+                .collect()
+        }
+    }
+
+    pub fn attribute<'n, N>(&self, name: N) -> Option<Attribute<'d>>
+    where
+        N: Into<QName<'n>>,
+    {
+        self.connections()
+            .attribute(self.node, name)
+            .map(|n| Attribute::wrap(self.package, n))
+    }
+
+    pub fn attributes(&self) -> Vec<Attribute<'d>> {
+        // This is safe because the package can no longer be mutated.
+        unsafe {
+            self.connections()
+                .attributes(self.node)
+                .iter()
+                .map(|n| Attribute::wrap(self.package, *n))
+                .collect()
+        }
+    }
+
+    pub fn attribute_value<'n, N>(&self, name: N) -> Option<&'d str>
+    where
+        N: Into<QName<'n>>,
+    {
+        self.attribute(name).map(|a| a.value())
+    }
+}
+
+impl<'d> fmt::Debug for Element<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Element {{ name: {:?} }}", self.name())
+    }
+}
+
+node!(
+    Attribute,
+    raw::Attribute,
+    "Metadata about the current element"
+);
+
+impl<'d> Attribute<'d> {
+    pub fn name(&self) -> QName<'d> {
+        self.node().name()
+    }
+
+    pub fn value(&self) -> &'d str {
+        self.node().value()
+    }
+
+    pub fn preferred_prefix(&self) -> Option<&'d str> {
+        self.node().preferred_prefix()
+    }
+
+    pub fn parent(&self) -> Option<Element<'d>> {
+        self.connections()
+            .attribute_parent(self.node)
+            .map(|n| Element::wrap(self.package, n))
+    }
+}
+
+impl<'d> fmt::Debug for Attribute<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Attribute {{ name: {:?}, value: {:?} }}",
+            self.name(),
+            self.value()
+        )
+    }
+}
+
+node!(Text, raw::Text, "Textual data");
+
+impl<'d> Text<'d> {
+    pub fn text(&self) -> &'d str {
+        self.node().text()
+    }
+
+    pub fn parent(&self) -> Option<Element<'d>> {
+        self.connections()
+            .text_parent(self.node)
+            .map(|n| Element::wrap(self.package, n))
+    }
+}
+
+impl<'d> fmt::Debug for Text<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Text {{ text: {:?} }}", self.text())
+    }
+}
+
+node!(
+    EntityReference,
+    raw::EntityReference,
+    "A reference to a general entity that was not expanded"
+);
+
+impl<'d> EntityReference<'d> {
+    pub fn name(&self) -> &'d str {
+        self.node().name()
+    }
+
+    pub fn value(&self) -> Option<&'d str> {
+        self.node().value()
+    }
+
+    pub fn parent(&self) -> Option<Element<'d>> {
+        self.connections()
+            .entity_reference_parent(self.node)
+            .map(|n| Element::wrap(self.package, n))
+    }
+}
+
+impl<'d> fmt::Debug for EntityReference<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EntityReference {{ name: {:?}, value: {:?} }}",
+            self.name(),
+            self.value()
+        )
+    }
+}
+
+node!(Comment, raw::Comment, "Information only relevant to humans");
+
+impl<'d> Comment<'d> {
+    pub fn text(&self) -> &'d str {
+        self.node().text()
+    }
+
+    pub fn parent(&self) -> Option<ParentOfChild<'d>> {
+        self.connections()
+            .comment_parent(self.node)
+            .map(|n| wrap_parent_of_child(self.package, n))
+    }
+}
+
+impl<'d> fmt::Debug for Comment<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Comment {{ text: {:?} }}", self.text())
+    }
+}
+
+node!(
+    ProcessingInstruction,
+    raw::ProcessingInstruction,
+    "Metadata relevant to the application, but not the XML processor or humans"
+);
+
+impl<'d> ProcessingInstruction<'d> {
+    pub fn target(&self) -> &'d str {
+        self.node().target()
+    }
+
+    pub fn value(&self) -> Option<&'d str> {
+        self.node().value()
+    }
+
+    pub fn parent(&self) -> Option<ParentOfChild<'d>> {
+        self.connections()
+            .processing_instruction_parent(self.node)
+            .map(|n| wrap_parent_of_child(self.package, n))
+    }
+}
+
+impl<'d> fmt::Debug for ProcessingInstruction<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ProcessingInstruction {{ target: {:?}, value: {:?} }}",
+            self.target(),
+            self.value()
+        )
+    }
+}
+
+macro_rules! unpack(
+    ($enum_name:ident, $name:ident, $wrapper:ident, $inner:ident) => (
+        pub fn $name(self) -> Option<$inner<'d>> {
+            match self {
+                $enum_name::$wrapper(n) => Some(n),
+                _ => None,
+            }
+        }
+    )
+);
+
+/// Nodes that may occur as a child of the root node
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChildOfRoot<'d> {
+    Element(Element<'d>),
+    Comment(Comment<'d>),
+    ProcessingInstruction(ProcessingInstruction<'d>),
+}
+
+impl<'d> ChildOfRoot<'d> {
+    unpack!(ChildOfRoot, element, Element, Element);
+    unpack!(ChildOfRoot, comment, Comment, Comment);
+    unpack!(
+        ChildOfRoot,
+        processing_instruction,
+        ProcessingInstruction,
+        ProcessingInstruction
+    );
+}
+
+/// Nodes that may occur as a child of an element node
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChildOfElement<'d> {
+    Element(Element<'d>),
+    Text(Text<'d>),
+    EntityReference(EntityReference<'d>),
+    Comment(Comment<'d>),
+    ProcessingInstruction(ProcessingInstruction<'d>),
+}
+
+impl<'d> ChildOfElement<'d> {
+    unpack!(ChildOfElement, element, Element, Element);
+    unpack!(ChildOfElement, text, Text, Text);
+    unpack!(
+        ChildOfElement,
+        entity_reference,
+        EntityReference,
+        EntityReference
+    );
+    unpack!(ChildOfElement, comment, Comment, Comment);
+    unpack!(
+        ChildOfElement,
+        processing_instruction,
+        ProcessingInstruction,
+        ProcessingInstruction
+    );
+}
+
+/// Nodes that may occur as the parent of a child node
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParentOfChild<'d> {
+    Root(Root<'d>),
+    Element(Element<'d>),
+}
+
+impl<'d> ParentOfChild<'d> {
+    unpack!(ParentOfChild, root, Root, Root);
+    unpack!(ParentOfChild, element, Element, Element);
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread};
+
+    use super::{super::parser, FrozenPackage};
+
+    fn frozen(xml: &str) -> FrozenPackage {
+        parser::parse(xml).expect("Failed to parse").freeze()
+    }
+
+    #[test]
+    fn navigates_children_attributes_and_parents() {
+        let package = frozen("<a xmlns:x='urn:x' x:b='1' c='2'>hi<!--c--><?pi v?><d/></a>");
+        let root = package.root();
+
+        let a = root.children()[0].element().unwrap();
+        assert_eq!(a.name().local_part(), "a");
+        assert_eq!(a.parent().and_then(|p| p.root()), Some(root));
+        assert_eq!(a.attribute_value(("urn:x", "b")), Some("1"));
+        assert_eq!(a.attribute_value("c"), Some("2"));
+        assert_eq!(a.attributes().len(), 2);
+        assert_eq!(a.attributes()[0].parent(), Some(a));
+        assert_eq!(a.namespace_uri_for_prefix("x"), Some("urn:x"));
+
+        let children = a.children();
+        assert_eq!(children.len(), 4);
+        assert_eq!(children[0].text().unwrap().text(), "hi");
+        assert_eq!(children[0].text().unwrap().parent(), Some(a));
+        assert_eq!(children[1].comment().unwrap().text(), "c");
+        let pi = children[2].processing_instruction().unwrap();
+        assert_eq!((pi.target(), pi.value()), ("pi", Some("v")));
+
+        let d = children[3].element().unwrap();
+        assert_eq!(d.parent().and_then(|p| p.element()), Some(a));
+        assert_eq!(d.recursive_default_namespace_uri(), None);
+    }
+
+    #[test]
+    fn can_be_shared_between_threads() {
+        let package = Arc::new(frozen("<a><b>1</b><b>2</b><b>3</b></a>"));
+
+        let workers: Vec<_> = (0..3)
+            .map(|i| {
+                let package = Arc::clone(&package);
+                thread::spawn(move || {
+                    let a = package.root().children()[0].element().unwrap();
+                    let b = a.children()[i].element().unwrap();
+                    b.children()[0].text().unwrap().text().to_owned()
+                })
+            })
+            .collect();
+
+        let texts: Vec<_> = workers
+            .into_iter()
+            .map(|w| w.join().expect("Thread panicked"))
+            .collect();
+        assert_eq!(texts, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn can_be_thawed() {
+        let package = frozen("<a/>").thaw();
+        let doc = package.as_document();
+
+        let a = doc.root().children()[0].element().unwrap();
+        a.set_attribute_value("b", "c");
+        assert_eq!(a.attribute_value("b"), Some("c"));
+    }
+
+    #[test]
+    #[cfg(feature = "compile_failure")]
+    fn nodes_cannot_outlive_the_frozen_package() {
+        let _ = {
+            let package = frozen("<a/>");
+            package.root().children()[0].element().unwrap()
+        };
+    }
+}
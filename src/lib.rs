@@ -27,6 +27,18 @@
 //! document and reading an XML document. You may wish to perform
 //! large modifications to your data *before* creating a document.
 //!
+//! When the source text outlives the document, a `BorrowedPackage`
+//! from `parser::parse_borrowed` refers to the source instead of
+//! copying it.
+//!
+//! ### Threads
+//!
+//! A `Package` may be moved to another thread, so a document can be
+//! parsed on one thread and used on another. The nodes returned by
+//! `as_document` borrow the package and cannot leave the thread that
+//! owns it. A `Package` is not `Sync`; to share a document between
+//! threads, `freeze` it into a read-only `frozen::FrozenPackage`.
+//!
 //! ### Namespaces, QNames, and Prefixes
 //!
 //! The names of elements and attributes may use namespaces. XML
@@ -55,19 +67,26 @@
 #[macro_use]
 extern crate peresil;
 
-use std::fmt;
+use std::{fmt, marker::PhantomData};
 
 mod lazy_hash_map;
 mod raw;
+mod scan;
 mod str;
 mod str_ext;
 mod string_pool;
+mod uri;
 
+pub mod catalog;
+pub mod compare;
+pub mod diff;
 pub mod dom;
+pub mod frozen;
 pub mod parser;
 #[doc(hidden)]
 pub mod thindom;
 pub mod writer;
+pub mod xinclude;
 
 pub use crate::str::XmlChar;
 
@@ -161,12 +180,7 @@ pub struct Package {
 
 impl Default for Package {
     fn default() -> Package {
-        let s = raw::Storage::new();
-        let root = s.create_root();
-        Package {
-            storage: s,
-            connections: raw::Connections::new(root),
-        }
+        Package::with_storage(raw::Storage::new())
     }
 }
 
@@ -175,10 +189,34 @@ impl Package {
         Self::default()
     }
 
+    fn with_storage(storage: raw::Storage) -> Package {
+        let root = storage.create_root();
+        Package {
+            storage,
+            connections: raw::Connections::new(root),
+        }
+    }
+
     pub fn as_document(&self) -> dom::Document<'_> {
         dom::Document::new(&self.storage, &self.connections)
     }
 
+    /// The URI the document was retrieved from, used as the base for
+    /// resolving relative URIs
+    pub fn document_uri(&self) -> Option<&str> {
+        self.as_document().document_uri()
+    }
+
+    pub fn set_document_uri(&self, uri: Option<&str>) {
+        self.as_document().set_document_uri(uri);
+    }
+
+    /// Prevents any further modification, allowing the document to
+    /// be shared between threads.
+    pub fn freeze(self) -> frozen::FrozenPackage {
+        frozen::FrozenPackage::new(self)
+    }
+
     #[doc(hidden)]
     pub fn as_thin_document(&self) -> (thindom::Storage<'_>, thindom::Connections<'_>) {
         let s = thindom::Storage::new(&self.storage);
@@ -187,6 +225,52 @@ impl Package {
     }
 }
 
+// Every raw pointer inside a `Package` points into the arenas and
+// string pool owned by its `Storage`, and the `Cell` / `RefCell`
+// interior mutability is only reachable through a `&Package`. Moving
+// the package moves all of that together, and the `dom` and `thindom`
+// handles borrow the package so none can be left behind on the
+// original thread. The package remains `!Sync`.
+unsafe impl Send for Package {}
+
+/// A `Package` that refers to the text it was parsed from
+///
+/// Names, attribute values and text that appear verbatim in the input
+/// are not copied; only content that had to be decoded, such as text
+/// containing references, is stored separately. This reduces the
+/// memory used by large documents, but the input must outlive the
+/// package. Create one with `parser::parse_borrowed`.
+pub struct BorrowedPackage<'input> {
+    package: Package,
+    input: PhantomData<&'input str>,
+}
+
+impl<'input> BorrowedPackage<'input> {
+    pub(crate) fn new(input: &'input str) -> BorrowedPackage<'input> {
+        // This is safe because the lifetime ties the package to the input
+        let storage = unsafe { raw::Storage::borrowing(input) };
+        BorrowedPackage {
+            package: Package::with_storage(storage),
+            input: PhantomData,
+        }
+    }
+
+    pub fn as_document(&self) -> dom::Document<'_> {
+        self.package.as_document()
+    }
+
+    #[doc(hidden)]
+    pub fn as_thin_document(&self) -> (thindom::Storage<'_>, thindom::Connections<'_>) {
+        self.package.as_thin_document()
+    }
+}
+
+impl<'input> fmt::Debug for BorrowedPackage<'input> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BorrowedPackage")
+    }
+}
+
 impl PartialEq for Package {
     fn eq(&self, other: &Package) -> bool {
         self as *const Package == other as *const Package
@@ -17,20 +17,21 @@
 #[allow(unused, deprecated)] // rust-lang/rust#46510
 use std::ascii::AsciiExt;
 use std::{
-    char,
-    collections::{BTreeSet, HashMap},
-    error, fmt, iter,
-    mem::replace,
-    ops::Deref,
+    cell::Cell, char, collections::BTreeSet, error, fmt, iter, mem::replace, ops::Deref, str,
 };
 
 use peresil::{self, ParseMaster, Recoverable, StringPoint};
 
 use self::Reference::*;
 
-use super::{dom, str::XmlStr, PrefixedName, QName};
+use super::{
+    catalog::Catalog,
+    dom,
+    str::{XmlChar, XmlStr},
+    uri, PrefixedName, QName,
+};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum SpecificError {
     Expected(&'static str),
 
@@ -61,6 +62,7 @@ enum SpecificError {
     ExpectedDocumentTypeName,
     ExpectedIntSubset,
     ExpectedSystemLiteral,
+    ExpectedPublicLiteral,
 
     ExpectedClosingQuote(&'static str),
     ExpectedOpeningQuote(&'static str),
@@ -81,11 +83,14 @@ enum SpecificError {
     UnknownNamedReference,
 
     DuplicateAttribute,
+    DuplicateExpandedAttribute(String, String),
     RedefinedNamespace,
     RedefinedDefaultNamespace,
     EmptyNamespace,
     UnknownNamespacePrefix,
     UnclosedElement,
+
+    InvalidUtf8,
 }
 
 impl Recoverable for SpecificError {
@@ -101,11 +106,13 @@ impl Recoverable for SpecificError {
             | InvalidHexReference
             | UnknownNamedReference
             | DuplicateAttribute
+            | DuplicateExpandedAttribute(..)
             | RedefinedNamespace
             | RedefinedDefaultNamespace
             | EmptyNamespace
             | UnknownNamespacePrefix
-            | UnclosedElement => false,
+            | UnclosedElement
+            | InvalidUtf8 => false,
             _ => true,
         }
     }
@@ -120,6 +127,13 @@ impl fmt::Display for SpecificError {
             Expected(s) | ExpectedClosingQuote(s) | ExpectedOpeningQuote(s) => {
                 write!(f, "Parser error: {} {}", self.description(), s)
             }
+            DuplicateExpandedAttribute(ref first, ref second) => write!(
+                f,
+                "Parser error: {} {} and {}",
+                self.description(),
+                first,
+                second
+            ),
             _ => write!(f, "Parser error: {}", self.description()),
         }
     }
@@ -151,6 +165,7 @@ impl error::Error for SpecificError {
             ExpectedDocumentTypeName => "expected document type name",
             ExpectedIntSubset => "expected int subset",
             ExpectedSystemLiteral => "expected system literal",
+            ExpectedPublicLiteral => "expected public literal",
             ExpectedClosingQuote(_) => "expected closing quote",
             ExpectedOpeningQuote(_) => "expected opening quote",
             ExpectedDecimalReferenceValue => "expected decimal reference value",
@@ -165,11 +180,13 @@ impl error::Error for SpecificError {
             InvalidHexReference => "invalid hex reference",
             UnknownNamedReference => "unknown named reference",
             DuplicateAttribute => "duplicate attribute",
+            DuplicateExpandedAttribute(..) => "duplicate attribute namespace and local name",
             RedefinedNamespace => "redefined namespace",
             RedefinedDefaultNamespace => "redefined default namespace",
             EmptyNamespace => "empty namespace",
             UnknownNamespacePrefix => "unknown namespace prefix",
             UnclosedElement => "unclosed element",
+            InvalidUtf8 => "invalid UTF-8",
         }
     }
 }
@@ -283,6 +300,7 @@ trait PrivateXmlParseExt<'a> {
     fn consume_pi_value(&self) -> XmlProgress<'a, &'a str>;
     fn consume_start_tag(&self) -> XmlProgress<'a, &'a str>;
     fn consume_encoding(&self) -> XmlProgress<'a, &'a str>;
+    fn consume_pubid_literal(&self, quote: &str) -> XmlProgress<'a, &'a str>;
 }
 
 impl<'a> PrivateXmlParseExt<'a> for StringPoint<'a> {
@@ -334,6 +352,11 @@ impl<'a> PrivateXmlParseExt<'a> for StringPoint<'a> {
         self.consume_to(self.s.end_of_encoding())
             .map_err(|_| SpecificError::ExpectedEncoding)
     }
+
+    fn consume_pubid_literal(&self, quote: &str) -> XmlProgress<'a, &'a str> {
+        self.consume_to(self.s.end_of_pubid_literal(quote))
+            .map_err(|_| SpecificError::ExpectedPublicLiteral)
+    }
 }
 
 trait X<'a> {
@@ -353,10 +376,16 @@ impl<'a> X<'a> for StringPoint<'a> {
     }
 }
 
+#[derive(Debug, Copy, Clone)]
+struct ExternalId<'a> {
+    public_id: Option<&'a str>,
+    system_id: &'a str,
+}
+
 #[derive(Debug, Copy, Clone)]
 enum Token<'a> {
-    XmlDeclaration,
-    DocumentTypeDeclaration,
+    XmlDeclaration(Option<Span<&'a str>>),
+    DocumentTypeDeclaration(Span<&'a str>, Option<ExternalId<'a>>, Option<&'a str>),
     Comment(&'a str),
     ProcessingInstruction(&'a str, Option<&'a str>),
     Whitespace(&'a str),
@@ -398,6 +427,10 @@ impl<'a> PullParser<'a> {
             state: State::AtBeginning,
         }
     }
+
+    fn offset(&self) -> usize {
+        self.xml.offset
+    }
 }
 
 fn parse_comment<'a>(xml: StringPoint<'a>) -> XmlProgress<'a, Token<'_>> {
@@ -478,12 +511,14 @@ fn parse_version_info<'a>(
 fn parse_encoding_declaration<'a>(
     pm: &mut XmlMaster<'a>,
     xml: StringPoint<'a>,
-) -> XmlProgress<'a, &'a str> {
+) -> XmlProgress<'a, Span<&'a str>> {
     let (xml, _) = try_parse!(xml.expect_space());
     let (xml, _) = try_parse!(xml.expect_literal("encoding"));
     let (xml, _) = try_parse!(parse_eq(xml));
-    let (xml, encoding) =
-        try_parse!(parse_quoted_value(pm, xml, |_, xml, _| xml.consume_encoding()));
+    let (xml, encoding) = try_parse!(parse_quoted_value(pm, xml, |_, xml, _| Span::parse(
+        xml,
+        |xml| xml.consume_encoding()
+    )));
 
     success(encoding, xml)
 }
@@ -512,26 +547,68 @@ fn parse_xml_declaration<'a>(
 ) -> XmlProgress<'a, Token<'a>> {
     let (xml, _) = try_parse!(xml.expect_literal("<?xml"));
     let (xml, _version) = try_parse!(parse_version_info(pm, xml));
-    let (xml, _encoding) =
+    let (xml, encoding) =
         try_parse!(pm.optional(xml, |pm, xml| { parse_encoding_declaration(pm, xml) }));
     let (xml, _standalone) =
         try_parse!(pm.optional(xml, |pm, xml| { parse_standalone_declaration(pm, xml) }));
     let (xml, _) = xml.consume_space().optional(xml);
     let (xml, _) = try_parse!(xml.expect_literal("?>"));
 
-    success(Token::XmlDeclaration, xml)
+    success(Token::XmlDeclaration(encoding), xml)
 }
 
-/* only the SYSTEM variant */
-fn parse_external_id<'a>(pm: &mut XmlMaster<'a>, xml: StringPoint<'a>) -> XmlProgress<'a, &'a str> {
-    let (xml, _) = try_parse!(xml.expect_space());
-    let (xml, _) = try_parse!(xml.expect_literal("SYSTEM"));
+fn parse_system_literal<'a>(
+    pm: &mut XmlMaster<'a>,
+    xml: StringPoint<'a>,
+) -> XmlProgress<'a, &'a str> {
+    parse_quoted_value(pm, xml, |_, xml, quote| {
+        xml.consume_attribute_value(quote)
+            .map_err(|_| SpecificError::ExpectedSystemLiteral)
+    })
+}
+
+fn parse_external_id<'a>(
+    pm: &mut XmlMaster<'a>,
+    xml: StringPoint<'a>,
+) -> XmlProgress<'a, ExternalId<'a>> {
+    fn system<'a>(pm: &mut XmlMaster<'a>, xml: StringPoint<'a>) -> XmlProgress<'a, ExternalId<'a>> {
+        let (xml, _) = try_parse!(xml.expect_literal("SYSTEM"));
+        let (xml, _) = try_parse!(xml.expect_space());
+        let (xml, system_id) = try_parse!(parse_system_literal(pm, xml));
+
+        success(
+            ExternalId {
+                public_id: None,
+                system_id,
+            },
+            xml,
+        )
+    }
+
+    fn public<'a>(pm: &mut XmlMaster<'a>, xml: StringPoint<'a>) -> XmlProgress<'a, ExternalId<'a>> {
+        let (xml, _) = try_parse!(xml.expect_literal("PUBLIC"));
+        let (xml, _) = try_parse!(xml.expect_space());
+        let (xml, public_id) = try_parse!(
+            parse_quoted_value(pm, xml, |_, xml, quote| xml.consume_pubid_literal(quote))
+        );
+        let (xml, _) = try_parse!(xml.expect_space());
+        let (xml, system_id) = try_parse!(parse_system_literal(pm, xml));
+
+        success(
+            ExternalId {
+                public_id: Some(public_id),
+                system_id,
+            },
+            xml,
+        )
+    }
+
     let (xml, _) = try_parse!(xml.expect_space());
-    let (xml, external_id) = try_parse!(parse_quoted_value(pm, xml, |_, xml, quote| xml
-        .consume_attribute_value(quote)
-        .map_err(|_| SpecificError::ExpectedSystemLiteral)));
 
-    success(external_id, xml)
+    pm.alternate()
+        .one(|pm| system(pm, xml))
+        .one(|pm| public(pm, xml))
+        .finish()
 }
 
 fn parse_int_subset<'a>(_pm: &mut XmlMaster<'a>, xml: StringPoint<'a>) -> XmlProgress<'a, &'a str> {
@@ -553,15 +630,92 @@ fn parse_document_type_declaration<'a>(
 ) -> XmlProgress<'a, Token<'a>> {
     let (xml, _) = try_parse!(xml.expect_literal("<!DOCTYPE"));
     let (xml, _) = try_parse!(xml.expect_space());
-    let (xml, _type_name) = try_parse!(xml
+    let (xml, type_name) = try_parse!(Span::parse(xml, |xml| xml
         .consume_name()
-        .map_err(|_| SpecificError::ExpectedDocumentTypeName));
-    let (xml, _id) = try_parse!(pm.optional(xml, |p, x| parse_external_id(p, x)));
+        .map_err(|_| SpecificError::ExpectedDocumentTypeName)));
+    let (xml, external_id) = try_parse!(pm.optional(xml, |p, x| parse_external_id(p, x)));
     let (xml, _) = xml.consume_space().optional(xml);
-    let (xml, _int_subset) = try_parse!(pm.optional(xml, |p, x| parse_int_subset(p, x)));
+    let (xml, int_subset) = try_parse!(pm.optional(xml, |p, x| parse_int_subset(p, x)));
     let (xml, _) = try_parse!(xml.expect_literal(">"));
 
-    success(Token::DocumentTypeDeclaration, xml)
+    success(
+        Token::DocumentTypeDeclaration(type_name, external_id, int_subset),
+        xml,
+    )
+}
+
+/// Finds the general entities declared in an internal subset, along
+/// with their replacement text when it is given literally. Only the
+/// first declaration of an entity is binding.
+fn entity_declarations(int_subset: &str) -> Vec<(&str, Option<String>)> {
+    let is_space = |c: char| c.is_space_char();
+    let mut entities: Vec<(&str, Option<String>)> = Vec::new();
+    let mut rest = int_subset;
+
+    while let Some(start) = rest.find("<!ENTITY") {
+        rest = rest[start + "<!ENTITY".len()..].trim_start_matches(is_space);
+
+        if rest.starts_with('%') {
+            // A parameter entity
+            continue;
+        }
+
+        let name_end = rest.find(is_space).unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        rest = rest[name_end..].trim_start_matches(is_space);
+
+        let value = match rest.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => {
+                let literal = &rest[1..];
+                literal.find(quote).map(|end| {
+                    rest = &literal[end + 1..];
+                    decode_character_references(&literal[..end])
+                })
+            }
+            // An external entity
+            _ => None,
+        };
+
+        if entities.iter().all(|&(n, _)| n != name) {
+            entities.push((name, value));
+        }
+    }
+
+    entities
+}
+
+/// Replaces the character references in an entity value. References
+/// to other entities are left as they are.
+fn decode_character_references(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("&#") {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let reference = &rest["&#".len()..end];
+        let mut chars = reference.chars();
+        let c = if chars.next() == Some('x') {
+            u32::from_str_radix(chars.as_str(), 16)
+        } else {
+            reference.parse()
+        };
+
+        match c.ok().and_then(char::from_u32) {
+            Some(c) => decoded.push(c),
+            None => decoded.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    decoded.push_str(rest);
+    decoded
 }
 
 fn parse_pi_value(xml: StringPoint<'_>) -> XmlProgress<'_, &str> {
@@ -829,7 +983,7 @@ impl<'a> Iterator for PullParser<'a> {
         }
 
         let next_state = match (self.state, r) {
-            (State::AtBeginning, Token::XmlDeclaration)
+            (State::AtBeginning, Token::XmlDeclaration(..))
             | (State::AtBeginning, Token::ProcessingInstruction(..))
             | (State::AtBeginning, Token::Comment(..))
             | (State::AtBeginning, Token::Whitespace(..)) => State::AfterDeclaration,
@@ -838,7 +992,9 @@ impl<'a> Iterator for PullParser<'a> {
             (State::AfterDeclaration, Token::ProcessingInstruction(..))
             | (State::AfterDeclaration, Token::Comment(..))
             | (State::AfterDeclaration, Token::Whitespace(..)) => State::AfterDeclaration,
-            (State::AfterDeclaration, Token::DocumentTypeDeclaration) => State::AfterDeclaration,
+            (State::AfterDeclaration, Token::DocumentTypeDeclaration(..)) => {
+                State::AfterDeclaration
+            }
             (State::AfterDeclaration, Token::ElementStart(..)) => State::AfterElementStart(0),
 
             (State::AfterElementStart(d), Token::AttributeStart(_, q)) => {
@@ -879,55 +1035,261 @@ impl<'a> Iterator for PullParser<'a> {
     }
 }
 
-struct DomBuilder<'d> {
-    doc: dom::Document<'d>,
-    elements: Vec<dom::Element<'d>>,
-    element_names: Vec<Span<PrefixedName<'d>>>,
-    attributes: Vec<DeferredAttribute<'d>>,
-    seen_top_element: bool,
+/// Receives the nodes of a document as it is parsed.
+///
+/// The parser performs the well-formedness and namespace checks and
+/// passes each node to the sink with its namespace already resolved.
+/// This allows building a tree other than a `dom::Document`. The
+/// parser uses `Handle`s only to tell the sink where nodes belong.
+///
+/// Every element is created, given its namespace declarations and
+/// attributes, and then appended to its parent before any of its
+/// children are created. Character data may be split across several
+/// adjacent text nodes, such as around references.
+///
+/// ### Example
+///
+/// ```
+/// use sxd_document::{parser::{self, TreeSink}, QName};
+///
+/// // Records the depth of each element
+/// #[derive(Default)]
+/// struct Depths {
+///     depths: Vec<(String, usize)>,
+/// }
+///
+/// impl TreeSink for Depths {
+///     // The depth of the node
+///     type Handle = usize;
+///
+///     fn document(&mut self) -> usize {
+///         0
+///     }
+///
+///     fn create_element(&mut self, name: QName<'_>, _: Option<&str>) -> usize {
+///         self.depths.push((name.local_part().to_owned(), 0));
+///         self.depths.len()
+///     }
+///
+///     fn add_attribute(&mut self, _: &usize, _: QName<'_>, _: Option<&str>, _: &str) {}
+///
+///     fn create_text(&mut self, _: &str) -> usize { 0 }
+///     fn create_comment(&mut self, _: &str) -> usize { 0 }
+///     fn create_processing_instruction(&mut self, _: &str, _: Option<&str>) -> usize { 0 }
+///
+///     fn append(&mut self, parent: &usize, child: usize) {
+///         if child > 0 {
+///             let depth = if *parent == 0 { 0 } else { self.depths[*parent - 1].1 + 1 };
+///             self.depths[child - 1].1 = depth;
+///         }
+///     }
+/// }
+///
+/// let mut sink = Depths::default();
+/// parser::parse_with_sink("<a><b><c/></b><d/></a>", &mut sink).expect("Failed to parse");
+///
+/// let depths: Vec<_> = sink.depths.iter().map(|&(ref n, d)| (&n[..], d)).collect();
+/// assert_eq!(depths, [("a", 0), ("b", 1), ("c", 2), ("d", 1)]);
+/// ```
+pub trait TreeSink {
+    /// Refers to a node that the sink has created
+    type Handle: Clone;
+
+    /// The document itself, the parent of the top-level nodes
+    fn document(&mut self) -> Self::Handle;
+
+    /// Creates an element. The preferred prefix is the prefix used
+    /// in the document, if any.
+    fn create_element(&mut self, name: QName<'_>, preferred_prefix: Option<&str>) -> Self::Handle;
+
+    /// Adds an attribute to an element. The preferred prefix is the
+    /// prefix used in the document, if any.
+    fn add_attribute(
+        &mut self,
+        element: &Self::Handle,
+        name: QName<'_>,
+        preferred_prefix: Option<&str>,
+        value: &str,
+    );
+
+    /// Records a namespace prefix declared by an element
+    fn register_prefix(&mut self, _element: &Self::Handle, _prefix: &str, _namespace_uri: &str) {}
+
+    /// Records the default namespace declared by an element. An
+    /// empty declaration (`xmlns=""`) is reported as `None`.
+    fn set_default_namespace_uri(&mut self, _element: &Self::Handle, _namespace_uri: Option<&str>) {
+    }
+
+    fn create_text(&mut self, text: &str) -> Self::Handle;
+
+    /// Creates a reference to a general entity that was not
+    /// expanded, along with its replacement text when it was declared.
+    /// This is only used when a `Parser` does not expand entities; by
+    /// default, a text node containing the replacement text is created.
+    fn create_entity_reference(&mut self, _name: &str, value: Option<&str>) -> Self::Handle {
+        self.create_text(value.unwrap_or(""))
+    }
+
+    fn create_comment(&mut self, text: &str) -> Self::Handle;
+
+    fn create_processing_instruction(&mut self, target: &str, value: Option<&str>) -> Self::Handle;
+
+    /// Records the document type declaration. When a `Catalog` was
+    /// given to the `Parser` and maps the external identifier, the
+    /// location is the resource it maps to.
+    fn document_type(
+        &mut self,
+        _name: &str,
+        _public_id: Option<&str>,
+        _system_id: Option<&str>,
+        _location: Option<&str>,
+    ) {
+    }
+
+    /// Appends a node to the document or to an element
+    fn append(&mut self, parent: &Self::Handle, child: Self::Handle);
 }
 
-impl<'d> DomBuilder<'d> {
-    fn new(doc: dom::Document<'d>) -> DomBuilder<'d> {
-        DomBuilder {
-            doc,
-            elements: vec![],
-            element_names: Vec::new(),
-            attributes: Vec::new(),
-            seen_top_element: false,
+/// The namespace declarations of the open elements
+struct NamespaceScopes<'a> {
+    scopes: Vec<Vec<NamespaceDeclaration<'a>>>,
+}
+
+struct NamespaceDeclaration<'a> {
+    // A `None` prefix is the default namespace, where an empty URI
+    // means no namespace.
+    prefix: Option<&'a str>,
+    uri: String,
+    offset: usize,
+    // Whether any name has been resolved using this declaration
+    used: Cell<bool>,
+}
+
+impl<'a> NamespaceDeclaration<'a> {
+    fn new(prefix: Option<&'a str>, uri: String, offset: usize) -> NamespaceDeclaration<'a> {
+        NamespaceDeclaration {
+            prefix,
+            uri,
+            offset,
+            used: Cell::new(false),
         }
     }
+}
 
-    fn append_to_either<T>(&self, child: T)
-    where
-        T: Into<dom::ChildOfRoot<'d>>,
-    {
-        match self.elements.last() {
-            None => self.doc.root().append_child(child),
-            Some(parent) => parent.append_child(child.into()),
+impl<'a> NamespaceScopes<'a> {
+    fn new() -> NamespaceScopes<'a> {
+        let xml =
+            NamespaceDeclaration::new(Some(crate::XML_NS_PREFIX), crate::XML_NS_URI.to_owned(), 0);
+        NamespaceScopes {
+            scopes: vec![vec![xml]],
         }
     }
 
-    fn default_namespace_uri(&self) -> Option<&str> {
-        self.elements
-            .last()
-            .and_then(|e| e.recursive_default_namespace_uri())
+    fn push(&mut self, scope: Vec<NamespaceDeclaration<'a>>) {
+        self.scopes.push(scope);
+    }
+
+    fn pop(&mut self) -> Vec<NamespaceDeclaration<'a>> {
+        self.scopes.pop().unwrap_or_default()
+    }
+
+    fn lookup(&self, prefix: Option<&str>) -> Option<&str> {
+        let declaration = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .find(|d| d.prefix == prefix)?;
+
+        declaration.used.set(true);
+        Some(&declaration.uri)
     }
 
     fn namespace_uri_for_prefix(&self, prefix: &str) -> Option<&str> {
-        self.elements
-            .last()
-            .and_then(|e| e.namespace_uri_for_prefix(prefix))
+        self.lookup(Some(prefix))
+    }
+
+    fn default_namespace_uri(&self) -> Option<&str> {
+        self.lookup(None)
+            .and_then(|uri| if uri.is_empty() { None } else { Some(uri) })
+    }
+}
+
+struct TreeBuilder<'a, 's, S>
+where
+    S: TreeSink,
+{
+    sink: &'s mut S,
+    document: S::Handle,
+    elements: Vec<S::Handle>,
+    element_names: Vec<Span<PrefixedName<'a>>>,
+    attributes: Vec<DeferredAttribute<'a>>,
+    namespaces: NamespaceScopes<'a>,
+    input: &'a str,
+    expand_entities: bool,
+    entities: Vec<(&'a str, Option<String>)>,
+    document_type: Option<&'a str>,
+    catalog: Option<&'s Catalog>,
+    warnings: Vec<Warning>,
+    lexical: Option<LexicalRecorder<'a, S::Handle>>,
+    // The node most recently added to the tree, when recording the
+    // lexical form
+    appended: Option<S::Handle>,
+}
+
+impl<'a, 's, S> TreeBuilder<'a, 's, S>
+where
+    S: TreeSink,
+{
+    fn new(sink: &'s mut S, input: &'a str, options: &'s Parser) -> TreeBuilder<'a, 's, S> {
+        let document = sink.document();
+        let lexical = if options.preserve_lexical_form {
+            Some(LexicalRecorder::new(input))
+        } else {
+            None
+        };
+        TreeBuilder {
+            sink,
+            document,
+            elements: Vec::new(),
+            element_names: Vec::new(),
+            attributes: Vec::new(),
+            namespaces: NamespaceScopes::new(),
+            input,
+            expand_entities: options.expand_entities,
+            entities: Vec::new(),
+            document_type: None,
+            catalog: options.catalog.as_ref(),
+            warnings: Vec::new(),
+            lexical,
+            appended: None,
+        }
+    }
+
+    fn warn(&mut self, location: usize, kind: WarningKind) {
+        self.warnings.push(Warning { location, kind });
+    }
+
+    fn append_to_either(&mut self, child: S::Handle) {
+        let parent = self.elements.last().unwrap_or(&self.document);
+        self.sink.append(parent, child.clone());
+        self.appended(child);
+    }
+
+    fn appended(&mut self, child: S::Handle) {
+        if self.lexical.is_some() {
+            self.appended = Some(child);
+        }
     }
 
     fn finish_opening_tag(&mut self) -> DomBuilderResult<()> {
-        let deferred_element = self.element_names.last().expect("Unknown element name");
+        let deferred_element = *self.element_names.last().expect("Unknown element name");
         let attributes = DeferredAttributes::new(replace(&mut self.attributes, Vec::new()));
 
         attributes.check_duplicates()?;
         let default_namespace = attributes.default_namespace()?;
 
-        let mut new_prefix_mappings = HashMap::new();
+        let mut new_prefix_mappings = Vec::new();
         for ns in attributes.namespaces() {
             let value = AttributeValueBuilder::convert(&ns.values)?;
 
@@ -935,82 +1297,158 @@ impl<'d> DomBuilder<'d> {
                 return Err(ns.name.map(|_| SpecificError::EmptyNamespace));
             }
 
-            new_prefix_mappings.insert(ns.name.value.local_part, value);
+            let prefix = Some(ns.name.value.local_part);
+            new_prefix_mappings.push(NamespaceDeclaration::new(prefix, value, ns.name.offset));
+        }
+        if let Some(ns_uri) = default_namespace {
+            new_prefix_mappings.push(NamespaceDeclaration::new(None, ns_uri.value, ns_uri.offset));
         }
-        let new_prefix_mappings = new_prefix_mappings;
+        for declaration in &new_prefix_mappings {
+            // Relative namespace URIs are deprecated
+            if !declaration.uri.is_empty() && !uri::has_scheme(&declaration.uri) {
+                let kind = WarningKind::RelativeNamespaceUri(declaration.uri.clone());
+                self.warn(declaration.offset, kind);
+            }
+        }
+        self.namespaces.push(new_prefix_mappings);
 
         let element_name = &deferred_element.value;
 
-        let element = if let Some(prefix) = element_name.prefix {
-            let ns_uri = new_prefix_mappings.get(prefix).map(|p| &p[..]);
-            let ns_uri = ns_uri.or_else(|| self.namespace_uri_for_prefix(prefix));
-
-            if let Some(ns_uri) = ns_uri {
-                let element = self.doc.create_element((ns_uri, element_name.local_part));
-                element.set_preferred_prefix(Some(prefix));
-                element
-            } else {
-                return Err(deferred_element.map(|_| SpecificError::UnknownNamespacePrefix));
+        if self.elements.is_empty() {
+            if let Some(document_type) = self.document_type.take() {
+                let root = qualified_name(*element_name);
+                if root != document_type {
+                    let kind = WarningKind::DocumentTypeMismatch {
+                        document_type: document_type.to_owned(),
+                        root,
+                    };
+                    self.warn(deferred_element.offset, kind);
+                }
             }
-        } else if let Some(ns_uri) = default_namespace {
-            if ns_uri.is_empty() {
-                let element = self.doc.create_element(element_name.local_part);
-                element.set_default_namespace_uri(None);
-                element
-            } else {
-                let element = self
-                    .doc
-                    .create_element((&ns_uri[..], element_name.local_part));
-                element.set_default_namespace_uri(Some(&ns_uri));
-                element
+        }
+
+        let element = if let Some(prefix) = element_name.prefix {
+            match self.namespaces.namespace_uri_for_prefix(prefix) {
+                Some(ns_uri) => self
+                    .sink
+                    .create_element((ns_uri, element_name.local_part).into(), Some(prefix)),
+                None => {
+                    return Err(deferred_element.map(|_| SpecificError::UnknownNamespacePrefix));
+                }
             }
         } else {
-            let ns_uri = self.default_namespace_uri();
+            let ns_uri = self.namespaces.default_namespace_uri();
             let name = QName::with_namespace_uri(ns_uri, element_name.local_part);
-            self.doc.create_element(name)
+            self.sink.create_element(name, None)
         };
 
-        for (prefix, ns_uri) in &new_prefix_mappings {
-            element.register_prefix(*prefix, ns_uri);
-        }
-
-        if !self.seen_top_element {
-            self.seen_top_element = true;
-            element.register_prefix(crate::XML_NS_PREFIX, crate::XML_NS_URI);
+        for declaration in self.namespaces.scopes.last().expect("No scope") {
+            let ns_uri = &declaration.uri;
+            match declaration.prefix {
+                Some(prefix) => self.sink.register_prefix(&element, prefix, ns_uri),
+                None => {
+                    let ns_uri = if ns_uri.is_empty() {
+                        None
+                    } else {
+                        Some(&ns_uri[..])
+                    };
+                    self.sink.set_default_namespace_uri(&element, ns_uri);
+                }
+            }
         }
 
-        self.append_to_either(element);
-        self.elements.push(element);
+        self.check_expanded_duplicates(&attributes)?;
 
         let mut builder = AttributeValueBuilder::new();
 
         for attribute in attributes.attributes() {
             let name = &attribute.name.value;
 
-            builder.clear();
-            builder.ingest(&attribute.values)?;
+            // A value without references can be used as it appears in the input
+            let value = match attribute.values[..] {
+                [AttributeValue::LiteralAttributeValue(v)] => v,
+                _ => {
+                    builder.clear();
+                    builder.ingest(&attribute.values)?;
+                    &builder
+                }
+            };
 
             if let Some(prefix) = name.prefix {
-                let ns_uri = new_prefix_mappings.get(prefix).map(|p| &p[..]);
-                let ns_uri = ns_uri.or_else(|| self.namespace_uri_for_prefix(prefix));
-
-                if let Some(ns_uri) = ns_uri {
-                    let attr = element.set_attribute_value((ns_uri, name.local_part), &builder);
-                    attr.set_preferred_prefix(Some(prefix));
+                if let Some(ns_uri) = self.namespaces.namespace_uri_for_prefix(prefix) {
+                    let name = (ns_uri, name.local_part).into();
+                    self.sink.add_attribute(&element, name, Some(prefix), value);
                 } else {
                     return Err(attribute
                         .name
                         .map(|_| SpecificError::UnknownNamespacePrefix));
                 }
             } else {
-                element.set_attribute_value(name.local_part, &builder);
+                self.sink
+                    .add_attribute(&element, name.local_part.into(), None, value);
+            }
+        }
+
+        self.append_to_either(element.clone());
+        self.elements.push(element);
+
+        Ok(())
+    }
+
+    /// Attributes with different prefixes bound to the same namespace
+    /// URI are also duplicates
+    fn check_expanded_duplicates(
+        &self,
+        attributes: &DeferredAttributes<'a>,
+    ) -> DomBuilderResult<()> {
+        let mut seen: Vec<(&str, PrefixedName<'a>)> = Vec::new();
+
+        for attribute in attributes.attributes() {
+            let name = attribute.name.value;
+            let prefix = match name.prefix {
+                Some(prefix) => prefix,
+                None => continue,
+            };
+            let ns_uri = match self.namespaces.namespace_uri_for_prefix(prefix) {
+                Some(ns_uri) => ns_uri,
+                None => continue,
+            };
+
+            let first = seen
+                .iter()
+                .find(|&&(u, n)| u == ns_uri && n.local_part == name.local_part);
+            if let Some(&(_, first)) = first {
+                return Err(attribute.name.map(|_| {
+                    SpecificError::DuplicateExpandedAttribute(
+                        qualified_name(first),
+                        qualified_name(name),
+                    )
+                }));
             }
+
+            seen.push((ns_uri, name));
         }
 
         Ok(())
     }
 
-    fn add_attribute_value(&mut self, v: AttributeValue<'d>) {
+    fn close_element(&mut self) -> Span<PrefixedName<'a>> {
+        self.elements.pop();
+
+        for declaration in self.namespaces.pop() {
+            if !declaration.used.get() && !declaration.uri.is_empty() {
+                let kind = WarningKind::UnusedNamespace {
+                    prefix: declaration.prefix.map(String::from),
+                    uri: declaration.uri,
+                };
+                self.warn(declaration.offset, kind);
+            }
+        }
+
+        self.element_names.pop().expect("No open element")
+    }
+
+    fn add_attribute_value(&mut self, v: AttributeValue<'a>) {
         let a = self
             .attributes
             .last_mut()
@@ -1018,26 +1456,77 @@ impl<'d> DomBuilder<'d> {
         a.values.push(v);
     }
 
-    fn add_text_data(&self, text: &str) {
+    fn add_text_data(&mut self, text: &str) {
+        let t = self.sink.create_text(text);
         let e = self
             .elements
             .last()
             .expect("Cannot add text node without a parent");
-        let t = self.doc.create_text(text);
-        e.append_child(t);
+        self.sink.append(e, t.clone());
+        self.appended(t);
+    }
+
+    fn add_entity_reference(&mut self, name: &str) {
+        let value = self
+            .entities
+            .iter()
+            .find(|e| e.0 == name)
+            .and_then(|e| e.1.as_ref())
+            .map(|v| &v[..]);
+        let r = self.sink.create_entity_reference(name, value);
+        let e = self
+            .elements
+            .last()
+            .expect("Cannot add entity reference without a parent");
+        self.sink.append(e, r.clone());
+        self.appended(r);
     }
 
     fn has_unclosed_elements(&self) -> bool {
         !self.elements.is_empty()
     }
 
-    fn consume(&mut self, token: Token<'d>) -> DomBuilderResult<()> {
+    fn consume(&mut self, token: Token<'a>, start: usize, end: usize) -> DomBuilderResult<()> {
+        self.build(token)?;
+
+        if let Some(ref mut lexical) = self.lexical {
+            lexical.consume(token, start, end, self.appended.take());
+        }
+
+        Ok(())
+    }
+
+    fn build(&mut self, token: Token<'a>) -> DomBuilderResult<()> {
         use self::Token::*;
 
         match token {
-            XmlDeclaration => {}
+            XmlDeclaration(encoding) => {
+                if let Some(encoding) = encoding {
+                    if !encoding_describes(encoding.value, self.input) {
+                        let kind = WarningKind::EncodingMismatch(encoding.value.to_owned());
+                        self.warn(encoding.offset, kind);
+                    }
+                }
+            }
 
-            DocumentTypeDeclaration => {}
+            DocumentTypeDeclaration(name, external_id, int_subset) => {
+                self.document_type = Some(name.value);
+                let public_id = external_id.and_then(|id| id.public_id);
+                let system_id = external_id.map(|id| id.system_id);
+                let location = match (self.catalog, external_id) {
+                    (Some(catalog), Some(_)) => catalog.resolve_external_id(public_id, system_id),
+                    _ => None,
+                };
+                self.sink.document_type(
+                    name.value,
+                    public_id,
+                    system_id,
+                    location.as_ref().map(|l| &l[..]),
+                );
+                if let Some(int_subset) = int_subset {
+                    self.entities = entity_declarations(int_subset);
+                }
+            }
 
             ElementStart(n) => {
                 self.element_names.push(n);
@@ -1049,14 +1538,11 @@ impl<'d> DomBuilder<'d> {
 
             ElementSelfClose => {
                 self.finish_opening_tag()?;
-
-                self.element_names.pop();
-                self.elements.pop();
+                self.close_element();
             }
 
             ElementClose(n) => {
-                let open_name = self.element_names.pop().expect("No open element");
-                self.elements.pop();
+                let open_name = self.close_element();
 
                 if n.value != open_name.value {
                     return Err(n.map(|_| SpecificError::MismatchedElementEndName));
@@ -1085,17 +1571,23 @@ impl<'d> DomBuilder<'d> {
 
             CharData(t) | CData(t) => self.add_text_data(t),
 
+            ContentReference(Entity(span))
+                if !self.expand_entities && !PREDEFINED_ENTITIES.contains(&span.value) =>
+            {
+                self.add_entity_reference(span.value);
+            }
+
             ContentReference(t) => {
                 decode_reference(t, |s| self.add_text_data(s))?;
             }
 
             Comment(c) => {
-                let c = self.doc.create_comment(c);
+                let c = self.sink.create_comment(c);
                 self.append_to_either(c);
             }
 
             ProcessingInstruction(t, v) => {
-                let pi = self.doc.create_processing_instruction(t, v);
+                let pi = self.sink.create_processing_instruction(t, v);
                 self.append_to_either(pi);
             }
         };
@@ -1104,6 +1596,129 @@ impl<'d> DomBuilder<'d> {
     }
 }
 
+const PREDEFINED_ENTITIES: [&str; 5] = ["amp", "lt", "gt", "apos", "quot"];
+
+/// Builds a `dom::Document`
+struct DomBuilder<'d> {
+    doc: dom::Document<'d>,
+}
+
+#[derive(Clone)]
+enum DomHandle<'d> {
+    Root(dom::Root<'d>),
+    Element(dom::Element<'d>),
+    Text(dom::Text<'d>),
+    EntityReference(dom::EntityReference<'d>),
+    Comment(dom::Comment<'d>),
+    ProcessingInstruction(dom::ProcessingInstruction<'d>),
+}
+
+impl<'d> DomHandle<'d> {
+    fn element(&self) -> dom::Element<'d> {
+        match *self {
+            DomHandle::Element(e) => e,
+            _ => unreachable!("The handle is not an element"),
+        }
+    }
+}
+
+impl<'d> TreeSink for DomBuilder<'d> {
+    type Handle = DomHandle<'d>;
+
+    fn document(&mut self) -> Self::Handle {
+        DomHandle::Root(self.doc.root())
+    }
+
+    fn create_element(&mut self, name: QName<'_>, preferred_prefix: Option<&str>) -> Self::Handle {
+        let element = self.doc.create_element(name);
+        if preferred_prefix.is_some() {
+            element.set_preferred_prefix(preferred_prefix);
+        }
+        DomHandle::Element(element)
+    }
+
+    fn add_attribute(
+        &mut self,
+        element: &Self::Handle,
+        name: QName<'_>,
+        preferred_prefix: Option<&str>,
+        value: &str,
+    ) {
+        let attr = element.element().set_attribute_value(name, value);
+        if preferred_prefix.is_some() {
+            attr.set_preferred_prefix(preferred_prefix);
+        }
+    }
+
+    fn register_prefix(&mut self, element: &Self::Handle, prefix: &str, namespace_uri: &str) {
+        element.element().register_prefix(prefix, namespace_uri);
+    }
+
+    fn set_default_namespace_uri(&mut self, element: &Self::Handle, namespace_uri: Option<&str>) {
+        element.element().set_default_namespace_uri(namespace_uri);
+    }
+
+    fn create_text(&mut self, text: &str) -> Self::Handle {
+        DomHandle::Text(self.doc.create_text(text))
+    }
+
+    fn create_entity_reference(&mut self, name: &str, value: Option<&str>) -> Self::Handle {
+        DomHandle::EntityReference(self.doc.create_entity_reference(name, value))
+    }
+
+    fn create_comment(&mut self, text: &str) -> Self::Handle {
+        DomHandle::Comment(self.doc.create_comment(text))
+    }
+
+    fn create_processing_instruction(&mut self, target: &str, value: Option<&str>) -> Self::Handle {
+        DomHandle::ProcessingInstruction(self.doc.create_processing_instruction(target, value))
+    }
+
+    fn document_type(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+        location: Option<&str>,
+    ) {
+        self.doc.root().set_document_type(Some(dom::DocumentType {
+            name,
+            public_id,
+            system_id,
+            location,
+        }));
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: Self::Handle) {
+        match *parent {
+            DomHandle::Root(root) => {
+                let child: dom::ChildOfRoot<'_> = match child {
+                    DomHandle::Element(e) => {
+                        e.register_prefix(crate::XML_NS_PREFIX, crate::XML_NS_URI);
+                        e.into()
+                    }
+                    DomHandle::Comment(c) => c.into(),
+                    DomHandle::ProcessingInstruction(pi) => pi.into(),
+                    _ => unreachable!("The root cannot contain this node"),
+                };
+                root.append_child(child);
+            }
+            DomHandle::Element(element) => {
+                let child: dom::ChildOfElement<'_> = match child {
+                    DomHandle::Element(e) => e.into(),
+                    DomHandle::Text(t) => t.into(),
+                    DomHandle::EntityReference(r) => r.into(),
+                    DomHandle::Comment(c) => c.into(),
+                    DomHandle::ProcessingInstruction(pi) => pi.into(),
+                    DomHandle::Root(_) => unreachable!("The root cannot be a child"),
+                };
+                element.append_child(child);
+            }
+            _ => unreachable!("Only the root and elements have children"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Error {
     location: usize,
@@ -1152,39 +1767,851 @@ impl error::Error for Error {
     }
 }
 
-/// Parses a string into a DOM. On failure, the location of the
-/// parsing failure and all possible failures will be returned.
-pub fn parse(xml: &str) -> Result<super::Package, Error> {
-    let parser = PullParser::new(xml);
-    let package = super::Package::new();
+/// A problem found in a document that was parsed successfully
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    location: usize,
+    kind: WarningKind,
+}
 
-    {
-        let doc = package.as_document();
-        let mut builder = DomBuilder::new(doc);
+impl Warning {
+    pub fn location(&self) -> usize {
+        self.location
+    }
 
-        for token in parser {
-            let token = token?;
-            builder.consume(token)?;
-        }
+    pub fn kind(&self) -> &WarningKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "XML parsing warning at {}: {}", self.location, self.kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    /// A namespace was declared but no name refers to it. A `None`
+    /// prefix is the default namespace.
+    UnusedNamespace { prefix: Option<String>, uri: String },
+    /// A namespace was declared with a relative URI
+    RelativeNamespaceUri(String),
+    /// The name in the document type declaration differs from the
+    /// name of the root element
+    DocumentTypeMismatch { document_type: String, root: String },
+    /// The encoding declaration does not describe the input, which is
+    /// always UTF-8
+    EncodingMismatch(String),
+}
 
-        if builder.has_unclosed_elements() {
-            return Err(Error::new(xml.len(), SpecificError::UnclosedElement));
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::WarningKind::*;
+
+        match *self {
+            UnusedNamespace {
+                prefix: Some(ref prefix),
+                ref uri,
+            } => write!(f, "namespace prefix {} ({}) is never used", prefix, uri),
+            UnusedNamespace {
+                prefix: None,
+                ref uri,
+            } => write!(f, "default namespace {} is never used", uri),
+            RelativeNamespaceUri(ref uri) => write!(f, "namespace URI {} is relative", uri),
+            DocumentTypeMismatch {
+                ref document_type,
+                ref root,
+            } => write!(
+                f,
+                "document type {} does not match root element {}",
+                document_type, root
+            ),
+            EncodingMismatch(ref encoding) => {
+                write!(f, "declared encoding {} does not match the input", encoding)
+            }
         }
     }
+}
+
+/// Whether text in the declared encoding could have been decoded as
+/// the given string without changing its meaning.
+fn encoding_describes(encoding: &str, xml: &str) -> bool {
+    let encoding = encoding.to_ascii_uppercase();
+
+    if encoding == "UTF-8" || encoding == "UTF8" {
+        return true;
+    }
 
+    let ascii_superset = ["US-ASCII", "ASCII"].contains(&&encoding[..])
+        || encoding.starts_with("ISO-8859-")
+        || encoding.starts_with("WINDOWS-125");
+
+    ascii_superset && xml.is_ascii()
+}
+
+/// Parses a string into a DOM. On failure, the location of the
+/// parsing failure and all possible failures will be returned.
+pub fn parse(xml: &str) -> Result<super::Package, Error> {
+    let package = super::Package::new();
+    parse_with_sink(
+        xml,
+        &mut DomBuilder {
+            doc: package.as_document(),
+        },
+    )?;
     Ok(package)
 }
 
-type DomBuilderResult<T> = Result<T, Span<SpecificError>>;
+/// Parses a string into a DOM that refers to the string instead of
+/// copying it. See `BorrowedPackage` for details.
+pub fn parse_borrowed(xml: &str) -> Result<super::BorrowedPackage<'_>, Error> {
+    let package = super::BorrowedPackage::new(xml);
+    parse_with_sink(
+        xml,
+        &mut DomBuilder {
+            doc: package.as_document(),
+        },
+    )?;
+    Ok(package)
+}
 
-fn decode_reference<F>(ref_data: Reference<'_>, cb: F) -> DomBuilderResult<()>
+/// Parses a string, passing the nodes to the sink instead of
+/// building a DOM. On failure, the location of the parsing failure
+/// and all possible failures will be returned.
+pub fn parse_with_sink<S>(xml: &str, sink: &mut S) -> Result<(), Error>
 where
-    F: FnOnce(&str),
+    S: TreeSink,
 {
-    match ref_data {
-        DecimalChar(span) => u32::from_str_radix(span.value, 10)
-            .ok()
-            .and_then(char::from_u32)
+    build_tree(xml, sink, &Parser::new()).map(|_| ())
+}
+
+/// The warnings found while building a tree and, if requested, the
+/// text of each node
+type BuiltTree<'a, H> = (Vec<Warning>, Option<LexicalRecorder<'a, H>>);
+
+fn build_tree<'a, S>(
+    xml: &'a str,
+    sink: &mut S,
+    options: &Parser,
+) -> Result<BuiltTree<'a, S::Handle>, Error>
+where
+    S: TreeSink,
+{
+    let mut parser = PullParser::new(xml);
+    let mut builder = TreeBuilder::new(sink, xml, options);
+
+    loop {
+        let start = parser.offset();
+        let token = match parser.next() {
+            Some(token) => token?,
+            None => break,
+        };
+        builder.consume(token, start, parser.offset())?;
+    }
+
+    if builder.has_unclosed_elements() {
+        return Err(Error::new(xml.len(), SpecificError::UnclosedElement));
+    }
+
+    let mut warnings = builder.warnings;
+    warnings.sort_by_key(Warning::location);
+    Ok((warnings, builder.lexical))
+}
+
+/// Parses a string into a DOM, specifying some parsing options
+///
+/// ```
+/// use sxd_document::{parser::Parser, writer::Writer};
+///
+/// let xml = r#"<?xml version="1.0"?>
+/// <config  mode="fast" >&#160;<value/></config>"#;
+///
+/// let package = Parser::new()
+///     .set_preserve_lexical_form(true)
+///     .parse(xml)
+///     .expect("Failed to parse");
+/// let doc = package.as_document();
+///
+/// let config = doc.root().children()[0].element().unwrap();
+/// config.set_attribute_value("mode", "safe");
+///
+/// let mut output = Vec::new();
+/// Writer::new().format_document(&doc, &mut output).expect("Failed to write");
+/// assert_eq!(
+///     String::from_utf8(output).unwrap(),
+///     r#"<?xml version="1.0"?>
+/// <config  mode="safe" >&#160;<value/></config>"#
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Parser {
+    preserve_lexical_form: bool,
+    expand_entities: bool,
+    catalog: Option<Catalog>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser {
+            preserve_lexical_form: false,
+            expand_entities: true,
+            catalog: None,
+        }
+    }
+}
+
+impl Parser {
+    /// Create a new `Parser` with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the original text of each node should be
+    /// recorded. The `Writer` reproduces the recorded text of nodes
+    /// that have not been modified, instead of formatting them anew.
+    pub fn set_preserve_lexical_form(mut self, preserve_lexical_form: bool) -> Self {
+        self.preserve_lexical_form = preserve_lexical_form;
+        self
+    }
+
+    /// Set whether references to general entities should be replaced
+    /// by their replacement text. When not expanded, each reference
+    /// becomes a `dom::EntityReference`, which is written back as
+    /// `&name;`. The predefined entities, such as `&amp;`, are always
+    /// expanded. Defaults to true.
+    pub fn set_expand_entities(mut self, expand_entities: bool) -> Self {
+        self.expand_entities = expand_entities;
+        self
+    }
+
+    /// Set the catalog used to find a local copy of the external
+    /// subset named by the document type declaration. The result is
+    /// available from `dom::DocumentType::location`.
+    pub fn set_catalog(mut self, catalog: Catalog) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    /// Parses a string into a DOM. On failure, the location of the
+    /// parsing failure and all possible failures will be returned.
+    pub fn parse(&self, xml: &str) -> Result<super::Package, Error> {
+        self.parse_with_warnings(xml).map(|(package, _)| package)
+    }
+
+    /// Parses a string into a DOM, also returning any problems that
+    /// did not prevent parsing, ordered by location.
+    ///
+    /// ```
+    /// use sxd_document::parser::{Parser, WarningKind};
+    ///
+    /// let xml = r#"<data xmlns:unused="urn:example"/>"#;
+    /// let (_package, warnings) = Parser::new()
+    ///     .parse_with_warnings(xml)
+    ///     .expect("Failed to parse");
+    ///
+    /// assert_eq!(
+    ///     warnings[0].kind(),
+    ///     &WarningKind::UnusedNamespace {
+    ///         prefix: Some("unused".into()),
+    ///         uri: "urn:example".into(),
+    ///     }
+    /// );
+    /// ```
+    pub fn parse_with_warnings(&self, xml: &str) -> Result<(super::Package, Vec<Warning>), Error> {
+        let package = super::Package::new();
+        let (warnings, lexical) = {
+            let mut builder = DomBuilder {
+                doc: package.as_document(),
+            };
+            build_tree(xml, &mut builder, self)?
+        };
+        if let Some(lexical) = lexical {
+            lexical.apply(package.as_document().root());
+        }
+        Ok((package, warnings))
+    }
+}
+
+/// An attribute or namespace declaration as it appeared in a start tag
+struct LexicalAttribute<'a> {
+    name: PrefixedName<'a>,
+    text: &'a str,
+    value_start: usize,
+}
+
+struct LexicalTag<'a> {
+    start: usize,
+    open: &'a str,
+    attributes: Vec<LexicalAttribute<'a>>,
+    close: &'a str,
+}
+
+enum LexicalNode<'a> {
+    /// The text of a text node, comment or processing instruction
+    Text(&'a str),
+    /// The start tag of an element and its end tag, if any
+    Element(LexicalTag<'a>, Option<&'a str>),
+}
+
+/// Records the text of each node as it is built, so that the text of
+/// unmodified nodes can be reproduced when writing
+struct LexicalRecorder<'a, H> {
+    xml: &'a str,
+    prolog: Option<&'a str>,
+    leading: Vec<(H, &'a str)>,
+    // The end of the most recent child of the root
+    root_gap_start: usize,
+    start_tag: Option<LexicalTag<'a>>,
+    attribute: Option<(PrefixedName<'a>, usize, usize)>,
+    open: Vec<(H, LexicalTag<'a>)>,
+    nodes: Vec<(H, LexicalNode<'a>)>,
+}
+
+impl<'a, H> LexicalRecorder<'a, H>
+where
+    H: Clone,
+{
+    fn new(xml: &'a str) -> LexicalRecorder<'a, H> {
+        LexicalRecorder {
+            xml,
+            prolog: None,
+            leading: Vec::new(),
+            root_gap_start: 0,
+            start_tag: None,
+            attribute: None,
+            open: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Records the text of a token. `created` is the node that the
+    /// token completed, if any.
+    fn consume(&mut self, token: Token<'a>, start: usize, end: usize, created: Option<H>) {
+        use self::Token::*;
+
+        let text = &self.xml[start..end];
+
+        match token {
+            ElementStart(..) => {
+                self.start_tag = Some(LexicalTag {
+                    start,
+                    open: text,
+                    attributes: Vec::new(),
+                    close: "",
+                });
+            }
+
+            AttributeStart(name, _) => {
+                self.attribute = Some((name.value, start, end - start));
+            }
+
+            AttributeEnd => {
+                let (name, attribute_start, value_start) =
+                    self.attribute.take().expect("No attribute was started");
+                let tag = self.start_tag.as_mut().expect("No start tag was started");
+                tag.attributes.push(LexicalAttribute {
+                    name,
+                    text: &self.xml[attribute_start..end],
+                    value_start,
+                });
+            }
+
+            ElementStartClose | ElementSelfClose => {
+                let mut tag = self.start_tag.take().expect("No start tag was started");
+                tag.close = text;
+                let element = created.expect("The start tag did not create an element");
+                self.add_child(&element, tag.start);
+
+                if let ElementSelfClose = token {
+                    self.nodes.push((element, LexicalNode::Element(tag, None)));
+                    self.finish_child(end);
+                } else {
+                    self.open.push((element, tag));
+                }
+            }
+
+            ElementClose(..) => {
+                let (element, tag) = self.open.pop().expect("No element was open");
+                self.nodes
+                    .push((element, LexicalNode::Element(tag, Some(text))));
+                self.finish_child(end);
+            }
+
+            CharData(..)
+            | CData(..)
+            | ContentReference(..)
+            | Comment(..)
+            | ProcessingInstruction(..) => {
+                if let Some(node) = created {
+                    self.add_child(&node, start);
+                    self.nodes.push((node, LexicalNode::Text(text)));
+                    self.finish_child(end);
+                }
+            }
+
+            XmlDeclaration(..)
+            | DocumentTypeDeclaration(..)
+            | Whitespace(..)
+            | LiteralAttributeValue(..)
+            | ReferenceAttributeValue(..) => {}
+        }
+    }
+
+    /// Records the text between the children of the root
+    fn add_child(&mut self, node: &H, start: usize) {
+        if self.open.is_empty() {
+            let gap = &self.xml[self.root_gap_start..start];
+            if self.prolog.is_none() {
+                self.prolog = Some(gap);
+            } else {
+                self.leading.push((node.clone(), gap));
+            }
+        }
+    }
+
+    fn finish_child(&mut self, end: usize) {
+        if self.open.is_empty() {
+            self.root_gap_start = end;
+        }
+    }
+}
+
+impl<'a, 'd> LexicalRecorder<'a, DomHandle<'d>> {
+    fn apply(self, root: dom::Root<'d>) {
+        for (node, lexical) in self.nodes {
+            match (node, lexical) {
+                (DomHandle::Element(element), LexicalNode::Element(tag, end)) => {
+                    let items = tag
+                        .attributes
+                        .into_iter()
+                        .map(|attribute| lexical_item(element, attribute))
+                        .collect();
+                    element.set_lexical(Some(dom::LexicalElement {
+                        open: tag.open,
+                        items,
+                        close: tag.close,
+                        end,
+                    }));
+                }
+                (DomHandle::Text(t), LexicalNode::Text(text)) => t.set_lexical(Some(text)),
+                (DomHandle::Comment(c), LexicalNode::Text(text)) => c.set_lexical(Some(text)),
+                (DomHandle::ProcessingInstruction(pi), LexicalNode::Text(text)) => {
+                    pi.set_lexical(Some(text))
+                }
+                _ => {}
+            }
+        }
+
+        let leading = self
+            .leading
+            .into_iter()
+            .filter_map(|(node, gap)| match node {
+                DomHandle::Element(n) => Some((n.into(), gap)),
+                DomHandle::Comment(n) => Some((n.into(), gap)),
+                DomHandle::ProcessingInstruction(n) => Some((n.into(), gap)),
+                _ => None,
+            })
+            .collect();
+
+        root.set_lexical(Some(dom::LexicalRoot {
+            prolog: self.prolog.unwrap_or(""),
+            leading,
+            trailing: &self.xml[self.root_gap_start..],
+        }));
+    }
+}
+
+fn lexical_item<'s, 'd: 's>(
+    element: dom::Element<'d>,
+    attribute: LexicalAttribute<'s>,
+) -> dom::LexicalItem<'s, 'd> {
+    let LexicalAttribute {
+        name,
+        text,
+        value_start,
+    } = attribute;
+
+    match (name.prefix, name.local_part) {
+        (None, "xmlns") => dom::LexicalItem::Namespace {
+            prefix: None,
+            uri: element.default_namespace_uri(),
+            text,
+        },
+        (Some("xmlns"), prefix) => dom::LexicalItem::Namespace {
+            prefix: Some(prefix),
+            uri: element.namespace_uri_for_prefix(prefix),
+            text,
+        },
+        (prefix, local_part) => {
+            let attribute = element
+                .attributes()
+                .into_iter()
+                .find(|a| a.preferred_prefix() == prefix && a.name().local_part == local_part)
+                .expect("The attribute was not created");
+            dom::LexicalItem::Attribute {
+                attribute,
+                prefix,
+                unchanged: true,
+                text,
+                value_start,
+            }
+        }
+    }
+}
+
+// A token may only be complete once the input after it is known. The
+// furthest any token looks is a prefixed name, which needs to see a
+// colon and the first character of the local part.
+const PUSH_PARSER_LOOKAHEAD: usize = 2;
+
+/// An event produced by a [`PushParser`](struct.PushParser.html).
+///
+/// Names are reported as they are written in the document, including
+/// any prefix; namespace prefixes are not resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// An opening or self-closing tag. Attributes are in document
+    /// order and their values have references decoded.
+    StartElement {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    /// A closing tag. A self-closing tag produces a `StartElement`
+    /// immediately followed by an `EndElement`.
+    EndElement {
+        name: String,
+    },
+    /// Character data, a CDATA section or a decoded reference. A
+    /// single run of text may be reported as several consecutive
+    /// events.
+    Text(String),
+    Comment(String),
+    ProcessingInstruction {
+        target: String,
+        value: Option<String>,
+    },
+}
+
+/// Parses XML that arrives in chunks of arbitrary size.
+///
+/// Each call to `feed` returns the events for the constructs that
+/// have been completely received. Tokens, UTF-8 sequences and
+/// references may be split across chunk boundaries. A construct that
+/// reaches the end of the available input is held back until more
+/// input arrives or `finish` is called, as it might still continue;
+/// text is reported as far as it has arrived.
+///
+/// Error locations are byte offsets from the start of the stream. A
+/// syntax error is reported as soon as input after it has arrived,
+/// except inside a comment, processing instruction, CDATA section or
+/// document type declaration, which can only be checked once its end
+/// has arrived, at the latest by `finish`.
+///
+/// ### Example
+///
+/// ```
+/// use sxd_document::parser::{Event, PushParser};
+///
+/// let mut parser = PushParser::new();
+/// let mut events = Vec::new();
+///
+/// for chunk in &["<greeting lang='e", "n'>Hello &am", "p; goodbye</greeting>"] {
+///     events.extend(parser.feed(chunk.as_bytes()).expect("Failed to parse"));
+/// }
+/// events.extend(parser.finish().expect("Failed to parse"));
+///
+/// assert_eq!(
+///     events[0],
+///     Event::StartElement {
+///         name: "greeting".into(),
+///         attributes: vec![("lang".into(), "en".into())],
+///     }
+/// );
+/// assert_eq!(events.last(), Some(&Event::EndElement { name: "greeting".into() }));
+/// ```
+#[derive(Debug)]
+pub struct PushParser {
+    buffer: String,
+    incomplete: Vec<u8>,
+    consumed: usize,
+    state: State,
+    builder: EventBuilder,
+}
+
+impl PushParser {
+    pub fn new() -> PushParser {
+        PushParser {
+            buffer: String::new(),
+            incomplete: Vec::new(),
+            consumed: 0,
+            state: State::AtBeginning,
+            builder: EventBuilder::default(),
+        }
+    }
+
+    /// Adds the next chunk of input, returning the events that it
+    /// completed.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<Event>, Error> {
+        self.incomplete.extend_from_slice(data);
+
+        let valid = match str::from_utf8(&self.incomplete) {
+            Ok(s) => s.len(),
+            Err(e) => {
+                if e.error_len().is_some() {
+                    let location = self.consumed + self.buffer.len() + e.valid_up_to();
+                    return Err(Error::new(location, SpecificError::InvalidUtf8));
+                }
+                // The remaining bytes are the start of a sequence
+                // that continues in the next chunk
+                e.valid_up_to()
+            }
+        };
+
+        let text = str::from_utf8(&self.incomplete[..valid]).expect("Input was already validated");
+        self.buffer.push_str(text);
+        self.incomplete.drain(..valid);
+
+        self.process(false)
+    }
+
+    /// Signals the end of the input, returning any remaining events.
+    pub fn finish(mut self) -> Result<Vec<Event>, Error> {
+        if !self.incomplete.is_empty() {
+            let location = self.consumed + self.buffer.len();
+            return Err(Error::new(location, SpecificError::InvalidUtf8));
+        }
+
+        let events = self.process(true)?;
+
+        if self.builder.has_unclosed_elements() {
+            let location = self.consumed + self.buffer.len();
+            return Err(Error::new(location, SpecificError::UnclosedElement));
+        }
+
+        Ok(events)
+    }
+
+    fn process(&mut self, at_end: bool) -> Result<Vec<Event>, Error> {
+        let base = self.consumed;
+        let mut events = Vec::new();
+        let mut consumed = 0;
+
+        {
+            let mut parser = PullParser {
+                pm: ParseMaster::new(),
+                xml: StringPoint::new(&self.buffer),
+                state: self.state,
+            };
+
+            loop {
+                let start = parser.xml.offset;
+                let token = match parser.next() {
+                    Some(Ok(token)) => token,
+                    Some(Err((offset, errors))) => {
+                        if at_end || !may_be_incomplete(&self.buffer, start, offset) {
+                            return Err(Error::from((base + offset, errors)));
+                        }
+                        break;
+                    }
+                    None => break,
+                };
+
+                if let Token::CharData(text) = token {
+                    // Text is complete once something follows it, and
+                    // otherwise everything but a possible start of `]]>`
+                    // can be reported now
+                    if !at_end && parser.xml.s.is_empty() {
+                        let held = text.len() - text.trim_end_matches(']').len();
+                        let text = &text[..text.len() - held.min(2)];
+                        if !text.is_empty() {
+                            self.builder
+                                .consume(Token::CharData(text), &mut events)
+                                .map_err(|e| Error::new(base + e.offset, e.value))?;
+                            self.state = parser.state;
+                            consumed = start + text.len();
+                        }
+                        break;
+                    }
+                } else if !at_end && parser.xml.s.len() < PUSH_PARSER_LOOKAHEAD {
+                    break;
+                }
+
+                self.builder
+                    .consume(token, &mut events)
+                    .map_err(|e| Error::new(base + e.offset, e.value))?;
+
+                self.state = parser.state;
+                consumed = parser.xml.offset;
+            }
+        }
+
+        self.buffer.drain(..consumed);
+        self.consumed += consumed;
+
+        Ok(events)
+    }
+}
+
+/// Whether a token starting at `start` that failed at `offset` might
+/// still succeed once more input arrives. Comments, processing
+/// instructions, CDATA sections and document type declarations fail
+/// at their start until their end has arrived; any other construct
+/// fails close to the end of the input.
+fn may_be_incomplete(buffer: &str, start: usize, offset: usize) -> bool {
+    let rest = &buffer[start..];
+
+    // The internal subset ends at the first `]`
+    let doctype_end = match (rest.find('['), rest.find('>')) {
+        (Some(open), Some(close)) if open < close => "]",
+        _ => ">",
+    };
+
+    let terminators = [
+        ("<!--", "-->"),
+        ("<![CDATA[", "]]>"),
+        ("<!DOCTYPE", doctype_end),
+        ("<?", "?>"),
+    ];
+    for &(opening, closing) in &terminators {
+        if opening.starts_with(rest) {
+            return true;
+        }
+        if let Some(body) = rest.strip_prefix(opening) {
+            return match body.find(closing) {
+                Some(end) if closing == "]" => !body[end..].contains('>'),
+                Some(_) => false,
+                None => true,
+            };
+        }
+    }
+
+    offset + PUSH_PARSER_LOOKAHEAD >= buffer.len()
+}
+
+impl Default for PushParser {
+    fn default() -> Self {
+        PushParser::new()
+    }
+}
+
+#[derive(Debug, Default)]
+struct EventBuilder {
+    element_names: Vec<String>,
+    attributes: Vec<(String, String)>,
+}
+
+impl EventBuilder {
+    fn has_unclosed_elements(&self) -> bool {
+        !self.element_names.is_empty()
+    }
+
+    fn add_attribute_value(&mut self, v: &str) {
+        let a = self
+            .attributes
+            .last_mut()
+            .expect("Cannot add attribute value without an attribute");
+        a.1.push_str(v);
+    }
+
+    fn start_element(&mut self, events: &mut Vec<Event>) {
+        let name = self.element_names.last().expect("Unknown element name");
+        events.push(Event::StartElement {
+            name: name.clone(),
+            attributes: self.attributes.drain(..).collect(),
+        });
+    }
+
+    fn consume(&mut self, token: Token<'_>, events: &mut Vec<Event>) -> DomBuilderResult<()> {
+        use self::Token::*;
+
+        match token {
+            XmlDeclaration(..) => {}
+
+            DocumentTypeDeclaration(..) => {}
+
+            ElementStart(n) => {
+                self.element_names.push(qualified_name(n.value));
+            }
+
+            ElementStartClose => {
+                self.start_element(events);
+            }
+
+            ElementSelfClose => {
+                self.start_element(events);
+
+                let name = self.element_names.pop().expect("No open element");
+                events.push(Event::EndElement { name });
+            }
+
+            ElementClose(n) => {
+                let open_name = self.element_names.pop().expect("No open element");
+
+                if qualified_name(n.value) != open_name {
+                    return Err(n.map(|_| SpecificError::MismatchedElementEndName));
+                }
+
+                events.push(Event::EndElement { name: open_name });
+            }
+
+            AttributeStart(n, _) => {
+                let name = qualified_name(n.value);
+
+                if self.attributes.iter().any(|a| a.0 == name) {
+                    return Err(n.map(|_| SpecificError::DuplicateAttribute));
+                }
+
+                self.attributes.push((name, String::new()));
+            }
+
+            LiteralAttributeValue(v) => {
+                self.add_attribute_value(v);
+            }
+
+            ReferenceAttributeValue(v) => {
+                decode_reference(v, |s| self.add_attribute_value(s))?;
+            }
+
+            AttributeEnd => {}
+
+            Whitespace(..) => {}
+
+            CharData(t) | CData(t) => events.push(Event::Text(t.to_owned())),
+
+            ContentReference(t) => {
+                decode_reference(t, |s| events.push(Event::Text(s.to_owned())))?;
+            }
+
+            Comment(c) => events.push(Event::Comment(c.to_owned())),
+
+            ProcessingInstruction(t, v) => events.push(Event::ProcessingInstruction {
+                target: t.to_owned(),
+                value: v.map(ToOwned::to_owned),
+            }),
+        };
+
+        Ok(())
+    }
+}
+
+fn qualified_name(name: PrefixedName<'_>) -> String {
+    match name.prefix {
+        Some(prefix) => format!("{}:{}", prefix, name.local_part),
+        None => name.local_part.to_owned(),
+    }
+}
+
+type DomBuilderResult<T> = Result<T, Span<SpecificError>>;
+
+fn decode_reference<F>(ref_data: Reference<'_>, cb: F) -> DomBuilderResult<()>
+where
+    F: FnOnce(&str),
+{
+    match ref_data {
+        DecimalChar(span) => u32::from_str_radix(span.value, 10)
+            .ok()
+            .and_then(char::from_u32)
             .ok_or_else(|| span.map(|_| SpecificError::InvalidDecimalReference))
             .and_then(|c| {
                 let s: String = iter::repeat(c).take(1).collect();
@@ -1332,13 +2759,13 @@ impl<'a> DeferredAttributes<'a> {
         &self.namespaces
     }
 
-    fn default_namespace(&self) -> DomBuilderResult<Option<String>> {
+    fn default_namespace(&self) -> DomBuilderResult<Option<Span<String>>> {
         match self.default_namespaces.len() {
             0 => Ok(None),
             1 => {
                 let ns = &self.default_namespaces[0];
                 let value = AttributeValueBuilder::convert(&ns.values)?;
-                Ok(Some(value))
+                Ok(Some(ns.name.map(|_| value)))
             }
             _ => {
                 let last_namespace = self.default_namespaces.last().unwrap();
@@ -1430,6 +2857,79 @@ mod test {
         assert_qname_eq!(top.name(), "hello");
     }
 
+    #[test]
+    fn a_prolog_with_a_doc_type_declaration_public_id() {
+        let package = quick_parse(
+            r#"<?xml version='1.0'?>
+        <!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" 'http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd'>
+        <hello/>"#,
+        );
+        let doc = package.as_document();
+        let top = top(&doc);
+        let document_type = doc.root().document_type().expect("No document type");
+
+        assert_qname_eq!(top.name(), "hello");
+        assert_eq!(document_type.name(), "html");
+        assert_eq!(
+            document_type.public_id(),
+            Some("-//W3C//DTD XHTML 1.0 Strict//EN")
+        );
+        assert_eq!(
+            document_type.location(),
+            Some("http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd")
+        );
+    }
+
+    #[test]
+    fn a_prolog_with_a_doc_type_declaration_empty_public_id() {
+        let package = quick_parse(
+            r#"<?xml version='1.0'?>
+        <!DOCTYPE a PUBLIC "" "a.dtd">
+        <a/>"#,
+        );
+        let doc = package.as_document();
+        let document_type = doc.root().document_type().expect("No document type");
+
+        assert_eq!(document_type.public_id(), Some(""));
+        assert_eq!(document_type.system_id(), Some("a.dtd"));
+    }
+
+    #[test]
+    fn a_catalog_remaps_the_doc_type_declaration_public_id() {
+        let mut resolver = |_: &str| {
+            Ok(
+                r#"<catalog xmlns="urn:oasis:names:tc:entity:xmlns:xml:catalog">
+                  <public publicId="-//Example//DTD Doc//EN" uri="dtds/doc.dtd"/>
+                </catalog>"#
+                    .to_owned(),
+            )
+        };
+        let mut catalog = Catalog::new();
+        catalog
+            .load("file:///etc/xml/catalog", &mut resolver)
+            .expect("Unable to load catalog");
+
+        let package = Parser::new()
+            .set_catalog(catalog)
+            .parse(
+                r#"<?xml version='1.0'?>
+        <!DOCTYPE doc PUBLIC "-//Example//DTD Doc//EN" "http://example.com/doc.dtd">
+        <doc/>"#,
+            )
+            .expect("Failed to parse");
+        let doc = package.as_document();
+        let document_type = doc.root().document_type().expect("No document type");
+
+        assert_eq!(
+            document_type.system_id(),
+            Some("http://example.com/doc.dtd")
+        );
+        assert_eq!(
+            document_type.location(),
+            Some("file:///etc/xml/dtds/doc.dtd")
+        );
+    }
+
     #[test]
     fn a_prolog_with_a_doc_type_declaration_int_subset() {
         let package = quick_parse(
@@ -1707,6 +3207,17 @@ mod test {
         assert_qname_eq!(world.name(), "world");
     }
 
+    #[test]
+    fn children_of_reset_default_namespace_have_no_namespace() {
+        let package = quick_parse("<hello xmlns='outer'><world xmlns=''><moon/></world></hello>");
+        let doc = package.as_document();
+        let hello = top(&doc);
+        let world = hello.children()[0].element().unwrap();
+        let moon = world.children()[0].element().unwrap();
+
+        assert_qname_eq!(moon.name(), "moon");
+    }
+
     #[test]
     fn nested_elements_with_attributes() {
         let package = quick_parse("<hello><world name='Earth'/></hello>");
@@ -1871,6 +3382,37 @@ mod test {
         assert_eq!(text3.text(), "3 math");
     }
 
+    #[test]
+    fn element_with_unexpanded_entity_reference() {
+        let package = Parser::new()
+            .set_expand_entities(false)
+            .parse(
+                r#"<?xml version="1.0"?><!DOCTYPE note [
+                <!ENTITY % param "ignored">
+                <!ENTITY company "Acme &#38; Co &#x2122;">
+                <!ENTITY company "Redeclared">
+                <!ENTITY logo SYSTEM "logo.xml">
+                ]><note>&company; &logo;&amp;&missing;</note>"#,
+            )
+            .expect("Failed to parse");
+        let doc = package.as_document();
+        let note = top(&doc);
+        let children = note.children();
+
+        let company = children[0].entity_reference().unwrap();
+        assert_eq!(company.name(), "company");
+        assert_eq!(company.value(), Some("Acme & Co \u{2122}"));
+        assert_eq!(children[1].text().unwrap().text(), " ");
+        let logo = children[2].entity_reference().unwrap();
+        assert_eq!(logo.name(), "logo");
+        assert_eq!(logo.value(), None);
+        assert_eq!(children[3].text().unwrap().text(), "&");
+        let missing = children[4].entity_reference().unwrap();
+        assert_eq!(missing.name(), "missing");
+        assert_eq!(missing.value(), None);
+        assert_eq!(company.parent(), Some(note));
+    }
+
     #[test]
     fn element_with_mixed_children() {
         let package = quick_parse("<hello>to <!--fixme--><a><![CDATA[the]]></a><?world?></hello>");
@@ -2176,6 +3718,132 @@ mod test {
         assert_parse_failure!(r, 9, DuplicateAttribute);
     }
 
+    #[test]
+    fn failure_duplicate_attribute_with_different_prefixes() {
+        use super::SpecificError::*;
+
+        let r = full_parse("<a xmlns:b='u' xmlns:c='u' b:x='1' c:x='2'/>");
+
+        assert_parse_failure!(
+            r,
+            35,
+            DuplicateExpandedAttribute("b:x".into(), "c:x".into())
+        );
+    }
+
+    #[test]
+    fn attributes_with_same_local_name_in_different_namespaces() {
+        let package = quick_parse("<a xmlns:b='u' xmlns:c='v' b:x='1' c:x='2' x='3'/>");
+        let doc = package.as_document();
+        let top = top(&doc);
+
+        assert_eq!(top.attribute_value(("u", "x")), Some("1"));
+        assert_eq!(top.attribute_value(("v", "x")), Some("2"));
+        assert_eq!(top.attribute_value("x"), Some("3"));
+    }
+
+    fn parse_warnings(xml: &str) -> Vec<Warning> {
+        let (_, warnings) = Parser::new()
+            .parse_with_warnings(xml)
+            .expect("Failed to parse the XML string");
+        warnings
+    }
+
+    #[test]
+    fn warning_unused_namespace() {
+        let warnings = parse_warnings("<p:a xmlns:p='urn:p' xmlns:b='urn:b' xmlns='urn:d'/>");
+
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|w| (w.location(), w.kind().clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    21,
+                    WarningKind::UnusedNamespace {
+                        prefix: Some("b".into()),
+                        uri: "urn:b".into()
+                    }
+                ),
+                (
+                    37,
+                    WarningKind::UnusedNamespace {
+                        prefix: None,
+                        uri: "urn:d".into()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn namespaces_used_by_attributes_or_descendants_are_not_warned_about() {
+        let warnings =
+            parse_warnings("<a xmlns:b='urn:b' xmlns:c='urn:c' xmlns=''><d b:x='1'><c:e/></d></a>");
+
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn warning_relative_namespace_uri() {
+        let warnings = parse_warnings("<a xmlns='relative/path'/>");
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].location(), 3);
+        assert_eq!(
+            warnings[0].kind(),
+            &WarningKind::RelativeNamespaceUri("relative/path".into())
+        );
+    }
+
+    #[test]
+    fn warning_document_type_mismatch() {
+        let warnings =
+            parse_warnings("<?xml version='1.0'?><!DOCTYPE html><p:body xmlns:p='urn:p'/>");
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].location(), 37);
+        assert_eq!(
+            warnings[0].kind(),
+            &WarningKind::DocumentTypeMismatch {
+                document_type: "html".into(),
+                root: "p:body".into()
+            }
+        );
+    }
+
+    #[test]
+    fn warning_encoding_mismatch() {
+        let warnings = parse_warnings("<?xml version='1.0' encoding='UTF-16'?><a/>");
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].location(), 30);
+        assert_eq!(
+            warnings[0].kind(),
+            &WarningKind::EncodingMismatch("UTF-16".into())
+        );
+    }
+
+    #[test]
+    fn ascii_compatible_encodings_match_ascii_input() {
+        assert_eq!(
+            parse_warnings("<?xml version='1.0' encoding='utf-8'?><a>é</a>"),
+            vec![]
+        );
+        assert_eq!(
+            parse_warnings("<?xml version='1.0' encoding='ISO-8859-1'?><a/>"),
+            vec![]
+        );
+
+        let warnings = parse_warnings("<?xml version='1.0' encoding='ISO-8859-1'?><a>é</a>");
+        assert_eq!(
+            warnings[0].kind(),
+            &WarningKind::EncodingMismatch("ISO-8859-1".into())
+        );
+    }
+
     #[test]
     fn failure_redefined_namespace() {
         use super::SpecificError::*;
@@ -2221,6 +3889,413 @@ mod test {
         assert_parse_failure!(r, 1, UnknownNamespacePrefix);
     }
 
+    // Records each call as a line of text
+    #[derive(Default)]
+    struct RecordingSink {
+        calls: Vec<String>,
+    }
+
+    impl RecordingSink {
+        fn record(&mut self, call: String) -> usize {
+            self.calls.push(call);
+            self.calls.len()
+        }
+    }
+
+    impl TreeSink for RecordingSink {
+        type Handle = usize;
+
+        fn document(&mut self) -> usize {
+            0
+        }
+
+        fn create_element(&mut self, name: QName<'_>, preferred_prefix: Option<&str>) -> usize {
+            self.record(format!("element {:?} {:?}", name, preferred_prefix))
+        }
+
+        fn add_attribute(
+            &mut self,
+            element: &usize,
+            name: QName<'_>,
+            preferred_prefix: Option<&str>,
+            value: &str,
+        ) {
+            let call = format!(
+                "attribute {} {:?} {:?} {}",
+                element, name, preferred_prefix, value
+            );
+            self.record(call);
+        }
+
+        fn register_prefix(&mut self, element: &usize, prefix: &str, namespace_uri: &str) {
+            self.record(format!("prefix {} {} {}", element, prefix, namespace_uri));
+        }
+
+        fn set_default_namespace_uri(&mut self, element: &usize, namespace_uri: Option<&str>) {
+            self.record(format!("default {} {:?}", element, namespace_uri));
+        }
+
+        fn create_text(&mut self, text: &str) -> usize {
+            self.record(format!("text {}", text))
+        }
+
+        fn create_comment(&mut self, text: &str) -> usize {
+            self.record(format!("comment {}", text))
+        }
+
+        fn create_processing_instruction(&mut self, target: &str, value: Option<&str>) -> usize {
+            self.record(format!("pi {} {:?}", target, value))
+        }
+
+        fn document_type(
+            &mut self,
+            name: &str,
+            public_id: Option<&str>,
+            system_id: Option<&str>,
+            location: Option<&str>,
+        ) {
+            let call = format!(
+                "doctype {} {:?} {:?} {:?}",
+                name, public_id, system_id, location
+            );
+            self.record(call);
+        }
+
+        fn append(&mut self, parent: &usize, child: usize) {
+            self.record(format!("append {} {}", parent, child));
+        }
+    }
+
+    #[test]
+    fn a_sink_receives_resolved_nodes_in_order() {
+        let mut sink = RecordingSink::default();
+        parse_with_sink(
+            "<!--c--><a xmlns='urn:d' xmlns:p='urn:p' p:x='1'>t&amp;<p:b xmlns=''/><?pi?></a>",
+            &mut sink,
+        )
+        .expect("Failed to parse");
+
+        let calls: Vec<_> = sink.calls.iter().map(|c| &c[..]).collect();
+        assert_eq!(
+            calls,
+            [
+                "comment c",
+                "append 0 1",
+                r#"element QName { namespace_uri: Some("urn:d"), local_part: "a" } None"#,
+                "prefix 3 p urn:p",
+                r#"default 3 Some("urn:d")"#,
+                r#"attribute 3 QName { namespace_uri: Some("urn:p"), local_part: "x" } Some("p") 1"#,
+                "append 0 3",
+                "text t",
+                "append 3 8",
+                "text &",
+                "append 3 10",
+                r#"element QName { namespace_uri: Some("urn:p"), local_part: "b" } Some("p")"#,
+                "default 12 None",
+                "append 3 12",
+                "pi pi None",
+                "append 3 15",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_sink_receives_the_document_type() {
+        let mut sink = RecordingSink::default();
+        parse_with_sink(
+            "<?xml version='1.0'?><!DOCTYPE a SYSTEM 'a.dtd'><a/>",
+            &mut sink,
+        )
+        .expect("Failed to parse");
+
+        assert_eq!(sink.calls[0], r#"doctype a None Some("a.dtd") None"#);
+    }
+
+    #[test]
+    fn parsing_with_a_sink_stops_at_the_first_failure() {
+        let mut sink = RecordingSink::default();
+        let r = parse_with_sink("<a><b:c/></a>", &mut sink);
+
+        assert_eq!(r.unwrap_err().location(), 4);
+        assert_eq!(sink.calls.len(), 2);
+    }
+
+    fn is_within(outer: &str, inner: &str) -> bool {
+        let start = outer.as_ptr() as usize;
+        let inner_start = inner.as_ptr() as usize;
+        start <= inner_start && inner_start + inner.len() <= start + outer.len()
+    }
+
+    #[test]
+    fn a_borrowed_document_refers_to_the_input() {
+        let xml = "<ns:a xmlns:ns='urn:x' b='c'>text<!--comment--><?pi value?></ns:a>";
+        let package = parse_borrowed(xml).expect("Failed to parse");
+        let doc = package.as_document();
+        let a = top(&doc);
+        let children = a.children();
+
+        assert!(is_within(xml, a.name().local_part()));
+        assert!(is_within(xml, a.attribute_value("b").unwrap()));
+        assert!(is_within(xml, children[0].text().unwrap().text()));
+        assert!(is_within(xml, children[1].comment().unwrap().text()));
+        assert!(is_within(
+            xml,
+            children[2].processing_instruction().unwrap().target()
+        ));
+    }
+
+    #[test]
+    fn a_borrowed_document_copies_decoded_content() {
+        let xml = "<a b='1 &amp; 2'>&lt;</a>";
+        let package = parse_borrowed(xml).expect("Failed to parse");
+        let doc = package.as_document();
+        let a = top(&doc);
+
+        let value = a.attribute_value("b").unwrap();
+        assert_eq!(value, "1 & 2");
+        assert!(!is_within(xml, value));
+
+        let text = a.children()[0].text().unwrap().text();
+        assert_eq!(text, "<");
+        assert!(!is_within(xml, text));
+    }
+
+    #[test]
+    fn a_borrowed_document_can_be_modified() {
+        let xml = "<a>hello</a>";
+        let package = parse_borrowed(xml).expect("Failed to parse");
+        let doc = package.as_document();
+        let a = top(&doc);
+
+        a.set_attribute_value("greeting", "hi");
+        a.children()[0].text().unwrap().set_text("goodbye");
+
+        assert_eq!(a.attribute_value("greeting"), Some("hi"));
+        assert_eq!(a.children()[0].text().unwrap().text(), "goodbye");
+    }
+
+    #[test]
+    #[cfg(feature = "compile_failure")]
+    fn a_borrowed_document_cannot_outlive_the_input() {
+        let _ = {
+            let xml = String::from("<a/>");
+            parse_borrowed(&xml).expect("Failed to parse")
+        };
+    }
+
+    fn push_parse(chunks: &[&[u8]]) -> Result<Vec<Event>, Error> {
+        let mut parser = PushParser::new();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            events.extend(parser.feed(chunk)?);
+        }
+        events.extend(parser.finish()?);
+        Ok(events)
+    }
+
+    fn push_parse_bytewise(xml: &str) -> Result<Vec<Event>, Error> {
+        let chunks: Vec<_> = xml.as_bytes().chunks(1).collect();
+        push_parse(&chunks)
+    }
+
+    /// Combines adjacent text, which may be split at any point
+    fn merge_text(events: Vec<Event>) -> Vec<Event> {
+        let mut merged = Vec::new();
+        for event in events {
+            match (merged.last_mut(), event) {
+                (Some(Event::Text(previous)), Event::Text(t)) => previous.push_str(&t),
+                (_, event) => merged.push(event),
+            }
+        }
+        merged
+    }
+
+    fn start(name: &str, attributes: &[(&str, &str)]) -> Event {
+        Event::StartElement {
+            name: name.into(),
+            attributes: attributes
+                .iter()
+                .map(|&(n, v)| (n.into(), v.into()))
+                .collect(),
+        }
+    }
+
+    fn end(name: &str) -> Event {
+        Event::EndElement { name: name.into() }
+    }
+
+    fn text(t: &str) -> Event {
+        Event::Text(t.into())
+    }
+
+    #[test]
+    fn push_parser_produces_events() {
+        let xml = "<?xml version='1.0'?><!--c--><a x='1' y=\"&lt;2\"><b/>hi<![CDATA[<]]>&amp;<?pi v?></a>";
+        let events = push_parse(&[xml.as_bytes()]).unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Comment("c".into()),
+                start("a", &[("x", "1"), ("y", "<2")]),
+                start("b", &[]),
+                end("b"),
+                text("hi"),
+                text("<"),
+                text("&"),
+                Event::ProcessingInstruction {
+                    target: "pi".into(),
+                    value: Some("v".into()),
+                },
+                end("a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn push_parser_is_independent_of_chunk_boundaries() {
+        let xml = "<?xml version='1.0' encoding='UTF-8'?>\n<!DOCTYPE a>\n\
+                   <ns:a xmlns:ns='urn:x' b='&#x41;&#66;'>text &gt; more<c>\
+                   <![CDATA[raw]]></c><!-- note --></ns:a>\n";
+
+        let whole = push_parse(&[xml.as_bytes()]).unwrap();
+        let bytewise = push_parse_bytewise(xml).unwrap();
+
+        assert_eq!(merge_text(whole.clone()), merge_text(bytewise));
+        assert_eq!(
+            whole[0],
+            start("ns:a", &[("xmlns:ns", "urn:x"), ("b", "AB")])
+        );
+        assert_eq!(whole.last(), Some(&end("ns:a")));
+    }
+
+    #[test]
+    fn push_parser_handles_split_utf8_sequences() {
+        let xml = "<a>caf\u{e9} \u{1f600}</a>";
+        let events = push_parse_bytewise(xml).unwrap();
+
+        assert_eq!(
+            merge_text(events),
+            vec![start("a", &[]), text("caf\u{e9} \u{1f600}"), end("a")]
+        );
+    }
+
+    #[test]
+    fn push_parser_handles_split_references() {
+        let events = push_parse(&[b"<a>&a", b"m", b"p;&#x3", b"e;</a>"]).unwrap();
+
+        assert_eq!(
+            events,
+            vec![start("a", &[]), text("&"), text(">"), end("a")]
+        );
+    }
+
+    #[test]
+    fn push_parser_returns_completed_events_from_each_feed() {
+        let mut parser = PushParser::new();
+
+        assert_eq!(parser.feed(b"<a><b").unwrap(), vec![start("a", &[])]);
+        assert_eq!(
+            parser.feed(b">te").unwrap(),
+            vec![start("b", &[]), text("te")]
+        );
+        assert_eq!(parser.feed(b"xt</b>").unwrap(), vec![text("xt")]);
+        assert_eq!(parser.finish().unwrap_err().location(), 14);
+    }
+
+    #[test]
+    fn push_parser_reports_text_as_it_arrives() {
+        let mut parser = PushParser::new();
+
+        assert_eq!(
+            parser.feed(b"<a>xy").unwrap(),
+            vec![start("a", &[]), text("xy")]
+        );
+        for &byte in b"yz" {
+            let expected = (byte as char).to_string();
+            assert_eq!(parser.feed(&[byte]).unwrap(), vec![text(&expected)]);
+        }
+        // A `]` might start a `]]>`, so it waits for the next input
+        assert_eq!(parser.feed(b"]").unwrap(), vec![]);
+        assert_eq!(parser.feed(b"]").unwrap(), vec![]);
+        assert_eq!(parser.feed(b"]").unwrap(), vec![text("]")]);
+        assert_eq!(parser.feed(b"!</a>").unwrap(), vec![text("]]!")]);
+        assert_eq!(parser.finish().unwrap(), vec![end("a")]);
+    }
+
+    #[test]
+    fn push_parser_holds_back_unterminated_constructs() {
+        let mut parser = PushParser::new();
+
+        for chunk in &[
+            "<?xml version='1.0'?><!DOCTYPE a [<!ENTITY e 'x'>",
+            "]><a><!-- a long",
+            " comment --><?pi a long",
+            " value?><![CDATA[a long",
+            " section]]></a>",
+        ] {
+            parser.feed(chunk.as_bytes()).expect("Failed to parse");
+        }
+        parser.finish().expect("Failed to parse");
+    }
+
+    #[test]
+    fn push_parser_failure_reported_before_more_input() {
+        use super::SpecificError::*;
+
+        let mut parser = PushParser::new();
+        let r = parser
+            .feed(b"<a><<<")
+            .and_then(|_| parser.feed(&[b'x'; 1024]));
+
+        assert_parse_failure!(r, 4, ExpectedElementName);
+    }
+
+    #[test]
+    fn push_parser_failure_mismatched_end_name() {
+        use super::SpecificError::*;
+
+        let r = push_parse(&[b"<a><b>", b"</a>"]);
+
+        assert_parse_failure!(r, 8, MismatchedElementEndName);
+    }
+
+    #[test]
+    fn push_parser_failure_duplicate_attribute() {
+        use super::SpecificError::*;
+
+        let r = push_parse(&[b"<a b='c' ", b"b='d'/>"]);
+
+        assert_parse_failure!(r, 9, DuplicateAttribute);
+    }
+
+    #[test]
+    fn push_parser_failure_invalid_utf8() {
+        use super::SpecificError::*;
+
+        let r = push_parse(&[b"<a>", b"ab\xffc</a>"]);
+        assert_parse_failure!(r, 5, InvalidUtf8);
+
+        let r = push_parse(&[b"<a>\xc3"]);
+        assert_parse_failure!(r, 3, InvalidUtf8);
+    }
+
+    #[test]
+    fn push_parser_failure_unclosed_element() {
+        use super::SpecificError::*;
+
+        let r = push_parse(&[b"<hi>", b"wow"]);
+
+        assert_parse_failure!(r, 7, UnclosedElement);
+    }
+
+    #[test]
+    fn push_parser_failure_reported_at_finish() {
+        let r = push_parse(&[b"<a>", b"</a>junk"]);
+
+        assert_eq!(r.unwrap_err().location(), 7);
+    }
+
     #[test]
     fn failure_is_an_error() {
         fn __assert_well_behaved_error()
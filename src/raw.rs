@@ -1,7 +1,7 @@
 use super::{lazy_hash_map::LazyHashMap, QName};
 
 use crate::string_pool::{InternedString, StringPool};
-use std::{marker::PhantomData, slice};
+use std::{cmp, marker::PhantomData, slice};
 use typed_arena::Arena;
 
 struct InternedQName {
@@ -20,6 +20,67 @@ impl InternedQName {
 
 pub struct Root {
     children: Vec<ChildOfRoot>,
+    uri: Option<InternedString>,
+    document_type: Option<DocumentType>,
+    lexical: Option<RootLexical>,
+}
+
+impl Root {
+    pub fn uri(&self) -> Option<&str> {
+        self.uri.map(|u| u.as_slice())
+    }
+    pub fn document_type(&self) -> Option<&DocumentType> {
+        self.document_type.as_ref()
+    }
+    pub fn lexical(&self) -> Option<&RootLexical> {
+        self.lexical.as_ref()
+    }
+}
+
+/// The document type declaration of a parsed document
+pub struct DocumentType {
+    pub name: InternedString,
+    pub public_id: Option<InternedString>,
+    pub system_id: Option<InternedString>,
+    /// Where a catalog maps the external identifier
+    pub location: Option<InternedString>,
+}
+
+/// The text around the children of the root as it appeared in the
+/// parsed document, such as the XML declaration and whitespace
+pub struct RootLexical {
+    /// The text before the first child
+    pub prolog: InternedString,
+    /// The text before each of the other children
+    pub leading: Vec<(ChildOfRoot, InternedString)>,
+    pub trailing: InternedString,
+}
+
+/// A start tag as it appeared in the parsed document
+pub struct ElementLexical {
+    /// The `<` and the name
+    pub open: InternedString,
+    pub items: Vec<LexicalItem>,
+    /// Any whitespace and the `>` or `/>`
+    pub close: InternedString,
+    /// The end tag, if the element was not self-closing
+    pub end: Option<InternedString>,
+}
+
+/// A namespace declaration or attribute, including the whitespace
+/// before it
+pub enum LexicalItem {
+    Namespace {
+        prefix: Option<InternedString>,
+        uri: Option<InternedString>,
+        text: InternedString,
+    },
+    Attribute {
+        attribute: *mut Attribute,
+        text: InternedString,
+        /// The offset of the value within the text
+        value_start: usize,
+    },
 }
 
 pub struct Element {
@@ -30,6 +91,7 @@ pub struct Element {
     parent: Option<ParentOfChild>,
     attributes: Vec<*mut Attribute>,
     prefix_to_namespace: LazyHashMap<InternedString, InternedString>,
+    lexical: Option<Box<ElementLexical>>,
 }
 
 impl Element {
@@ -42,6 +104,16 @@ impl Element {
     pub fn preferred_prefix(&self) -> Option<&str> {
         self.preferred_prefix.map(|p| p.as_slice())
     }
+    pub fn lexical(&self) -> Option<&ElementLexical> {
+        self.lexical.as_ref().map(Box::as_ref)
+    }
+    /// The prefixes registered on this element, without those of its
+    /// ancestors
+    pub fn registered_prefixes(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.prefix_to_namespace
+            .iter()
+            .map(|(prefix, uri)| (prefix.as_slice(), uri.as_slice()))
+    }
 }
 
 pub struct Attribute {
@@ -66,29 +138,54 @@ impl Attribute {
 pub struct Text {
     text: InternedString,
     parent: Option<*mut Element>,
+    lexical: Option<InternedString>,
 }
 
 impl Text {
     pub fn text(&self) -> &str {
         &self.text
     }
+    pub fn lexical(&self) -> Option<&str> {
+        self.lexical.map(|l| l.as_slice())
+    }
+}
+
+/// A reference to a general entity that was not expanded
+pub struct EntityReference {
+    name: InternedString,
+    value: Option<InternedString>,
+    parent: Option<*mut Element>,
+}
+
+impl EntityReference {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn value(&self) -> Option<&str> {
+        self.value.map(|v| v.as_slice())
+    }
 }
 
 pub struct Comment {
     text: InternedString,
     parent: Option<ParentOfChild>,
+    lexical: Option<InternedString>,
 }
 
 impl Comment {
     pub fn text(&self) -> &str {
         &self.text
     }
+    pub fn lexical(&self) -> Option<&str> {
+        self.lexical.map(|l| l.as_slice())
+    }
 }
 
 pub struct ProcessingInstruction {
     target: InternedString,
     value: Option<InternedString>,
     parent: Option<ParentOfChild>,
+    lexical: Option<InternedString>,
 }
 
 impl ProcessingInstruction {
@@ -98,6 +195,9 @@ impl ProcessingInstruction {
     pub fn value(&self) -> Option<&str> {
         self.value.map(|v| v.as_slice())
     }
+    pub fn lexical(&self) -> Option<&str> {
+        self.lexical.map(|l| l.as_slice())
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -156,6 +256,7 @@ impl ChildOfRoot {
 pub enum ChildOfElement {
     Element(*mut Element),
     Text(*mut Text),
+    EntityReference(*mut EntityReference),
     Comment(*mut Comment),
     ProcessingInstruction(*mut ProcessingInstruction),
 }
@@ -217,6 +318,16 @@ impl ChildOfElement {
                     prev_parent_r.children.retain(|n| n != self);
                 }
 
+                n.parent = Some(parent);
+            }
+            ChildOfElement::EntityReference(n) => {
+                let n = unsafe { &mut *n };
+
+                if let Some(prev_parent) = n.parent {
+                    let prev_parent_r = unsafe { &mut *prev_parent };
+                    prev_parent_r.children.retain(|n| n != self);
+                }
+
                 n.parent = Some(parent);
             }
         };
@@ -240,6 +351,10 @@ impl ChildOfElement {
                 let n = unsafe { &mut *n };
                 n.parent = None;
             }
+            ChildOfElement::EntityReference(n) => {
+                let n = unsafe { &mut *n };
+                n.parent = None;
+            }
         };
     }
 }
@@ -266,6 +381,7 @@ conversion_trait!(
     ChildOfElement, {
         Element               => ChildOfElement::Element,
         Text                  => ChildOfElement::Text,
+        EntityReference       => ChildOfElement::EntityReference,
         Comment               => ChildOfElement::Comment,
         ProcessingInstruction => ChildOfElement::ProcessingInstruction
     }
@@ -279,6 +395,13 @@ conversion_trait!(
     }
 );
 
+conversion_trait!(
+    ParentOfChild, {
+        Root    => ParentOfChild::Root,
+        Element => ParentOfChild::Element
+    }
+);
+
 impl From<ChildOfRoot> for ChildOfElement {
     fn from(v: ChildOfRoot) -> ChildOfElement {
         match v {
@@ -291,10 +414,12 @@ impl From<ChildOfRoot> for ChildOfElement {
 
 pub struct Storage {
     strings: StringPool,
+    borrowed: Option<(usize, usize)>,
     roots: Arena<Root>,
     elements: Arena<Element>,
     attributes: Arena<Attribute>,
     texts: Arena<Text>,
+    entity_references: Arena<EntityReference>,
     comments: Arena<Comment>,
     processing_instructions: Arena<ProcessingInstruction>,
 }
@@ -303,10 +428,12 @@ impl Default for Storage {
     fn default() -> Storage {
         Storage {
             strings: StringPool::new(),
+            borrowed: None,
             roots: Arena::new(),
             elements: Arena::new(),
             attributes: Arena::new(),
             texts: Arena::new(),
+            entity_references: Arena::new(),
             comments: Arena::new(),
             processing_instructions: Arena::new(),
         }
@@ -318,7 +445,28 @@ impl Storage {
         Self::default()
     }
 
-    fn intern(&self, s: &str) -> InternedString {
+    /// Strings that lie within `source` are referenced instead of
+    /// being copied into the string pool.
+    ///
+    /// # Safety
+    ///
+    /// `source` must outlive the storage.
+    pub unsafe fn borrowing(source: &str) -> Storage {
+        let start = source.as_ptr() as usize;
+        Storage {
+            borrowed: Some((start, start + source.len())),
+            ..Self::default()
+        }
+    }
+
+    pub fn intern(&self, s: &str) -> InternedString {
+        if let Some((start, end)) = self.borrowed {
+            let s_start = s.as_ptr() as usize;
+            if start <= s_start && s_start + s.len() <= end {
+                return InternedString::from_str(s);
+            }
+        }
+
         let interned = self.strings.intern(s);
         InternedString::from_str(interned)
     }
@@ -333,6 +481,9 @@ impl Storage {
     pub fn create_root(&self) -> *mut Root {
         self.roots.alloc(Root {
             children: Vec::new(),
+            uri: None,
+            document_type: None,
+            lexical: None,
         })
     }
 
@@ -351,6 +502,7 @@ impl Storage {
             parent: None,
             attributes: Vec::new(),
             prefix_to_namespace: LazyHashMap::new(),
+            lexical: None,
         })
     }
 
@@ -373,13 +525,32 @@ impl Storage {
     pub fn create_text(&self, text: &str) -> *mut Text {
         let text = self.intern(text);
 
-        self.texts.alloc(Text { text, parent: None })
+        self.texts.alloc(Text {
+            text,
+            parent: None,
+            lexical: None,
+        })
+    }
+
+    pub fn create_entity_reference(&self, name: &str, value: Option<&str>) -> *mut EntityReference {
+        let name = self.intern(name);
+        let value = value.map(|v| self.intern(v));
+
+        self.entity_references.alloc(EntityReference {
+            name,
+            value,
+            parent: None,
+        })
     }
 
     pub fn create_comment(&self, text: &str) -> *mut Comment {
         let text = self.intern(text);
 
-        self.comments.alloc(Comment { text, parent: None })
+        self.comments.alloc(Comment {
+            text,
+            parent: None,
+            lexical: None,
+        })
     }
 
     pub fn create_processing_instruction(
@@ -394,9 +565,53 @@ impl Storage {
             target,
             value,
             parent: None,
+            lexical: None,
         })
     }
 
+    pub fn root_set_uri(&self, root: *mut Root, uri: Option<&str>) {
+        let uri = uri.map(|u| self.intern(u));
+        let root_r = unsafe { &mut *root };
+        root_r.uri = uri;
+    }
+
+    pub fn root_set_document_type(&self, root: *mut Root, document_type: Option<DocumentType>) {
+        let root_r = unsafe { &mut *root };
+        root_r.document_type = document_type;
+    }
+
+    pub fn root_set_lexical(&self, root: *mut Root, lexical: Option<RootLexical>) {
+        let root_r = unsafe { &mut *root };
+        root_r.lexical = lexical;
+    }
+
+    pub fn element_set_lexical(&self, element: *mut Element, lexical: Option<ElementLexical>) {
+        let element_r = unsafe { &mut *element };
+        element_r.lexical = lexical.map(Box::new);
+    }
+
+    pub fn text_set_lexical(&self, text: *mut Text, lexical: Option<&str>) {
+        let lexical = lexical.map(|l| self.intern(l));
+        let text_r = unsafe { &mut *text };
+        text_r.lexical = lexical;
+    }
+
+    pub fn comment_set_lexical(&self, comment: *mut Comment, lexical: Option<&str>) {
+        let lexical = lexical.map(|l| self.intern(l));
+        let comment_r = unsafe { &mut *comment };
+        comment_r.lexical = lexical;
+    }
+
+    pub fn processing_instruction_set_lexical(
+        &self,
+        pi: *mut ProcessingInstruction,
+        lexical: Option<&str>,
+    ) {
+        let lexical = lexical.map(|l| self.intern(l));
+        let pi_r = unsafe { &mut *pi };
+        pi_r.lexical = lexical;
+    }
+
     pub fn element_set_name<'n, N>(&self, element: *mut Element, name: N)
     where
         N: Into<QName<'n>>,
@@ -405,6 +620,7 @@ impl Storage {
         let name = self.intern_qname(name);
         let element_r = unsafe { &mut *element };
         element_r.name = name;
+        element_r.lexical = None;
     }
 
     pub fn element_register_prefix(
@@ -433,6 +649,7 @@ impl Storage {
         let prefix = prefix.map(|p| self.intern(p));
         let element_r = unsafe { &mut *element };
         element_r.preferred_prefix = prefix;
+        element_r.lexical = None;
     }
 
     pub fn attribute_set_preferred_prefix(&self, attribute: *mut Attribute, prefix: Option<&str>) {
@@ -445,12 +662,14 @@ impl Storage {
         let new_text = self.intern(new_text);
         let text_r = unsafe { &mut *text };
         text_r.text = new_text;
+        text_r.lexical = None;
     }
 
     pub fn comment_set_text(&self, comment: *mut Comment, new_text: &str) {
         let new_text = self.intern(new_text);
         let comment_r = unsafe { &mut *comment };
         comment_r.text = new_text;
+        comment_r.lexical = None;
     }
 
     pub fn processing_instruction_set_target(
@@ -461,6 +680,7 @@ impl Storage {
         let new_target = self.intern(new_target);
         let pi_r = unsafe { &mut *pi };
         pi_r.target = new_target;
+        pi_r.lexical = None;
     }
 
     pub fn processing_instruction_set_value(
@@ -471,6 +691,7 @@ impl Storage {
         let new_value = new_value.map(|v| self.intern(v));
         let pi_r = unsafe { &mut *pi };
         pi_r.value = new_value;
+        pi_r.lexical = None;
     }
 }
 
@@ -497,6 +718,11 @@ impl Connections {
         child_r.parent
     }
 
+    pub fn entity_reference_parent(&self, child: *mut EntityReference) -> Option<*mut Element> {
+        let child_r = unsafe { &*child };
+        child_r.parent
+    }
+
     pub fn comment_parent(&self, child: *mut Comment) -> Option<ParentOfChild> {
         let child_r = unsafe { &*child };
         child_r.parent
@@ -532,6 +758,50 @@ impl Connections {
         parent_r.children.push(child);
     }
 
+    /// Removes the child from its current parent, then inserts it at
+    /// the index among the remaining children. An index past the last
+    /// child appends the child.
+    pub fn insert_root_child<C>(&self, index: usize, child: C)
+    where
+        C: Into<ChildOfRoot>,
+    {
+        let child = child.into();
+        let parent_r = unsafe { &mut *self.root };
+
+        child.replace_parent(self.root);
+        let index = cmp::min(index, parent_r.children.len());
+        parent_r.children.insert(index, child);
+    }
+
+    /// Removes the child from its current parent, then inserts it at
+    /// the index among the remaining children. An index past the last
+    /// child appends the child.
+    pub fn insert_element_child<C>(&self, parent: *mut Element, index: usize, child: C)
+    where
+        C: Into<ChildOfElement>,
+    {
+        let child = child.into();
+        let parent_r = unsafe { &mut *parent };
+
+        child.replace_parent(parent);
+        let index = cmp::min(index, parent_r.children.len());
+        parent_r.children.insert(index, child);
+    }
+
+    pub fn root_child_index(&self, child: ChildOfRoot) -> Option<usize> {
+        let parent_r = unsafe { &*self.root };
+        parent_r.children.iter().position(|&c| c == child)
+    }
+
+    pub fn element_child_index(
+        &self,
+        parent: *mut Element,
+        child: ChildOfElement,
+    ) -> Option<usize> {
+        let parent_r = unsafe { &*parent };
+        parent_r.children.iter().position(|&c| c == child)
+    }
+
     pub fn remove_root_child<C>(&self, child: C)
     where
         C: Into<ChildOfRoot>,
@@ -591,6 +861,13 @@ impl Connections {
         }
     }
 
+    pub fn remove_entity_reference_from_parent(&self, child: *mut EntityReference) {
+        let child_r = unsafe { &mut *child };
+        if let Some(parent) = child_r.parent {
+            self.remove_element_child(parent, child);
+        }
+    }
+
     pub fn remove_comment_from_parent(&self, child: *mut Comment) {
         let child_r = unsafe { &mut *child };
         match child_r.parent {
@@ -685,6 +962,40 @@ impl Connections {
         }
     }
 
+    /// Returns the sibling nodes that come before this node. The
+    /// nodes are in document order.
+    pub unsafe fn entity_reference_preceding_siblings(
+        &self,
+        entity_reference: *mut EntityReference,
+    ) -> SiblingIter<'_> {
+        let entity_reference_r = &*entity_reference;
+        match entity_reference_r.parent {
+            Some(element_parent) => SiblingIter::of_element(
+                SiblingDirection::Preceding,
+                element_parent,
+                ChildOfElement::EntityReference(entity_reference),
+            ),
+            None => SiblingIter::dead(),
+        }
+    }
+
+    /// Returns the sibling nodes that come after this node. The
+    /// nodes are in document order.
+    pub unsafe fn entity_reference_following_siblings(
+        &self,
+        entity_reference: *mut EntityReference,
+    ) -> SiblingIter<'_> {
+        let entity_reference_r = &*entity_reference;
+        match entity_reference_r.parent {
+            Some(element_parent) => SiblingIter::of_element(
+                SiblingDirection::Following,
+                element_parent,
+                ChildOfElement::EntityReference(entity_reference),
+            ),
+            None => SiblingIter::dead(),
+        }
+    }
+
     /// Returns the sibling nodes that come before this node. The
     /// nodes are in document order.
     pub unsafe fn comment_preceding_siblings(&self, comment: *mut Comment) -> SiblingIter<'_> {
@@ -910,6 +1221,30 @@ impl Connections {
             .filter_map(|e| e.default_namespace_uri())
             .next()
     }
+
+    /// Finds the value of the attribute on the closest of the element
+    /// and its ancestors where the value is accepted.
+    pub fn element_inherited_attribute_value<'n, N, F>(
+        &self,
+        element: *mut Element,
+        name: N,
+        mut accept: F,
+    ) -> Option<&str>
+    where
+        N: Into<QName<'n>>,
+        F: FnMut(&str) -> bool,
+    {
+        let name = name.into();
+        self.element_parents(element)
+            .filter_map(|e| {
+                e.attributes
+                    .iter()
+                    .map(|a| unsafe { &**a })
+                    .find(|a| a.name.as_qname() == name)
+            })
+            .map(|a| a.value())
+            .find(|v| accept(v))
+    }
 }
 
 struct ElementParents<'a> {
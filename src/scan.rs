@@ -0,0 +1,176 @@
+//! Fast searching for the ASCII delimiters that end runs of XML text.
+//!
+//! On x86_64 the haystack is compared 16 bytes at a time using SSE2,
+//! or 32 bytes at a time when AVX2 is detected at runtime. Other
+//! targets use a simple byte-by-byte search. All needles are ASCII,
+//! so every returned offset is on a UTF-8 character boundary.
+
+/// Finds the first occurrence of any of the three bytes
+pub fn find_any(haystack: &[u8], needles: [u8; 3]) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if haystack.len() >= x86::SSE2_WIDTH {
+            if is_x86_feature_detected!("avx2") {
+                return unsafe { x86::find_any_avx2(haystack, needles) };
+            }
+            return unsafe { x86::find_any_sse2(haystack, needles) };
+        }
+    }
+
+    fallback::find_any(haystack, needles)
+}
+
+/// Finds the first occurrence of the byte
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    find_any(haystack, [needle; 3])
+}
+
+/// Finds the first occurrence of the byte sequence
+pub fn find_sequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let first = needle[0];
+    let mut start = 0;
+
+    while let Some(i) = find_byte(&haystack[start..], first) {
+        let i = start + i;
+        if haystack[i..].starts_with(needle) {
+            return Some(i);
+        }
+        start = i + 1;
+    }
+
+    None
+}
+
+mod fallback {
+    pub fn find_any(haystack: &[u8], needles: [u8; 3]) -> Option<usize> {
+        let [a, b, c] = needles;
+        haystack.iter().position(|&x| x == a || x == b || x == c)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::fallback;
+
+    pub const SSE2_WIDTH: usize = 16;
+    const AVX2_WIDTH: usize = 32;
+
+    // SSE2 is part of the x86_64 baseline, so no detection is needed.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn find_any_sse2(haystack: &[u8], needles: [u8; 3]) -> Option<usize> {
+        let a = _mm_set1_epi8(needles[0] as i8);
+        let b = _mm_set1_epi8(needles[1] as i8);
+        let c = _mm_set1_epi8(needles[2] as i8);
+
+        let ptr = haystack.as_ptr();
+        let mut i = 0;
+
+        while i + SSE2_WIDTH <= haystack.len() {
+            let chunk = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+            let found = _mm_or_si128(
+                _mm_or_si128(_mm_cmpeq_epi8(chunk, a), _mm_cmpeq_epi8(chunk, b)),
+                _mm_cmpeq_epi8(chunk, c),
+            );
+            let mask = _mm_movemask_epi8(found) as u32;
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize);
+            }
+            i += SSE2_WIDTH;
+        }
+
+        fallback::find_any(&haystack[i..], needles).map(|p| i + p)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn find_any_avx2(haystack: &[u8], needles: [u8; 3]) -> Option<usize> {
+        let a = _mm256_set1_epi8(needles[0] as i8);
+        let b = _mm256_set1_epi8(needles[1] as i8);
+        let c = _mm256_set1_epi8(needles[2] as i8);
+
+        let ptr = haystack.as_ptr();
+        let mut i = 0;
+
+        while i + AVX2_WIDTH <= haystack.len() {
+            let chunk = _mm256_loadu_si256(ptr.add(i) as *const __m256i);
+            let found = _mm256_or_si256(
+                _mm256_or_si256(_mm256_cmpeq_epi8(chunk, a), _mm256_cmpeq_epi8(chunk, b)),
+                _mm256_cmpeq_epi8(chunk, c),
+            );
+            let mask = _mm256_movemask_epi8(found) as u32;
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize);
+            }
+            i += AVX2_WIDTH;
+        }
+
+        find_any_sse2(&haystack[i..], needles).map(|p| i + p)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fallback, find_any, find_byte, find_sequence};
+
+    // Long enough to cover several AVX2 blocks and every tail length
+    const LENGTHS: ::std::ops::Range<usize> = 0..100;
+
+    #[test]
+    fn find_any_matches_the_fallback_at_every_position() {
+        let needles = [b'<', b'&', b']'];
+
+        for len in LENGTHS {
+            let mut haystack = vec![b'a'; len];
+            assert_eq!(find_any(&haystack, needles), None);
+
+            for pos in 0..len {
+                for &needle in &needles {
+                    haystack[pos] = needle;
+                    assert_eq!(
+                        find_any(&haystack, needles),
+                        fallback::find_any(&haystack, needles)
+                    );
+                    assert_eq!(find_any(&haystack, needles), Some(pos));
+                    haystack[pos] = b'a';
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_any_returns_the_first_of_several_matches() {
+        let mut haystack = vec![b'x'; 70];
+        haystack[40] = b'&';
+        haystack[33] = b'<';
+        haystack[65] = b'<';
+
+        assert_eq!(find_any(&haystack, [b'<', b'&', b'&']), Some(33));
+    }
+
+    #[test]
+    fn find_any_ignores_bytes_with_the_high_bit_set() {
+        let haystack = "\u{e9}\u{1f600}\u{3c}".repeat(20);
+
+        assert_eq!(find_byte(haystack.as_bytes(), b'<'), Some(6));
+    }
+
+    #[test]
+    fn find_sequence_skips_partial_matches() {
+        for len in LENGTHS {
+            let mut haystack = vec![b']'; len];
+            if len >= 3 {
+                haystack[len - 1] = b'>';
+                assert_eq!(find_sequence(&haystack, b"]]>"), Some(len - 3));
+            } else {
+                assert_eq!(find_sequence(&haystack, b"]]>"), None);
+            }
+        }
+    }
+
+    #[test]
+    fn find_sequence_handles_a_needle_at_the_end() {
+        assert_eq!(find_sequence(b"abc-", b"--"), None);
+        assert_eq!(find_sequence(b"abc--", b"--"), Some(3));
+    }
+}
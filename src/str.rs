@@ -1,3 +1,5 @@
+use crate::scan;
+
 trait StrParseExt {
     fn end_of_start_rest<F1, F2>(&self, is_first: F1, is_rest: F2) -> Option<usize>
     where
@@ -53,6 +55,8 @@ pub trait XmlStr {
     fn end_of_encoding(&self) -> Option<usize>;
     /// Find the end of the internal doc type declaration, not including the ]
     fn end_of_int_subset(&self) -> Option<usize>;
+    /// Find the end of the quoted [public identifier](http://www.w3.org/TR/xml/#NT-PubidLiteral), not including the quote
+    fn end_of_pubid_literal(&self, quote: &str) -> Option<usize>;
 }
 
 impl<'a> XmlStr for &'a str {
@@ -65,22 +69,23 @@ impl<'a> XmlStr for &'a str {
             return None;
         }
 
-        let quote_char = quote.chars().next().expect("Cant have null quote");
+        let quote_byte = *quote.as_bytes().first().expect("Cant have null quote");
 
-        self.find(&['&', '<', quote_char][..])
-            .or_else(|| Some(self.len()))
+        Some(scan::find_any(self.as_bytes(), [b'&', b'<', quote_byte]).unwrap_or(self.len()))
     }
 
     fn end_of_char_data(&self) -> Option<usize> {
         fn find_end_of_char_data(bytes: &[u8]) -> Option<usize> {
-            for (i, &b) in bytes.iter().enumerate() {
-                if b == b'<' || b == b'&' {
-                    return Some(i);
-                }
+            let mut start = 0;
+
+            while let Some(i) = scan::find_any(&bytes[start..], [b'<', b'&', b']']) {
+                let i = start + i;
 
-                if b == b']' && bytes[i..].starts_with(b"]]>") {
+                if bytes[i] != b']' || bytes[i..].starts_with(b"]]>") {
                     return Some(i);
                 }
+
+                start = i + 1;
             }
             None
         }
@@ -93,7 +98,7 @@ impl<'a> XmlStr for &'a str {
     }
 
     fn end_of_cdata(&self) -> Option<usize> {
-        self.find("]]>")
+        scan::find_sequence(self.as_bytes(), b"]]>")
     }
 
     fn end_of_decimal_chars(&self) -> Option<usize> {
@@ -108,11 +113,11 @@ impl<'a> XmlStr for &'a str {
         // This deliberately does not include the >. -- is not allowed
         // in a comment, so we can just test the end if it matches the
         // complete close delimiter.
-        self.find("--")
+        scan::find_sequence(self.as_bytes(), b"--")
     }
 
     fn end_of_pi_value(&self) -> Option<usize> {
-        self.find("?>")
+        scan::find_sequence(self.as_bytes(), b"?>")
     }
 
     fn end_of_name(&self) -> Option<usize> {
@@ -152,7 +157,26 @@ impl<'a> XmlStr for &'a str {
     }
 
     fn end_of_int_subset(&self) -> Option<usize> {
-        self.find(']')
+        scan::find_byte(self.as_bytes(), b']')
+    }
+
+    fn end_of_pubid_literal(&self, quote: &str) -> Option<usize> {
+        fn is_pubid_char(c: char) -> bool {
+            matches!(c,
+                ' ' | '\r' | '\n'
+                | 'a'..='z' | 'A'..='Z' | '0'..='9'
+                | '-' | '\'' | '(' | ')' | '+' | ',' | '.' | '/' | ':'
+                | '=' | '?' | ';' | '!' | '*' | '#' | '@' | '$' | '_' | '%')
+        }
+
+        let end = self
+            .char_indices()
+            .find(|&(_, c)| !is_pubid_char(c) || quote.starts_with(c))
+            .map_or(self.len(), |(offset, _)| offset);
+
+        // An empty literal is allowed; the closing quote is checked
+        // by the caller
+        Some(end)
     }
 }
 
@@ -302,8 +326,113 @@ mod test {
         );
     }
 
+    #[test]
+    fn end_of_char_data_past_a_vector_width() {
+        let s = format!("{}]]{}<", "a".repeat(40), "b".repeat(40));
+        assert_eq!(s.as_str().end_of_char_data(), Some(s.len() - 1));
+    }
+
+    #[test]
+    fn end_of_attribute_past_a_vector_width() {
+        let s = format!("{}\"{}'", "a".repeat(40), "b".repeat(40));
+        assert_eq!(s.as_str().end_of_attribute("'"), Some(s.len() - 1));
+        assert_eq!(s.as_str().end_of_attribute("\""), Some(40));
+    }
+
+    #[test]
+    fn end_of_comment_past_a_vector_width() {
+        let s = format!("{}-{}-->", "a".repeat(40), "b".repeat(40));
+        assert_eq!(s.as_str().end_of_comment(), Some(s.len() - 3));
+    }
+
     #[test]
     fn end_of_int_subset_excludes_right_square() {
         assert_eq!("hello]>world".end_of_int_subset(), Some("hello".len()))
     }
+
+    #[test]
+    fn end_of_pubid_literal_stops_at_the_quote() {
+        assert_eq!(
+            "-//W3C//DTD XHTML 1.0//EN\" \"x".end_of_pubid_literal("\""),
+            Some("-//W3C//DTD XHTML 1.0//EN".len())
+        );
+        assert_eq!("it's'".end_of_pubid_literal("'"), Some("it".len()));
+        assert_eq!("it's\"".end_of_pubid_literal("\""), Some("it's".len()));
+    }
+
+    #[test]
+    fn end_of_pubid_literal_excludes_invalid_characters() {
+        assert_eq!("a<b".end_of_pubid_literal("\""), Some(1));
+        assert_eq!("\u{e9}".end_of_pubid_literal("\""), Some(0));
+    }
+
+    #[test]
+    fn end_of_pubid_literal_allows_an_empty_literal() {
+        assert_eq!("\" \"a.dtd\"".end_of_pubid_literal("\""), Some(0));
+    }
+}
+
+#[cfg(feature = "unstable")]
+mod bench {
+    extern crate test;
+
+    use self::test::Bencher;
+
+    use super::XmlStr;
+
+    fn text(len: usize) -> String {
+        "The quick brown fox jumps over the lazy dog. ]"
+            .chars()
+            .cycle()
+            .take(len)
+            .collect()
+    }
+
+    #[bench]
+    fn char_data(b: &mut Bencher) {
+        let s = text(64 * 1024) + "<";
+        let s = &s[..];
+        b.iter(|| s.end_of_char_data());
+        b.bytes = s.len() as u64;
+    }
+
+    #[bench]
+    fn char_data_short(b: &mut Bencher) {
+        let s = text(20) + "<";
+        let s = &s[..];
+        b.iter(|| s.end_of_char_data());
+        b.bytes = s.len() as u64;
+    }
+
+    #[bench]
+    fn attribute(b: &mut Bencher) {
+        let s = text(4 * 1024) + "'";
+        let s = &s[..];
+        b.iter(|| s.end_of_attribute("'"));
+        b.bytes = s.len() as u64;
+    }
+
+    #[bench]
+    fn cdata(b: &mut Bencher) {
+        let s = text(64 * 1024) + "]]>";
+        let s = &s[..];
+        b.iter(|| s.end_of_cdata());
+        b.bytes = s.len() as u64;
+    }
+
+    #[bench]
+    fn comment(b: &mut Bencher) {
+        let s = text(64 * 1024) + "-->";
+        let s = &s[..];
+        b.iter(|| s.end_of_comment());
+        b.bytes = s.len() as u64;
+    }
+
+    #[bench]
+    fn pi_value(b: &mut Bencher) {
+        let s = text(64 * 1024) + "?>";
+        let s = &s[..];
+        b.iter(|| s.end_of_pi_value());
+        b.bytes = s.len() as u64;
+    }
 }
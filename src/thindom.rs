@@ -402,6 +402,29 @@ impl<'d> fmt::Debug for Text<'d> {
     }
 }
 
+node!(EntityReference, raw::EntityReference);
+
+impl<'d> EntityReference<'d> {
+    pub fn name(&self) -> &str {
+        self.node().name()
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.node().value()
+    }
+}
+
+impl<'d> fmt::Debug for EntityReference<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EntityReference {{ name: {:?}, value: {:?} }}",
+            self.name(),
+            self.value()
+        )
+    }
+}
+
 node!(Comment, raw::Comment);
 
 impl<'d> Comment<'d> {
@@ -491,6 +514,7 @@ impl<'d> ChildOfRoot<'d> {
 pub enum ChildOfElement<'d> {
     Element(Element<'d>),
     Text(Text<'d>),
+    EntityReference(EntityReference<'d>),
     Comment(Comment<'d>),
     ProcessingInstruction(ProcessingInstruction<'d>),
 }
@@ -498,6 +522,12 @@ pub enum ChildOfElement<'d> {
 impl<'d> ChildOfElement<'d> {
     unpack!(ChildOfElement, element, Element, Element);
     unpack!(ChildOfElement, text, Text, Text);
+    unpack!(
+        ChildOfElement,
+        entity_reference,
+        EntityReference,
+        EntityReference
+    );
     unpack!(ChildOfElement, comment, Comment, Comment);
     unpack!(
         ChildOfElement,
@@ -510,6 +540,9 @@ impl<'d> ChildOfElement<'d> {
         match node {
             raw::ChildOfElement::Element(n) => ChildOfElement::Element(Element::wrap(n)),
             raw::ChildOfElement::Text(n) => ChildOfElement::Text(Text::wrap(n)),
+            raw::ChildOfElement::EntityReference(n) => {
+                ChildOfElement::EntityReference(EntityReference::wrap(n))
+            }
             raw::ChildOfElement::Comment(n) => ChildOfElement::Comment(Comment::wrap(n)),
             raw::ChildOfElement::ProcessingInstruction(n) => {
                 ChildOfElement::ProcessingInstruction(ProcessingInstruction::wrap(n))
@@ -521,6 +554,7 @@ impl<'d> ChildOfElement<'d> {
         match *self {
             ChildOfElement::Element(n) => raw::ChildOfElement::Element(n.node),
             ChildOfElement::Text(n) => raw::ChildOfElement::Text(n.node),
+            ChildOfElement::EntityReference(n) => raw::ChildOfElement::EntityReference(n.node),
             ChildOfElement::Comment(n) => raw::ChildOfElement::Comment(n.node),
             ChildOfElement::ProcessingInstruction(n) => {
                 raw::ChildOfElement::ProcessingInstruction(n.node)
@@ -571,6 +605,7 @@ conversion_trait!(
     ChildOfElement, {
         Element               => ChildOfElement::Element,
         Text                  => ChildOfElement::Text,
+        EntityReference       => ChildOfElement::EntityReference,
         Comment               => ChildOfElement::Comment,
         ProcessingInstruction => ChildOfElement::ProcessingInstruction
     }
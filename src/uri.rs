@@ -0,0 +1,325 @@
+//! Resolution of URI references as described by [RFC 3986,
+//! section 5](https://tools.ietf.org/html/rfc3986#section-5).
+
+/// The components of a URI reference. Absent components are `None`,
+/// which differs from being present but empty.
+#[derive(Debug, PartialEq)]
+struct Reference<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> Reference<'a> {
+    /// Splits a reference using the expression from [appendix
+    /// B](https://tools.ietf.org/html/rfc3986#appendix-B).
+    fn parse(s: &'a str) -> Reference<'a> {
+        let (s, fragment) = match s.find('#') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let (s, query) = match s.find('?') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let (scheme, s) = match s.find(&[':', '/'][..]) {
+            Some(i) if i > 0 && s[i..].starts_with(':') => (Some(&s[..i]), &s[i + 1..]),
+            _ => (None, s),
+        };
+
+        let (authority, path) = match s.get(2..).filter(|_| s.starts_with("//")) {
+            Some(s) => match s.find('/') {
+                Some(i) => (Some(&s[..i]), &s[i..]),
+                None => (Some(s), ""),
+            },
+            None => (None, s),
+        };
+
+        Reference {
+            scheme,
+            authority,
+            path,
+            query,
+            fragment,
+        }
+    }
+}
+
+/// The scheme of the reference, if it is an absolute URI
+pub fn scheme(reference: &str) -> Option<&str> {
+    Reference::parse(reference).scheme
+}
+
+/// Whether the reference is an absolute URI, rather than a relative
+/// reference
+pub fn has_scheme(reference: &str) -> bool {
+    scheme(reference).is_some()
+}
+
+/// Decodes the percent-encoded octets in a component of a URI. Fails
+/// if an escape is not followed by two hex digits or the decoded
+/// octets are not UTF-8.
+pub fn percent_decode(component: &str) -> Option<String> {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = component.get(i + 1..i + 3)?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Resolves the reference against the base URI
+pub fn resolve(base: &str, reference: &str) -> String {
+    let base = Reference::parse(base);
+    let r = Reference::parse(reference);
+
+    let (scheme, authority, path, query);
+
+    if r.scheme.is_some() {
+        scheme = r.scheme;
+        authority = r.authority;
+        path = remove_dot_segments(r.path);
+        query = r.query;
+    } else {
+        if r.authority.is_some() {
+            authority = r.authority;
+            path = remove_dot_segments(r.path);
+            query = r.query;
+        } else {
+            if r.path.is_empty() {
+                path = base.path.to_owned();
+                query = r.query.or(base.query);
+            } else {
+                if r.path.starts_with('/') {
+                    path = remove_dot_segments(r.path);
+                } else {
+                    path = remove_dot_segments(&merge(&base, r.path));
+                }
+                query = r.query;
+            }
+            authority = base.authority;
+        }
+        scheme = base.scheme;
+    }
+
+    recompose(scheme, authority, &path, query, r.fragment)
+}
+
+/// Merges a relative path with the path of the base, as described in
+/// [section 5.2.3](https://tools.ietf.org/html/rfc3986#section-5.2.3)
+fn merge(base: &Reference<'_>, path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        return format!("/{}", path);
+    }
+
+    match base.path.rfind('/') {
+        Some(i) => format!("{}{}", &base.path[..=i], path),
+        None => path.to_owned(),
+    }
+}
+
+/// Interprets the `.` and `..` segments of a path, as described in
+/// [section 5.2.4](https://tools.ietf.org/html/rfc3986#section-5.2.4)
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output = String::with_capacity(path.len());
+
+    while !input.is_empty() {
+        if input.starts_with("../") {
+            input = &input[3..];
+        } else if input.starts_with("./") || input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            let last_segment = output.rfind('/').unwrap_or(0);
+            output.truncate(last_segment);
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let start = if input.starts_with('/') { 1 } else { 0 };
+            let end = input[start..].find('/').map_or(input.len(), |i| start + i);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+
+    output
+}
+
+/// Combines the components, as described in [section
+/// 5.3](https://tools.ietf.org/html/rfc3986#section-5.3)
+fn recompose(
+    scheme: Option<&str>,
+    authority: Option<&str>,
+    path: &str,
+    query: Option<&str>,
+    fragment: Option<&str>,
+) -> String {
+    let mut result = String::new();
+
+    if let Some(scheme) = scheme {
+        result.push_str(scheme);
+        result.push(':');
+    }
+    if let Some(authority) = authority {
+        result.push_str("//");
+        result.push_str(authority);
+    }
+    result.push_str(path);
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    if let Some(fragment) = fragment {
+        result.push('#');
+        result.push_str(fragment);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{has_scheme, percent_decode, remove_dot_segments, resolve, scheme, Reference};
+
+    const BASE: &str = "http://a/b/c/d;p?q";
+
+    fn assert_resolves(examples: &[(&str, &str)]) {
+        for &(reference, expected) in examples {
+            assert_eq!(
+                resolve(BASE, reference),
+                expected,
+                "resolving {}",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn parses_all_components() {
+        assert_eq!(
+            Reference::parse("http://example.com/a/b?c=d#e"),
+            Reference {
+                scheme: Some("http"),
+                authority: Some("example.com"),
+                path: "/a/b",
+                query: Some("c=d"),
+                fragment: Some("e"),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_a_relative_path_containing_a_colon() {
+        let r = Reference::parse("a/b:c");
+        assert_eq!((r.scheme, r.path), (None, "a/b:c"));
+    }
+
+    #[test]
+    fn finds_the_scheme() {
+        assert_eq!(scheme("http://example.com/a"), Some("http"));
+        assert_eq!(scheme("C:/docs/a.xml"), Some("C"));
+        assert_eq!(scheme("docs/a:b.xml"), None);
+        assert!(!has_scheme("/docs/a.xml"));
+    }
+
+    #[test]
+    fn resolves_the_normal_examples() {
+        // RFC 3986, section 5.4.1
+        assert_resolves(&[
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g#s", "http://a/b/c/g#s"),
+            ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"),
+            ("g;x", "http://a/b/c/g;x"),
+            ("g;x?y#s", "http://a/b/c/g;x?y#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../", "http://a/"),
+            ("../../g", "http://a/g"),
+        ]);
+    }
+
+    #[test]
+    fn resolves_the_abnormal_examples() {
+        // RFC 3986, section 5.4.2
+        assert_resolves(&[
+            ("../../../g", "http://a/g"),
+            ("../../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            (".g", "http://a/b/c/.g"),
+            ("g..", "http://a/b/c/g.."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("./g/.", "http://a/b/c/g/"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+            ("g?y/./x", "http://a/b/c/g?y/./x"),
+            ("g?y/../x", "http://a/b/c/g?y/../x"),
+            ("g#s/./x", "http://a/b/c/g#s/./x"),
+            ("g#s/../x", "http://a/b/c/g#s/../x"),
+            ("http:g", "http:g"),
+        ]);
+    }
+
+    #[test]
+    fn removes_dot_segments() {
+        // RFC 3986, section 5.2.4
+        assert_eq!(remove_dot_segments("/a/b/c/./../../g"), "/a/g");
+        assert_eq!(remove_dot_segments("mid/content=5/../6"), "mid/6");
+    }
+
+    #[test]
+    fn resolves_against_a_base_without_a_path() {
+        assert_eq!(resolve("http://a", "g"), "http://a/g");
+        assert_eq!(resolve("file:docs/", "g"), "file:docs/g");
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(
+            percent_decode("/docs/my%20chapter%2fa.xml").as_deref(),
+            Some("/docs/my chapter/a.xml")
+        );
+        assert_eq!(percent_decode("%C3%A9").as_deref(), Some("\u{e9}"));
+        assert_eq!(percent_decode("a%2"), None);
+        assert_eq!(percent_decode("a%+1b"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+}
@@ -115,8 +115,12 @@ struct PrefixMapping<'d> {
 
 impl<'d> PrefixMapping<'d> {
     fn new() -> PrefixMapping<'d> {
+        // The xml prefix is bound by definition and never declared
+        let mut outermost = PrefixScope::new();
+        outermost.add_mapping(crate::XML_NS_PREFIX, crate::XML_NS_URI);
+
         PrefixMapping {
-            scopes: vec![PrefixScope::new()],
+            scopes: vec![outermost],
             generated_prefix_count: 0,
         }
     }
@@ -163,6 +167,17 @@ impl<'d> PrefixMapping<'d> {
             }
         }
 
+        self.set_attribute_prefixes(attributes);
+
+        let name = element.name();
+        if let Some(uri) = name.namespace_uri {
+            self.generate_prefix(uri);
+        }
+
+        self.generate_attribute_prefixes(attributes);
+    }
+
+    fn set_attribute_prefixes(&mut self, attributes: &[dom::Attribute<'d>]) {
         for attribute in attributes.iter() {
             if let Some(prefix) = attribute.preferred_prefix() {
                 let name = attribute.name();
@@ -171,12 +186,9 @@ impl<'d> PrefixMapping<'d> {
                 }
             }
         }
+    }
 
-        let name = element.name();
-        if let Some(uri) = name.namespace_uri {
-            self.generate_prefix(uri);
-        }
-
+    fn generate_attribute_prefixes(&mut self, attributes: &[dom::Attribute<'d>]) {
         for attribute in attributes.iter() {
             let name = attribute.name();
             if let Some(uri) = name.namespace_uri {
@@ -185,6 +197,47 @@ impl<'d> PrefixMapping<'d> {
         }
     }
 
+    /// Adds the namespace declarations of a start tag that will be
+    /// written as it originally appeared
+    fn populate_scope_lexically(&mut self, lexical: &dom::LexicalElement<'d, 'd>) {
+        let scope = self.scopes.last_mut().unwrap();
+
+        for item in &lexical.items {
+            if let dom::LexicalItem::Namespace { prefix, uri, .. } = *item {
+                // An empty default namespace undeclares the default
+                let uri = uri.unwrap_or("");
+                match prefix {
+                    Some(prefix) => scope.add_mapping(prefix, uri),
+                    None => scope.default_namespace_uri = Some(uri),
+                }
+            }
+        }
+    }
+
+    fn clear_scope(&mut self) {
+        *self.scopes.last_mut().unwrap() = PrefixScope::new();
+    }
+
+    /// Whether a name written with this prefix refers to the namespace
+    fn prefix_refers_to(
+        &self,
+        prefix: Option<&str>,
+        namespace_uri: Option<&str>,
+        ignore_default: bool,
+    ) -> bool {
+        match prefix {
+            Some(prefix) => {
+                namespace_uri.is_some()
+                    && self.active_namespace_uri_for_prefix(prefix) == namespace_uri
+            }
+            None if ignore_default => namespace_uri.is_none(),
+            None => {
+                let default = self.active_default_namespace_uri();
+                default.filter(|uri| !uri.is_empty()) == namespace_uri
+            }
+        }
+    }
+
     fn set_prefix(&mut self, prefix: &str, namespace_uri: &'d str) {
         let idx_of_last = self.scopes.len().saturating_sub(1);
         let (parents, current_scope) = self.scopes.split_at_mut(idx_of_last);
@@ -276,7 +329,9 @@ impl<'d> PrefixMapping<'d> {
 enum Content<'d> {
     Element(dom::Element<'d>),
     ElementEnd(dom::Element<'d>),
+    LexicalElementEnd(&'d str),
     Text(dom::Text<'d>),
+    EntityReference(dom::EntityReference<'d>),
     Comment(dom::Comment<'d>),
     ProcessingInstruction(dom::ProcessingInstruction<'d>),
 }
@@ -307,6 +362,7 @@ enum Content<'d> {
 pub struct Writer {
     single_quotes: bool,
     write_encoding: bool,
+    preserve_lexical_form: bool,
 }
 
 impl Default for Writer {
@@ -314,6 +370,7 @@ impl Default for Writer {
         Self {
             single_quotes: true,
             write_encoding: false,
+            preserve_lexical_form: true,
         }
     }
 }
@@ -336,6 +393,14 @@ impl Writer {
         self
     }
 
+    /// Set whether nodes parsed with
+    /// `Parser::set_preserve_lexical_form` that have not been modified
+    /// since should be written exactly as they appeared. Defaults to true.
+    pub fn set_preserve_lexical_form(mut self, preserve_lexical_form: bool) -> Self {
+        self.preserve_lexical_form = preserve_lexical_form;
+        self
+    }
+
     fn quote_char(&self) -> &'static str {
         if self.single_quotes {
             "'"
@@ -406,6 +471,10 @@ impl Writer {
     where
         W: Write,
     {
+        if let Some(lexical) = self.lexical_element(element, mapping) {
+            return self.format_lexical_element(element, lexical, todo, mapping, writer);
+        }
+
         let attrs = element.attributes();
 
         mapping.populate_scope(&element, &attrs);
@@ -420,12 +489,7 @@ impl Writer {
         )?;
 
         for attr in &attrs {
-            writer.write_str(" ")?;
-            self.format_qname(attr.name(), mapping, attr.preferred_prefix(), true, writer)?;
-            write!(writer, "=")?;
-            write!(writer, "{}", self.quote_char())?;
-            self.format_attribute_value(attr.value(), writer)?;
-            write!(writer, "{}", self.quote_char())?;
+            self.format_attribute(*attr, mapping, writer)?;
         }
 
         if let Some(ns_uri) = mapping.default_namespace_uri_in_current_scope() {
@@ -434,32 +498,177 @@ impl Writer {
             writer.write_str("'")?;
         }
 
-        for &(ref prefix, ref ns_uri) in mapping.prefixes_in_current_scope() {
-            writer.write_str(" xmlns:")?;
-            writer.write_str(prefix)?;
-            write!(writer, "='{}'", ns_uri)?;
-        }
+        self.format_defined_prefixes(mapping, writer)?;
 
-        let mut children = element.children();
+        let children = element.children();
         if children.is_empty() {
             writer.write_str("/>")?;
             mapping.pop_scope();
-            Ok(())
         } else {
             writer.write_str(">")?;
 
             todo.push(ElementEnd(element));
-            children.reverse();
-            let x = children.into_iter().map(|c| match c {
-                ChildOfElement::Element(element) => Element(element),
-                ChildOfElement::Text(t) => Text(t),
-                ChildOfElement::Comment(c) => Comment(c),
-                ChildOfElement::ProcessingInstruction(p) => ProcessingInstruction(p),
-            });
-            todo.extend(x);
+            self.push_children(children, todo);
+        }
 
-            Ok(())
+        Ok(())
+    }
+
+    fn format_attribute<'d, W>(
+        &self,
+        attr: dom::Attribute<'d>,
+        mapping: &mut PrefixMapping<'d>,
+        writer: &mut W,
+    ) -> io::Result<()>
+    where
+        W: ?Sized + Write,
+    {
+        writer.write_str(" ")?;
+        self.format_qname(attr.name(), mapping, attr.preferred_prefix(), true, writer)?;
+        write!(writer, "=")?;
+        write!(writer, "{}", self.quote_char())?;
+        self.format_attribute_value(attr.value(), writer)?;
+        write!(writer, "{}", self.quote_char())
+    }
+
+    fn format_defined_prefixes<W>(
+        &self,
+        mapping: &PrefixMapping<'_>,
+        writer: &mut W,
+    ) -> io::Result<()>
+    where
+        W: ?Sized + Write,
+    {
+        for &(ref prefix, ref ns_uri) in mapping.prefixes_in_current_scope() {
+            writer.write_str(" xmlns:")?;
+            writer.write_str(prefix)?;
+            write!(writer, "='{}'", ns_uri)?;
+        }
+        Ok(())
+    }
+
+    fn push_children<'d>(
+        &self,
+        mut children: Vec<ChildOfElement<'d>>,
+        todo: &mut Vec<Content<'d>>,
+    ) {
+        children.reverse();
+        let x = children.into_iter().map(|c| match c {
+            ChildOfElement::Element(element) => Element(element),
+            ChildOfElement::Text(t) => Text(t),
+            ChildOfElement::EntityReference(r) => EntityReference(r),
+            ChildOfElement::Comment(c) => Comment(c),
+            ChildOfElement::ProcessingInstruction(p) => ProcessingInstruction(p),
+        });
+        todo.extend(x);
+    }
+
+    /// The original tags of the element, if they should be reused. The
+    /// prefixes they contain must still refer to the same namespaces,
+    /// which may not be the case when an ancestor has been modified.
+    fn lexical_element<'d>(
+        &self,
+        element: dom::Element<'d>,
+        mapping: &mut PrefixMapping<'d>,
+    ) -> Option<dom::LexicalElement<'d, 'd>> {
+        if !self.preserve_lexical_form {
+            return None;
+        }
+        let lexical = element.lexical()?;
+
+        mapping.populate_scope_lexically(&lexical);
+
+        let name = element.name();
+        let consistent =
+            mapping.prefix_refers_to(element.preferred_prefix(), name.namespace_uri, false)
+                && lexical.items.iter().all(|item| match *item {
+                    dom::LexicalItem::Attribute {
+                        attribute, prefix, ..
+                    } => mapping.prefix_refers_to(prefix, attribute.name().namespace_uri, true),
+                    dom::LexicalItem::Namespace { .. } => true,
+                });
+
+        if consistent {
+            Some(lexical)
+        } else {
+            mapping.clear_scope();
+            None
+        }
+    }
+
+    fn format_lexical_element<'d, W>(
+        &self,
+        element: dom::Element<'d>,
+        lexical: dom::LexicalElement<'d, 'd>,
+        todo: &mut Vec<Content<'d>>,
+        mapping: &mut PrefixMapping<'d>,
+        writer: &mut W,
+    ) -> io::Result<()>
+    where
+        W: ?Sized + Write,
+    {
+        let mut added_attrs = element.attributes();
+
+        writer.write_str(lexical.open)?;
+
+        for item in &lexical.items {
+            match *item {
+                dom::LexicalItem::Namespace { text, .. } => writer.write_str(text)?,
+                dom::LexicalItem::Attribute {
+                    attribute,
+                    unchanged,
+                    text,
+                    value_start,
+                    ..
+                } => {
+                    added_attrs.retain(|a| *a != attribute);
+
+                    if unchanged {
+                        writer.write_str(text)?;
+                    } else {
+                        // Keep the name, the spacing, and the quote
+                        writer.write_str(&text[..value_start])?;
+                        self.format_attribute_value(attribute.value(), writer)?;
+                        writer.write_str(&text[value_start - 1..value_start])?;
+                    }
+                }
+            }
+        }
+
+        mapping.set_attribute_prefixes(&added_attrs);
+        mapping.generate_attribute_prefixes(&added_attrs);
+
+        for attr in &added_attrs {
+            self.format_attribute(*attr, mapping, writer)?;
         }
+
+        self.format_defined_prefixes(mapping, writer)?;
+
+        let children = element.children();
+        if children.is_empty() {
+            writer.write_str(lexical.close)?;
+            if let Some(end) = lexical.end {
+                writer.write_str(end)?;
+            }
+            mapping.pop_scope();
+        } else {
+            match lexical.end {
+                Some(end) => {
+                    writer.write_str(lexical.close)?;
+                    todo.push(LexicalElementEnd(end));
+                }
+                None => {
+                    // A self-closing tag that has gained children
+                    let close = lexical.close;
+                    writer.write_str(&close[..close.len() - "/>".len()])?;
+                    writer.write_str(">")?;
+                    todo.push(ElementEnd(element));
+                }
+            }
+            self.push_children(children, todo);
+        }
+
+        Ok(())
     }
 
     fn format_element_end<'d, W: ?Sized>(
@@ -486,6 +695,10 @@ impl Writer {
     where
         W: Write,
     {
+        if let Some(lexical) = self.lexical(text.lexical()) {
+            return writer.write_str(lexical);
+        }
+
         for item in text
             .text()
             .split_keeping_delimiter(|c| c == '<' || c == '>' || c == '&')
@@ -501,10 +714,33 @@ impl Writer {
         Ok(())
     }
 
+    fn format_entity_reference<W>(
+        &self,
+        entity_reference: dom::EntityReference<'_>,
+        writer: &mut W,
+    ) -> io::Result<()>
+    where
+        W: ?Sized + Write,
+    {
+        write!(writer, "&{};", entity_reference.name())
+    }
+
+    fn lexical<'d>(&self, lexical: Option<&'d str>) -> Option<&'d str> {
+        if self.preserve_lexical_form {
+            lexical
+        } else {
+            None
+        }
+    }
+
     fn format_comment<W: ?Sized>(&self, comment: dom::Comment<'_>, writer: &mut W) -> io::Result<()>
     where
         W: Write,
     {
+        if let Some(lexical) = self.lexical(comment.lexical()) {
+            return writer.write_str(lexical);
+        }
+
         write!(writer, "<!--{}-->", comment.text())
     }
 
@@ -516,6 +752,10 @@ impl Writer {
     where
         W: Write,
     {
+        if let Some(lexical) = self.lexical(pi.lexical()) {
+            return writer.write_str(lexical);
+        }
+
         match pi.value() {
             None => write!(writer, "<?{}?>", pi.target()),
             Some(v) => write!(writer, "<?{} {}?>", pi.target(), v),
@@ -542,7 +782,12 @@ impl Writer {
                 mapping.pop_scope();
                 r
             }
+            LexicalElementEnd(end) => {
+                mapping.pop_scope();
+                writer.write_str(end)
+            }
             Text(t) => self.format_text(t, writer),
+            EntityReference(r) => self.format_entity_reference(r, writer),
             Comment(c) => self.format_comment(c, writer),
             ProcessingInstruction(p) => self.format_processing_instruction(p, writer),
         }
@@ -596,19 +841,49 @@ impl Writer {
     where
         W: Write,
     {
-        self.format_declaration(writer)?;
-
-        for child in doc.root().children().into_iter() {
-            match child {
-                ChildOfRoot::Element(e) => self.format_body(e, writer),
-                ChildOfRoot::Comment(c) => self.format_comment(c, writer),
-                ChildOfRoot::ProcessingInstruction(p) => {
-                    self.format_processing_instruction(p, writer)
+        let root = doc.root();
+
+        let lexical = if self.preserve_lexical_form {
+            root.lexical()
+        } else {
+            None
+        };
+
+        match lexical {
+            Some(lexical) => {
+                // The prolog contains the declaration
+                writer.write_str(lexical.prolog)?;
+
+                for child in root.children() {
+                    if let Some(&(_, text)) = lexical.leading.iter().find(|l| l.0 == child) {
+                        writer.write_str(text)?;
+                    }
+                    self.format_root_child(child, writer)?;
                 }
-            }?
+
+                writer.write_str(lexical.trailing)
+            }
+            None => {
+                self.format_declaration(writer)?;
+
+                for child in root.children() {
+                    self.format_root_child(child, writer)?;
+                }
+
+                Ok(())
+            }
         }
+    }
 
-        Ok(())
+    fn format_root_child<W>(&self, child: ChildOfRoot<'_>, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + Write,
+    {
+        match child {
+            ChildOfRoot::Element(e) => self.format_body(e, writer),
+            ChildOfRoot::Comment(c) => self.format_comment(c, writer),
+            ChildOfRoot::ProcessingInstruction(p) => self.format_processing_instruction(p, writer),
+        }
     }
 }
 
@@ -623,7 +898,7 @@ where
 #[cfg(test)]
 mod test {
     use super::{
-        super::{dom, Package},
+        super::{dom, parser::Parser, Package},
         Writer,
     };
 
@@ -728,6 +1003,18 @@ mod test {
         );
     }
 
+    #[test]
+    fn attribute_in_xml_namespace_uses_undeclared_xml_prefix() {
+        let p = Package::new();
+        let d = p.as_document();
+        let e = d.create_element("hello");
+        e.set_attribute_value((crate::XML_NS_URI, "lang"), "en");
+        d.root().append_child(e);
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<?xml version='1.0'?><hello xml:lang='en'/>");
+    }
+
     #[test]
     fn attribute_with_preferred_namespace_prefix() {
         let p = Package::new();
@@ -922,6 +1209,19 @@ mod test {
         );
     }
 
+    #[test]
+    fn nested_entity_reference() {
+        let p = Package::new();
+        let d = p.as_document();
+        let hello = d.create_element("hello");
+        let r = d.create_entity_reference("company", Some("Acme"));
+        hello.append_child(r);
+        d.root().append_child(hello);
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<?xml version='1.0'?><hello>&company;</hello>");
+    }
+
     #[test]
     fn nested_comment() {
         let p = Package::new();
@@ -1015,4 +1315,146 @@ mod test {
         );
         assert_eq!(xml, r#"<?xml version="1.0" encoding="UTF-8"?><hello/>"#);
     }
+
+    fn parse_lexical(xml: &str) -> Package {
+        Parser::new()
+            .set_preserve_lexical_form(true)
+            .parse(xml)
+            .expect("Not parsed")
+    }
+
+    const LEXICAL_XML: &str = r#"<?xml version="1.0" encoding='UTF-8'?>
+<!DOCTYPE config SYSTEM "config.dtd">
+<!-- settings -->
+<config xmlns:b="urn:b"  mode = "fast" xmlns="urn:a" b:level='3' >
+  <value>&#160;&amp;<![CDATA[<raw>]]></value>
+  <b:empty></b:empty>
+  <empty   />
+  <?tool  run ?>
+</config >
+"#;
+
+    #[test]
+    fn lexical_form_is_reproduced() {
+        let p = parse_lexical(LEXICAL_XML);
+        let d = p.as_document();
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, LEXICAL_XML);
+    }
+
+    #[test]
+    fn lexical_form_with_unexpanded_entities_is_reproduced() {
+        let xml =
+            "<?xml version='1.0'?>\n<!DOCTYPE a [<!ENTITY e 'x'>]>\n<a  b='1'>t&e;&#65;<c /></a>\n";
+        let p = Parser::new()
+            .set_preserve_lexical_form(true)
+            .set_expand_entities(false)
+            .parse(xml)
+            .expect("Not parsed");
+
+        assert_eq!(format_xml(&p.as_document()), xml);
+    }
+
+    #[test]
+    fn lexical_form_is_not_reproduced_when_disabled() {
+        let p = parse_lexical("<a  b=\"c\"></a>");
+        let d = p.as_document();
+
+        let xml = format_xml_writer(Writer::new().set_preserve_lexical_form(false), &d);
+        assert_eq!(xml, "<?xml version='1.0'?><a b='c'/>");
+    }
+
+    #[test]
+    fn lexical_form_of_changed_attribute_keeps_quote() {
+        let p = parse_lexical(LEXICAL_XML);
+        let d = p.as_document();
+        let config = d.root().children()[1].element().unwrap();
+        config.set_attribute_value("mode", "\"safe\"");
+
+        let xml = format_xml(&d);
+        assert_eq!(
+            xml,
+            LEXICAL_XML.replace(r#"mode = "fast""#, r#"mode = "&quot;safe&quot;""#)
+        );
+    }
+
+    #[test]
+    fn lexical_form_of_removed_attribute_is_dropped() {
+        let p = parse_lexical("<a  b='1' c='2' />");
+        let d = p.as_document();
+        let a = d.root().children()[0].element().unwrap();
+        a.remove_attribute("b");
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<a c='2' />");
+    }
+
+    #[test]
+    fn lexical_form_with_added_attribute() {
+        let p = parse_lexical("<a  b=\"1\" />");
+        let d = p.as_document();
+        let a = d.root().children()[0].element().unwrap();
+        a.set_attribute_value(("urn:x", "c"), "2");
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<a  b=\"1\" autons0:c='2' xmlns:autons0='urn:x' />");
+    }
+
+    #[test]
+    fn lexical_form_of_self_closing_element_with_new_child() {
+        let p = parse_lexical("<a><b:c xmlns:b='urn:b' /></a>");
+        let d = p.as_document();
+        let a = d.root().children()[0].element().unwrap();
+        let c = a.children()[0].element().unwrap();
+        c.append_child(d.create_text("hi"));
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<a><b:c xmlns:b='urn:b' >hi</b:c></a>");
+    }
+
+    #[test]
+    fn lexical_form_of_modified_nodes_is_not_used() {
+        let p = parse_lexical("<a  x='1'>&#65;<!--c--><?p  v?></a >");
+        let d = p.as_document();
+        let a = d.root().children()[0].element().unwrap();
+        let children = a.children();
+        children[0].text().unwrap().set_text("<");
+        children[1].comment().unwrap().set_text("d");
+        children[2]
+            .processing_instruction()
+            .unwrap()
+            .set_value(Some("w"));
+        a.set_name("b");
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<b x='1'>&lt;<!--d--><?p w?></b>");
+    }
+
+    #[test]
+    fn lexical_form_with_undeclared_prefix_is_not_used() {
+        let p = parse_lexical("<a xmlns:p='urn:1'><p:b  /></a>");
+        let d = p.as_document();
+        let a = d.root().children()[0].element().unwrap();
+        let b = a.children()[0].element().unwrap();
+        a.set_name("c");
+        b.set_attribute_value("x", "1");
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<c><p:b x='1' xmlns:p='urn:1'/></c>");
+    }
+
+    #[test]
+    fn lexical_form_with_new_root_child() {
+        let p = parse_lexical("<?xml version='1.0'?>\n<a/>\n");
+        let d = p.as_document();
+        let a = d.root().children()[0].element().unwrap();
+        let comment = d.create_comment("first");
+        d.root().clear_children();
+        d.root().append_child(comment);
+        d.root().append_child(a);
+
+        let xml = format_xml(&d);
+        assert_eq!(xml, "<?xml version='1.0'?>\n<!--first--><a/>\n");
+    }
 }
@@ -0,0 +1,820 @@
+//! Processes [XInclude 1.0](https://www.w3.org/TR/xinclude/)
+//! elements, replacing each `xi:include` with the content it refers
+//! to.
+//!
+//! Resources are loaded through a [`Resolver`](trait.Resolver.html),
+//! which is given the absolute URI of each resource. Relative `href`
+//! values are resolved against the base URI of the include element,
+//! so the document URI should be set before processing.
+//!
+//! ### Example
+//! ```
+//! use sxd_document::{parser, xinclude};
+//!
+//! let package = parser::parse(r#"<book xmlns:xi="http://www.w3.org/2001/XInclude">
+//!   <xi:include href="chapter1.xml"/>
+//! </book>"#).expect("Failed to parse");
+//! package.set_document_uri(Some("file:///books/book.xml"));
+//!
+//! let mut resolver = |uri: &str| match uri {
+//!     "file:///books/chapter1.xml" => Ok("<chapter>Hello</chapter>".to_owned()),
+//!     _ => Err(std::io::Error::new(std::io::ErrorKind::NotFound, uri)),
+//! };
+//!
+//! let doc = package.as_document();
+//! xinclude::process(doc, &mut resolver).expect("Failed to include");
+//! ```
+//!
+//! ### XPointers
+//!
+//! The `xpointer` attribute supports the `element()` scheme and
+//! shorthand pointers. Since DTDs are not processed, a shorthand
+//! pointer matches an `xml:id` attribute or an `id` attribute without
+//! a namespace. Pointer parts using other schemes are skipped.
+
+use std::{error, fmt, fs, io};
+
+use crate::{
+    dom::{ChildOfElement, ChildOfRoot, Document, Element, ParentOfChild},
+    parser, uri, QName,
+};
+
+pub const XINCLUDE_NS_URI: &str = "http://www.w3.org/2001/XInclude";
+
+/// Loads the resources referred to by include elements
+pub trait Resolver {
+    /// Returns the contents of the resource at the absolute URI
+    fn load(&mut self, uri: &str) -> io::Result<String>;
+}
+
+impl<F> Resolver for F
+where
+    F: FnMut(&str) -> io::Result<String>,
+{
+    fn load(&mut self, uri: &str) -> io::Result<String> {
+        self(uri)
+    }
+}
+
+/// Loads resources from the local file system. URIs may be `file:`
+/// URIs, whose path is percent-decoded and whose query is ignored, or
+/// plain paths; other schemes are rejected.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FileResolver;
+
+impl Resolver for FileResolver {
+    fn load(&mut self, uri: &str) -> io::Result<String> {
+        let path = ["file://localhost", "file://", "file:"]
+            .iter()
+            .find(|&prefix| uri.starts_with(prefix))
+            .map(|prefix| &uri[prefix.len()..]);
+
+        let path = match (path, uri::scheme(uri)) {
+            (Some(path), _) => {
+                let path = path.find(&['?', '#'][..]).map_or(path, |i| &path[..i]);
+                uri::percent_decode(path).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid percent-encoding in URI: {}", uri),
+                    )
+                })?
+            }
+            // A single letter is more likely a Windows drive
+            (None, Some(scheme)) if scheme.len() > 1 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unsupported URI scheme: {}", uri),
+                ));
+            }
+            (None, _) => uri.to_owned(),
+        };
+
+        fs::read_to_string(path)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// An include element has neither an `href` nor an `xpointer`
+    /// attribute, or a text include has no `href`
+    MissingHref,
+    /// The `href` attribute contains a fragment identifier
+    FragmentInHref(String),
+    /// The `parse` attribute is neither `xml` nor `text`
+    UnknownParse(String),
+    /// A text include has an `xpointer` attribute
+    XPointerWithTextParse,
+    /// An include element has more than one `xi:fallback` child
+    MultipleFallbacks,
+    /// An `xi:fallback` element is not the child of an include
+    /// element
+    FallbackOutsideInclude,
+    /// The resource could not be loaded
+    Resource { uri: String, error: io::Error },
+    /// The XPointer did not locate an element in the resource
+    UnresolvedXPointer { uri: String, xpointer: String },
+    /// The included resource is not well-formed
+    Parse { uri: String, error: parser::Error },
+    /// The resource includes itself, directly or indirectly
+    Loop(String),
+    /// An include element that is the document element was replaced
+    /// by something other than a single element
+    InvalidDocumentElement,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Error::*;
+
+        match *self {
+            MissingHref => write!(f, "Include element has no href"),
+            FragmentInHref(ref href) => write!(f, "Include href has a fragment: {}", href),
+            UnknownParse(ref parse) => write!(f, "Unknown parse attribute value: {}", parse),
+            XPointerWithTextParse => write!(f, "Text includes may not have an xpointer"),
+            MultipleFallbacks => write!(f, "Include element has more than one fallback"),
+            FallbackOutsideInclude => write!(f, "Fallback element outside of an include"),
+            Resource { ref uri, ref error } => write!(f, "Unable to load {}: {}", uri, error),
+            UnresolvedXPointer {
+                ref uri,
+                ref xpointer,
+            } => write!(f, "XPointer {} did not match in {}", xpointer, uri),
+            Parse { ref uri, ref error } => write!(f, "Unable to parse {}: {}", uri, error),
+            Loop(ref uri) => write!(f, "Inclusion loop at {}", uri),
+            InvalidDocumentElement => write!(f, "Document element must include one element"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "Unable to process XInclude"
+    }
+}
+
+/// Replaces every include element in the document with the content
+/// it refers to.
+pub fn process<R>(document: Document<'_>, resolver: &mut R) -> Result<(), Error>
+where
+    R: Resolver,
+{
+    let mut processor = Processor {
+        resolver,
+        stack: document
+            .document_uri()
+            .map(String::from)
+            .into_iter()
+            .collect(),
+    };
+    processor.process_document(document)
+}
+
+struct Processor<'r, R> {
+    resolver: &'r mut R,
+    // The resources currently being included, to detect loops
+    stack: Vec<String>,
+}
+
+impl<'r, R> Processor<'r, R>
+where
+    R: Resolver,
+{
+    fn process_document(&mut self, document: Document<'_>) -> Result<(), Error> {
+        for child in document.root().children() {
+            if let ChildOfRoot::Element(element) = child {
+                self.process_element(element)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_element(&mut self, element: Element<'_>) -> Result<(), Error> {
+        if is_xinclude(element, "include") {
+            self.include(element)
+        } else if is_xinclude(element, "fallback") {
+            Err(Error::FallbackOutsideInclude)
+        } else {
+            self.process_children(element)
+        }
+    }
+
+    fn process_children(&mut self, element: Element<'_>) -> Result<(), Error> {
+        for child in element.children() {
+            if let ChildOfElement::Element(child) = child {
+                self.process_element(child)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, include: Element<'_>) -> Result<(), Error> {
+        let fallback = fallback_of(include)?;
+
+        // Resource errors may be recovered from by a fallback
+        let content = match (self.load(include), fallback) {
+            (Ok(content), _) => content,
+            (Err(Error::Resource { .. }), Some(fallback))
+            | (Err(Error::UnresolvedXPointer { .. }), Some(fallback)) => {
+                self.process_children(fallback)?;
+                fallback.children()
+            }
+            (Err(e), _) => return Err(e),
+        };
+
+        replace(include, content)
+    }
+
+    fn load<'d>(&mut self, include: Element<'d>) -> Result<Vec<ChildOfElement<'d>>, Error> {
+        let document = include.document();
+        let href = include.attribute_value("href").unwrap_or("");
+        let xpointer = include.attribute_value("xpointer");
+        let parse = include.attribute_value("parse").unwrap_or("xml");
+
+        if href.contains('#') {
+            return Err(Error::FragmentInHref(href.into()));
+        }
+
+        let is_text = match parse {
+            "xml" => false,
+            "text" => true,
+            _ => return Err(Error::UnknownParse(parse.into())),
+        };
+
+        if is_text && xpointer.is_some() {
+            return Err(Error::XPointerWithTextParse);
+        }
+
+        if href.is_empty() {
+            return match xpointer {
+                Some(xpointer) if !is_text => self.load_same_document(include, xpointer),
+                _ => Err(Error::MissingHref),
+            };
+        }
+
+        let uri = include.resolve_uri(href);
+        if !is_text && self.stack.contains(&uri) {
+            return Err(Error::Loop(uri));
+        }
+
+        let text = self.resolver.load(&uri).map_err(|error| Error::Resource {
+            uri: uri.clone(),
+            error,
+        })?;
+
+        if is_text {
+            return Ok(vec![document.create_text(&text).into()]);
+        }
+
+        let package = parser::parse(&text).map_err(|error| Error::Parse {
+            uri: uri.clone(),
+            error,
+        })?;
+        let source = package.as_document();
+        source.set_document_uri(Some(&uri));
+
+        self.stack.push(uri.clone());
+        let processed = self.process_document(source);
+        self.stack.pop();
+        processed?;
+
+        let nodes: Vec<ChildOfElement<'_>> = match xpointer {
+            Some(xpointer) => {
+                let element =
+                    locate(source, xpointer).ok_or_else(|| Error::UnresolvedXPointer {
+                        uri: uri.clone(),
+                        xpointer: xpointer.into(),
+                    })?;
+                vec![element.into()]
+            }
+            None => source
+                .root()
+                .children()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        };
+
+        let parent_base = match include.parent() {
+            Some(ParentOfChild::Element(parent)) => parent.base_uri(),
+            _ => document.document_uri().map(String::from),
+        };
+
+        Ok(nodes
+            .into_iter()
+            .map(|node| {
+                let copy = document.import_node(node, true);
+                if let (ChildOfElement::Element(original), ChildOfElement::Element(copy)) =
+                    (node, copy)
+                {
+                    fix_base(original, copy, parent_base.as_ref());
+                }
+                copy
+            })
+            .collect())
+    }
+
+    fn load_same_document<'d>(
+        &mut self,
+        include: Element<'d>,
+        xpointer: &str,
+    ) -> Result<Vec<ChildOfElement<'d>>, Error> {
+        let document = include.document();
+        let uri = document.document_uri().unwrap_or("");
+        let key = format!("{}#{}", uri, xpointer);
+
+        if self.stack.contains(&key) {
+            return Err(Error::Loop(key));
+        }
+
+        let target = locate(document, xpointer).ok_or_else(|| Error::UnresolvedXPointer {
+            uri: uri.into(),
+            xpointer: xpointer.into(),
+        })?;
+
+        if is_ancestor_or_self(target, include) {
+            return Err(Error::Loop(key));
+        }
+
+        let copy = document.import_element(target, true);
+
+        self.stack.push(key);
+        let processed = self.process_children(copy);
+        self.stack.pop();
+        processed?;
+
+        Ok(vec![copy.into()])
+    }
+}
+
+fn is_xinclude(element: Element<'_>, local_part: &str) -> bool {
+    element.name() == QName::with_namespace_uri(Some(XINCLUDE_NS_URI), local_part)
+}
+
+fn fallback_of(include: Element<'_>) -> Result<Option<Element<'_>>, Error> {
+    let mut fallbacks = include
+        .children()
+        .into_iter()
+        .filter_map(ChildOfElement::element)
+        .filter(|&e| is_xinclude(e, "fallback"));
+
+    let fallback = fallbacks.next();
+    if fallbacks.next().is_some() {
+        return Err(Error::MultipleFallbacks);
+    }
+    Ok(fallback)
+}
+
+fn is_ancestor_or_self(ancestor: Element<'_>, element: Element<'_>) -> bool {
+    let mut element = Some(element);
+    while let Some(e) = element {
+        if e == ancestor {
+            return true;
+        }
+        element = e.parent().and_then(ParentOfChild::element);
+    }
+    false
+}
+
+/// Puts the content in the place of the include element
+fn replace<'d>(include: Element<'d>, content: Vec<ChildOfElement<'d>>) -> Result<(), Error> {
+    match include.parent() {
+        Some(ParentOfChild::Element(parent)) => {
+            let children = parent.children().into_iter().flat_map(|child| {
+                if child == ChildOfElement::Element(include) {
+                    content.clone()
+                } else {
+                    vec![child]
+                }
+            });
+            parent.replace_children(children.collect::<Vec<_>>());
+        }
+        Some(ParentOfChild::Root(root)) => {
+            let mut replacement = Vec::new();
+            for node in content {
+                match node {
+                    ChildOfElement::Element(n) => replacement.push(ChildOfRoot::from(n)),
+                    ChildOfElement::Comment(n) => replacement.push(n.into()),
+                    ChildOfElement::ProcessingInstruction(n) => replacement.push(n.into()),
+                    ChildOfElement::Text(n) if n.text().trim().is_empty() => {}
+                    ChildOfElement::Text(_) | ChildOfElement::EntityReference(_) => {
+                        return Err(Error::InvalidDocumentElement)
+                    }
+                }
+            }
+
+            let elements = replacement.iter().filter(|n| n.element().is_some()).count();
+            if elements != 1 {
+                return Err(Error::InvalidDocumentElement);
+            }
+
+            let children = root.children().into_iter().flat_map(|child| {
+                if child == ChildOfRoot::Element(include) {
+                    replacement.clone()
+                } else {
+                    vec![child]
+                }
+            });
+            root.replace_children(children.collect::<Vec<_>>());
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// Adds an `xml:base` attribute to an included element when its base
+/// URI differs from the base URI of its new parent
+fn fix_base(original: Element<'_>, copy: Element<'_>, parent_base: Option<&String>) {
+    if let Some(base) = original.base_uri() {
+        if Some(&base) != parent_base {
+            copy.set_attribute_value((crate::XML_NS_URI, "base"), &base);
+        }
+    }
+}
+
+/// Finds the element identified by an XPointer, trying each pointer
+/// part in turn
+fn locate<'d>(document: Document<'d>, xpointer: &str) -> Option<Element<'d>> {
+    let xpointer = xpointer.trim();
+
+    if !xpointer.contains('(') {
+        return document_element(document).and_then(|e| find_by_id(e, xpointer));
+    }
+
+    pointer_parts(xpointer)?
+        .into_iter()
+        .filter(|(scheme, _)| scheme == "element")
+        .filter_map(|(_, data)| locate_element_scheme(document, &data))
+        .next()
+}
+
+/// Splits a pointer into its `scheme(data)` parts, unescaping the
+/// data. Returns `None` when the pointer is malformed.
+fn pointer_parts(xpointer: &str) -> Option<Vec<(String, String)>> {
+    let mut parts = Vec::new();
+    let mut chars = xpointer.chars().peekable();
+
+    loop {
+        while let Some(&c) = chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            return Some(parts);
+        }
+
+        let mut scheme = String::new();
+        loop {
+            match chars.next()? {
+                '(' => break,
+                c => scheme.push(c),
+            }
+        }
+
+        let mut data = String::new();
+        let mut depth = 0;
+        loop {
+            match chars.next()? {
+                '^' => data.push(chars.next()?),
+                '(' => {
+                    depth += 1;
+                    data.push('(');
+                }
+                ')' if depth == 0 => break,
+                ')' => {
+                    depth -= 1;
+                    data.push(')');
+                }
+                c => data.push(c),
+            }
+        }
+
+        parts.push((scheme.trim().to_owned(), data));
+    }
+}
+
+/// Evaluates the data of the `element()` scheme: an optional id
+/// followed by a child sequence such as `/1/3`
+fn locate_element_scheme<'d>(document: Document<'d>, data: &str) -> Option<Element<'d>> {
+    let mut steps = data.split('/');
+    let id = steps.next()?;
+
+    let mut element = if id.is_empty() {
+        match steps.next()? {
+            "1" => document_element(document)?,
+            _ => return None,
+        }
+    } else {
+        find_by_id(document_element(document)?, id)?
+    };
+
+    for step in steps {
+        let index: usize = step.parse().ok()?;
+        element = element
+            .children()
+            .into_iter()
+            .filter_map(ChildOfElement::element)
+            .nth(index.checked_sub(1)?)?;
+    }
+
+    Some(element)
+}
+
+fn document_element(document: Document<'_>) -> Option<Element<'_>> {
+    document
+        .root()
+        .children()
+        .into_iter()
+        .filter_map(ChildOfRoot::element)
+        .next()
+}
+
+fn find_by_id<'d>(element: Element<'d>, id: &str) -> Option<Element<'d>> {
+    let matches = element.attribute_value((crate::XML_NS_URI, "id")) == Some(id)
+        || element.attribute_value("id") == Some(id);
+    if matches {
+        return Some(element);
+    }
+
+    element
+        .children()
+        .into_iter()
+        .filter_map(ChildOfElement::element)
+        .filter_map(|child| find_by_id(child, id))
+        .next()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, io};
+
+    use super::{process, Error, FileResolver, Resolver};
+    use crate::{parser, writer::format_document, Package};
+
+    const XI: &str = r#"xmlns:xi="http://www.w3.org/2001/XInclude""#;
+
+    struct Files(HashMap<&'static str, &'static str>);
+
+    impl Files {
+        fn new(files: &[(&'static str, &'static str)]) -> Files {
+            Files(files.iter().cloned().collect())
+        }
+    }
+
+    impl Resolver for Files {
+        fn load(&mut self, uri: &str) -> io::Result<String> {
+            self.0
+                .get(uri)
+                .map(|&s| s.to_owned())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, uri))
+        }
+    }
+
+    fn parse(xml: &str) -> Package {
+        let package = parser::parse(xml).expect("Failed to parse");
+        package.set_document_uri(Some("http://example.com/book.xml"));
+        package
+    }
+
+    fn include(xml: &str, files: &[(&'static str, &'static str)]) -> Result<String, Error> {
+        let package = parse(xml);
+        process(package.as_document(), &mut Files::new(files))?;
+
+        let mut output = Vec::new();
+        format_document(&package.as_document(), &mut output).expect("Failed to format");
+        Ok(String::from_utf8(output).expect("Not UTF-8"))
+    }
+
+    fn body(output: &str) -> &str {
+        &output["<?xml version='1.0'?>".len()..]
+    }
+
+    #[test]
+    fn replaces_include_with_document_element() {
+        let xml = format!(r#"<book {}><xi:include href="ch1.xml"/></book>"#, XI);
+        let output = include(
+            &xml,
+            &[(
+                "http://example.com/ch1.xml",
+                "<!--c--><chapter>One</chapter>",
+            )],
+        )
+        .unwrap();
+
+        assert_eq!(
+            body(&output),
+            "<book><!--c--><chapter xml:base='http://example.com/ch1.xml'>One</chapter></book>"
+        );
+    }
+
+    #[test]
+    fn includes_text() {
+        let xml = format!(
+            r#"<code {}><xi:include href="a.rs" parse="text"/></code>"#,
+            XI
+        );
+        let output = include(&xml, &[("http://example.com/a.rs", "if a < b {}")]).unwrap();
+
+        assert_eq!(body(&output), "<code>if a &lt; b {}</code>");
+    }
+
+    #[test]
+    fn uses_fallback_when_resource_is_missing() {
+        let xml = format!(
+            r#"<book {}><xi:include href="missing.xml"><xi:fallback><p>None</p></xi:fallback></xi:include></book>"#,
+            XI
+        );
+        let output = include(&xml, &[]).unwrap();
+
+        assert_eq!(body(&output), "<book><p>None</p></book>");
+    }
+
+    #[test]
+    fn missing_resource_without_fallback_is_an_error() {
+        let xml = format!(r#"<book {}><xi:include href="missing.xml"/></book>"#, XI);
+
+        match include(&xml, &[]) {
+            Err(Error::Resource { uri, .. }) => assert_eq!(uri, "http://example.com/missing.xml"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn selects_element_with_xpointer() {
+        let xml = format!(
+            r#"<book {}><xi:include href="ch.xml" xpointer="element(/1/2)"/><xi:include href="ch.xml" xpointer="intro"/></book>"#,
+            XI
+        );
+        let chapters = r#"<all><a xml:id="intro"/><b/></all>"#;
+        let output = include(&xml, &[("http://example.com/ch.xml", chapters)]).unwrap();
+
+        assert_eq!(
+            body(&output),
+            "<book><b xml:base='http://example.com/ch.xml'/><a xml:id='intro' xml:base='http://example.com/ch.xml'/></book>"
+        );
+    }
+
+    #[test]
+    fn element_scheme_accepts_an_id_and_child_sequence() {
+        let xml = format!(
+            r#"<book {}><xi:include href="ch.xml" xpointer="element(nope) element(s/2)"/></book>"#,
+            XI
+        );
+        let chapters = r#"<all><s id="s"><a/><b/></s></all>"#;
+        let output = include(&xml, &[("http://example.com/ch.xml", chapters)]).unwrap();
+
+        assert_eq!(
+            body(&output),
+            "<book><b xml:base='http://example.com/ch.xml'/></book>"
+        );
+    }
+
+    #[test]
+    fn includes_from_the_same_document() {
+        let xml = format!(
+            r#"<book {}><p id="x">Hi</p><xi:include xpointer="x"/></book>"#,
+            XI
+        );
+        let output = include(&xml, &[]).unwrap();
+
+        assert_eq!(
+            body(&output),
+            "<book><p id='x'>Hi</p><p id='x'>Hi</p></book>"
+        );
+    }
+
+    #[test]
+    fn processes_nested_includes_relative_to_the_included_document() {
+        let xml = format!(r#"<book {}><xi:include href="a/ch.xml"/></book>"#, XI);
+        let chapter =
+            r#"<ch xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="s.xml"/></ch>"#;
+        let output = include(
+            &xml,
+            &[
+                ("http://example.com/a/ch.xml", chapter),
+                ("http://example.com/a/s.xml", "<s/>"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            body(&output),
+            "<book><ch xml:base='http://example.com/a/ch.xml'><s xml:base='http://example.com/a/s.xml'/></ch></book>"
+        );
+    }
+
+    #[test]
+    fn detects_inclusion_loops() {
+        let xml = format!(r#"<book {}><xi:include href="a.xml"/></book>"#, XI);
+        let a =
+            r#"<a xmlns:xi="http://www.w3.org/2001/XInclude"><xi:include href="book.xml"/></a>"#;
+
+        match include(&xml, &[("http://example.com/a.xml", a)]) {
+            Err(Error::Loop(uri)) => assert_eq!(uri, "http://example.com/book.xml"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detects_same_document_loops() {
+        let xml = format!(
+            r#"<book {}><p id="x"><xi:include xpointer="x"/></p></book>"#,
+            XI
+        );
+
+        match include(&xml, &[]) {
+            Err(Error::Loop(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keeps_namespaces_of_included_content() {
+        let xml = format!(r#"<book {}><xi:include href="ch.xml"/></book>"#, XI);
+        let chapter = r#"<wrap xmlns:h="urn:h" xmlns="urn:d"><h:p><q/></h:p></wrap>"#;
+        let package = parse(&xml);
+        let doc = package.as_document();
+        let mut files = Files::new(&[("http://example.com/ch.xml", chapter)]);
+        process(doc, &mut files).unwrap();
+
+        let book = doc.root().children()[0].element().unwrap();
+        let wrap = book.children()[0].element().unwrap();
+        let p = wrap.children()[0].element().unwrap();
+        let q = p.children()[0].element().unwrap();
+
+        assert_eq!(p.name().namespace_uri(), Some("urn:h"));
+        assert_eq!(p.preferred_prefix(), Some("h"));
+        assert_eq!(q.name().namespace_uri(), Some("urn:d"));
+        assert_eq!(q.recursive_default_namespace_uri(), Some("urn:d"));
+    }
+
+    #[test]
+    fn document_element_include_must_produce_one_element() {
+        let xml = format!(r#"<xi:include {} href="two.xml" parse="text"/>"#, XI);
+
+        match include(&xml, &[("http://example.com/two.xml", "text")]) {
+            Err(Error::InvalidDocumentElement) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn file_resolver_reads_file_uris() {
+        let path = std::env::temp_dir().join("sxd-document-xinclude-test.txt");
+        std::fs::write(&path, "contents").unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut resolver = FileResolver;
+        assert_eq!(resolver.load(path).unwrap(), "contents");
+        assert_eq!(
+            resolver.load(&format!("file://{}", path)).unwrap(),
+            "contents"
+        );
+        assert!(resolver.load("http://example.com/a.txt").is_err());
+    }
+
+    #[test]
+    fn file_resolver_decodes_file_uris() {
+        let dir = std::env::temp_dir().join("sxd-document-xinclude-\u{e9} dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("my chapter.txt"), "contents").unwrap();
+        let uri = format!(
+            "file://{}/my%20chapter.txt?version=2",
+            dir.to_str()
+                .unwrap()
+                .replace('\u{e9}', "%C3%A9")
+                .replace(' ', "%20")
+        );
+
+        let mut resolver = FileResolver;
+        assert_eq!(resolver.load(&uri).unwrap(), "contents");
+
+        let error = resolver.load("file:///docs/a%2.xml").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rejects_invalid_include_attributes() {
+        let cases = vec![
+            (r#"<xi:include/>"#, "MissingHref"),
+            (r#"<xi:include href="a.xml#b"/>"#, "FragmentInHref"),
+            (r#"<xi:include href="a.xml" parse="html"/>"#, "UnknownParse"),
+            (
+                r#"<xi:include href="a.xml" parse="text" xpointer="b"/>"#,
+                "XPointerWithTextParse",
+            ),
+            (r#"<xi:fallback/>"#, "FallbackOutsideInclude"),
+        ];
+
+        for (element, expected) in cases {
+            let xml = format!(r#"<book {}>{}</book>"#, XI, element);
+            let error = include(&xml, &[]).unwrap_err();
+            assert!(
+                format!("{:?}", error).starts_with(expected),
+                "{:?} for {}",
+                error,
+                element
+            );
+        }
+    }
+}